@@ -4,7 +4,13 @@
 //
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+use crate::internal::{
+    common::{Arch, Format},
+    pkgbuild::BuildOpts,
+};
+use anyhow::anyhow;
 use clap::{Parser, Subcommand};
+use clap_complete::Shell;
 use indoc::indoc;
 use std::path::PathBuf;
 
@@ -23,6 +29,38 @@ use std::path::PathBuf;
     "}
 )]
 pub struct Args {
+    #[arg(
+        long = "arch",
+        global = true,
+        help = "Architecture to be used instead of the architecture of the system"
+    )]
+    pub arch: Option<String>,
+    #[arg(
+        short = 'y',
+        long = "yes",
+        global = true,
+        help = "Answer all confirmation prompts with their default answer"
+    )]
+    pub yes: bool,
+    #[arg(
+        long = "json-errors",
+        global = true,
+        help = "Print errors as JSON to stderr"
+    )]
+    pub json_errors: bool,
+    #[arg(
+        long = "change-summary",
+        global = true,
+        help = "Write a sorted, machine-readable summary of the changed packages to a file (add, rm, update)"
+    )]
+    pub change_summary: Option<PathBuf>,
+    #[arg(
+        long = "max-parallel",
+        global = true,
+        value_name = "N",
+        help = "Maximum number of parallel AUR requests and clones (overrides max_parallel of /etc/repman.conf)"
+    )]
+    pub max_parallel: Option<usize>,
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -31,10 +69,12 @@ pub struct Args {
 pub enum Commands {
     #[command(
         name = "add",
+        alias = "install",
         about = "Build and add packages to a repository",
         long_about = indoc! {"
-            Build and add packages to a repository that can either be from the AUR or from
-            PKGBUILD files that are stored in the local file system. The packages can be
+            Build and add packages to a repository that can either be from the AUR, from
+            PKGBUILD files that are stored in the local file system or from git
+            repositories that contain a PKGBUILD file. The packages can be
             signed. For this, the environment variable GPGKEY must contain the id of the
             corresponding gpg key
         "}
@@ -44,8 +84,32 @@ pub enum Commands {
         repo_name: String,
         #[arg(short = 'a', long = "aur", action = clap::ArgAction::Append, help = "Name of AUR package")]
         aur_pkg_names: Vec<String>,
-        #[arg(short = 'd', long = "directory", action = clap::ArgAction::Append, help = "Local directory with PKGBUILD file")]
+        #[arg(
+            short = 'd',
+            long = "directory",
+            action = clap::ArgAction::Append,
+            help = "Local directory with PKGBUILD file or path to a build script file"
+        )]
         pkgbuild_dirs: Vec<PathBuf>,
+        #[arg(long = "git", action = clap::ArgAction::Append, help = "URL of git repository with PKGBUILD file (a revision can be appended as #<BRANCH> or ?ref=<REVISION>)")]
+        git_urls: Vec<String>,
+        #[arg(short = 'f', long = "file", visible_alias = "from-pkg", action = clap::ArgAction::Append, help = "Pre-built package file to be imported")]
+        pkg_files: Vec<PathBuf>,
+        #[arg(
+            long = "edit",
+            help = "Review/edit PKGBUILD files from AUR in $EDITOR before building"
+        )]
+        edit: bool,
+        #[arg(
+            long = "asdeps",
+            help = "Record the packages as dependencies instead of explicitly added packages"
+        )]
+        as_deps: bool,
+        #[arg(
+            long = "only-new",
+            help = "Skip AUR packages that are already contained in the repository"
+        )]
+        only_new: bool,
         #[arg(
             short = 'c',
             long = "clean",
@@ -53,17 +117,24 @@ pub enum Commands {
         )]
         clean_chroot: bool,
         #[arg(
-            short = 'A',
-            long = "ignorearch",
-            help = "Ignore field arch in PKGBUILD"
+            long = "format",
+            value_enum,
+            default_value_t = Format::Text,
+            help = "Output format of build durations"
+        )]
+        format: Format,
+        #[arg(
+            long = "require-checksums",
+            help = "Refuse to build packages whose sources have no or skipped checksums"
         )]
-        ignore_arch: bool,
+        require_checksums: bool,
         #[arg(
-            short = 'n',
-            long = "nochroot",
-            help = "Don't build packages in chroot environment"
+            long = "install-after",
+            help = "Install the built packages locally after they were added (pacman -U)"
         )]
-        no_chroot: bool,
+        install_after: bool,
+        #[command(flatten)]
+        build_args: BuildArgs,
         #[arg(short = 's', long = "sign", help = "Sign packages")]
         sign: bool,
     },
@@ -76,11 +147,18 @@ pub enum Commands {
            other, it is checked that all package files belong to package (versions) that
            are contained in the repository DB.
            It is also checked that all signature files fit to their counterpart files.
+           With --prune-untracked, files that are neither DB files, package files nor
+           signature files are listed and removed after confirmation.
         "}
     )]
     CleanUp {
         #[arg(short = 'r', long = "repo", help = "Repository")]
         repo_name: String,
+        #[arg(
+            long = "prune-untracked",
+            help = "Remove files that are neither DB, package nor signature files"
+        )]
+        prune_untracked: bool,
     },
 
     #[command(
@@ -99,6 +177,100 @@ pub enum Commands {
         clear_chroot: bool,
     },
 
+    #[command(
+        name = "completions",
+        hide = true,
+        about = "Generate shell completions",
+        long_about = indoc! {"
+            Generate a completion script for a shell and print it to stdout
+        "}
+    )]
+    Completions {
+        #[arg(value_enum, help = "Shell")]
+        shell: Shell,
+    },
+
+    #[command(
+        name = "config-path",
+        about = "Print the paths of the configuration files and directories",
+        long_about = indoc! {"
+            Print the paths of the configuration files and of the directories that repman
+            uses, as they are resolved for the current user. This helps to find out where
+            repman reads its configuration from
+        "}
+    )]
+    ConfigPath,
+
+    #[command(
+        name = "deps",
+        about = "Show the dependencies between the packages of a repository",
+        long_about = indoc! {"
+            Show the dependencies between the packages of a repository (i.e., the
+            intra-repository dependency graph). Dependencies to packages that are not
+            contained in the repository are not shown. With --dot, the graph is printed in
+            the DOT language of Graphviz. Otherwise, the dependencies are printed as list
+            of edges, either as text or as JSON
+        "}
+    )]
+    Deps {
+        #[arg(short = 'r', long = "repo", help = "Repository")]
+        repo_name: String,
+        #[arg(
+            long = "dot",
+            help = "Print dependency graph in the DOT language of Graphviz"
+        )]
+        dot: bool,
+        #[arg(
+            long = "format",
+            value_enum,
+            default_value_t = Format::Text,
+            help = "Output format of the list of dependencies"
+        )]
+        format: Format,
+    },
+
+    #[command(
+        name = "dump-pacman-conf",
+        about = "Print the pacman.conf file that is used for chroot builds",
+        long_about = indoc! {"
+            Generate the pacman.conf file that is used for builds in the chroot container of
+            a repository and print it to stdout. Nothing is built. This helps analyzing
+            problems with dependencies that are contained in the repository
+        "}
+    )]
+    DumpPacmanConf {
+        #[arg(short = 'r', long = "repo", help = "Repository")]
+        repo_name: String,
+    },
+
+    #[command(
+        name = "gc",
+        about = "Remove leftovers of builds for a repository",
+        long_about = indoc! {"
+            Remove copies of the chroot container that makechrootpkg created besides the
+            root container, and temporary directories of repman processes that are no
+            longer running. Other than clear --chroot, the root container is kept
+        "}
+    )]
+    Gc {
+        #[arg(short = 'r', long = "repo", help = "Repository")]
+        repo_name: String,
+    },
+
+    #[command(
+        name = "history",
+        about = "Show the history of changes of a repository",
+        long_about = indoc! {"
+            Show the changes of a repository that were done with repman: For each package
+            that was added, imported, updated or removed, the time of the change, the
+            operation, and the name and version of the package are displayed
+        "}
+    )]
+    History {
+        #[arg(short = 'r', long = "repo", help = "Repository")]
+        repo_name: String,
+    },
+
     #[command(
         name = "ls",
         about = "List packages of a repository",
@@ -111,16 +283,37 @@ pub enum Commands {
     Ls {
         #[arg(short = 'r', long = "repo", help = "Repository")]
         repo_name: String,
+        #[arg(
+            long = "format",
+            value_enum,
+            default_value_t = Format::Text,
+            help = "Output format of the list of packages"
+        )]
+        format: Format,
+        #[arg(
+            long = "depends-on",
+            help = "Only list the packages that depend on this package"
+        )]
+        depends_on: Option<String>,
     },
 
     #[command(
         name = "lsrepos",
         about = "List all repositories",
         long_about = indoc! {"
-            List all repositories that are defined in the configuration file
+            List all repositories that are defined in the configuration file. With
+            --detailed, the server URL, whether the repository is remote, whether its DB
+            exists locally and remotely, and the number of packages are displayed for
+            each repository
         "}
     )]
-    LsRepos,
+    LsRepos {
+        #[arg(
+            long = "detailed",
+            help = "Show server URL, DB status and number of packages per repository"
+        )]
+        detailed: bool,
+    },
 
     #[command(
         name = "mkchroot",
@@ -134,12 +327,117 @@ pub enum Commands {
         repo_name: String,
     },
 
+    #[command(
+        name = "mirror",
+        about = "Copy a repository to another location",
+        long_about = indoc! {"
+            Copy a repository to another (remote) location, e.g. to maintain a backup
+            mirror. The repository DB is not changed
+        "}
+    )]
+    Mirror {
+        #[arg(short = 'r', long = "repo", help = "Repository")]
+        repo_name: String,
+        #[arg(long = "to", help = "URL of the target location")]
+        url: String,
+    },
+
+    #[command(
+        name = "packagelist",
+        about = "List the package files that a PKGBUILD file produces",
+        long_about = indoc! {"
+            Prints the names of the package files that would be built from a PKGBUILD file
+            (makepkg --packagelist). This shows which packages a PKGBUILD file with split
+            packages produces before it is built. Nothing is built and no repository is
+            required.
+        "}
+    )]
+    Packagelist {
+        #[arg(
+            short = 'd',
+            long = "directory",
+            help = "Local directory with PKGBUILD file or path to a PKGBUILD file"
+        )]
+        pkgbuild_dir: PathBuf,
+    },
+
+    #[command(
+        name = "prune-sigs",
+        about = "Remove orphaned signature files from a repository",
+        long_about = indoc! {"
+            Remove signature files that do not have a corresponding package or DB file.
+            Contrary to clean-up, the repository DB and the package files are not
+            checked
+        "}
+    )]
+    PruneSigs {
+        #[arg(short = 'r', long = "repo", help = "Repository")]
+        repo_name: String,
+    },
+
+    #[command(
+        name = "purge",
+        about = "Remove all local data of a repository",
+        long_about = indoc! {"
+            Remove everything repman created locally for a repository: The chroot
+            container, the local copy/cache of the repository if it is remote, left over
+            temporary directories and the lock file
+        "}
+    )]
+    Purge {
+        #[arg(short = 'r', long = "repo", help = "Repository")]
+        repo_name: String,
+        #[arg(
+            long = "noconfirm",
+            help = "Don't ask for confirmation and remove data directly"
+        )]
+        no_confirm: bool,
+    },
+
+    #[command(
+        name = "push",
+        about = "Upload the local copy of a remote repository",
+        long_about = indoc! {"
+            Upload the local copy of a remote repository without downloading it before
+            and without changing it. This allows to recover from a failed upload after
+            the repository was changed locally already
+        "}
+    )]
+    Push {
+        #[arg(short = 'r', long = "repo", help = "Repository")]
+        repo_name: String,
+    },
+
+    #[command(
+        name = "refresh-keys",
+        about = "Update the PGP keys used to verify sources during builds",
+        long_about = indoc! {"
+            Import the trusted keys configured for a repository into the keyring of the
+            current user and refresh all keys of that keyring from a key server. This
+            prevents builds from failing because of missing or expired keys listed in
+            validpgpkeys of PKGBUILD files. With --chroot, the pacman keyring of the
+            chroot container of the repository is refreshed as well
+        "}
+    )]
+    RefreshKeys {
+        #[arg(short = 'r', long = "repo", help = "Repository")]
+        repo_name: String,
+        #[arg(
+            long = "chroot",
+            help = "Refresh pacman keyring of chroot container as well"
+        )]
+        chroot: bool,
+    },
+
     #[command(
         name = "rm",
         about = "Remove packages from a repository",
         long_about = indoc! {"
             Packages are removed from the repository DB, and all related package files are
             deleted. This includes all existing signature files.
+            With --older-than, the packages that were built before the given date are
+            removed. If package names are given as well, only these packages are
+            considered.
         "}
     )]
     Rm {
@@ -150,15 +448,66 @@ pub enum Commands {
             help = "Don't ask for confirmation and remove packages directly"
         )]
         no_confirm: bool,
+        #[arg(
+            long = "verify-before-remove",
+            help = "Check other repositories for packages that depend on the to-be-removed packages"
+        )]
+        verify_before_remove: bool,
+        #[arg(
+            long = "older-than",
+            value_name = "DATE",
+            help = "Only remove packages that were built before DATE (YYYY-MM-DD)"
+        )]
+        older_than: Option<String>,
         pkg_names: Vec<String>,
     },
 
+    #[command(
+        name = "resign",
+        visible_alias = "touch",
+        about = "Re-sign all packages and the DB of a repository",
+        long_about = indoc! {"
+            Removes the signatures of all packages and of the repository DB, and signs them
+            again. This is required after the signing key was rotated. The key to be used
+            can be set with --key. Otherwise, the key from the environment variable GPGKEY
+            or from the relevant makepkg.conf file is used. The repository DB is signed only
+            if that is required by the configuration.
+        "}
+    )]
+    Resign {
+        #[arg(short = 'r', long = "repo", help = "Repository")]
+        repo_name: String,
+        #[arg(long = "key", help = "PGP key to be used for signing")]
+        gpg_key: Option<String>,
+    },
+
+    #[command(
+        name = "resync",
+        about = "Download the local copy of a remote repository from scratch",
+        long_about = indoc! {"
+            Removes the local copy of a remote repository from the cache and downloads the
+            repository completely again. This repairs a local copy that was corrupted, e.g.
+            by an interrupted download. The remote repository is not changed
+        "}
+    )]
+    Resync {
+        #[arg(short = 'r', long = "repo", help = "Repository")]
+        repo_name: String,
+        #[arg(
+            long = "noconfirm",
+            help = "Don't ask for confirmation and re-synchronize directly"
+        )]
+        no_confirm: bool,
+    },
+
     #[command(
         name = "sign",
         about = "Sign packages of a repository",
         long_about = indoc! {"
             Signs either all or only specific packages of a repository. The repository DB is
             signed as well if that is required by the configuration.
+            With --db-only, only the repository DB is signed, and package files are not
+            touched.
         "}
     )]
     Sign {
@@ -166,14 +515,41 @@ pub enum Commands {
         repo_name: String,
         #[arg(long, help = "All packages")]
         all: bool,
+        #[arg(long = "db-only", help = "Only sign the repository DB")]
+        db_only: bool,
+        #[arg(
+            long = "noconfirm",
+            help = "Don't ask for confirmation of packages that match patterns"
+        )]
+        no_confirm: bool,
         pkg_names: Vec<String>,
     },
 
+    #[command(
+        name = "srcinfo",
+        about = "Print the .SRCINFO of an AUR package",
+        long_about = indoc! {"
+            Retrieves the package repository of an AUR package and prints the package meta
+            data (.SRCINFO) of its PKGBUILD file, e.g. to inspect its dependencies and
+            architectures before it is added to a repository. Nothing is built. The AUR
+            instance configured for the repository is used.
+        "}
+    )]
+    Srcinfo {
+        #[arg(short = 'r', long = "repo", help = "Repository")]
+        repo_name: String,
+        pkg_name: String,
+    },
+
     #[command(
         name = "update",
+        alias = "upgrade",
         about = "Update AUR packages of a repository",
         long_about = indoc! {"
-            Updates AUR packages of a repository. For packages that are tied to a specific
+            Updates AUR packages of a repository. Packages that were built from PKGBUILD
+            files in local directories can be updated by submitting these directories. They
+            are updated if the version of the PKGBUILD file is newer than the version in
+            the repository. For packages that are tied to a specific
             version, the update is done based on the version information (i.e., if a newer
             package version is available according to AUR, a package is updated). For
             packages that are not tied to a specific version, but that build from
@@ -184,11 +560,25 @@ pub enum Commands {
             An updated package will be signed if the package was already signed before.
             Therefore, the environment variable GPGKEY must contain the id of the
             corresponding gpg key.
+            With --all-repos, all configured repositories are updated one after the
+            other. An error in one repository does not abort the updates of the others.
         "}
     )]
     Update {
-        #[arg(short = 'r', long = "repo", help = "Repository")]
-        repo_name: String,
+        #[arg(
+            short = 'r',
+            long = "repo",
+            help = "Repository",
+            required_unless_present = "all_repos"
+        )]
+        repo_name: Option<String>,
+        #[arg(
+            long = "all-repos",
+            help = "Update all packages of all configured repositories (requires --all)",
+            conflicts_with_all = ["repo_name", "pkg_names", "pkgbuild_dirs"],
+            requires = "all"
+        )]
+        all_repos: bool,
         #[arg(long, help = "All packages", group = "all_pkgs")]
         all: bool,
         #[arg(
@@ -198,6 +588,11 @@ pub enum Commands {
             group = "all_pkgs"
         )]
         clean_chroot: bool,
+        #[arg(
+            long = "diff",
+            help = "Show changes of PKGBUILD files since the last build before building"
+        )]
+        diff: bool,
         #[arg(
             short = 'F',
             long = "force-no-version",
@@ -205,22 +600,269 @@ pub enum Commands {
         )]
         force_no_version: bool,
         #[arg(
-            short = 'A',
-            long = "ignorearch",
-            help = "Ignore field arch in PKGBUILD"
+            long = "force-all",
+            help = "Force update / re-add all packages irrespective of their versions",
+            conflicts_with = "force_no_version"
+        )]
+        force_all: bool,
+        #[arg(
+            long = "format",
+            value_enum,
+            default_value_t = Format::Text,
+            help = "Output format of build durations"
         )]
-        ignore_arch: bool,
+        format: Format,
+        #[command(flatten)]
+        build_args: BuildArgs,
         #[arg(
-            short = 'n',
-            long = "nochroot",
-            help = "Don't build packages in chroot environment"
+            long = "since-commit",
+            help = "Only rebuild packages with git sources if these have new commits since the last build"
         )]
-        no_chroot: bool,
+        since_commit: bool,
         #[arg(
             long = "noconfirm",
             help = "Don't ask for confirmation and update packages directly"
         )]
         no_confirm: bool,
+        #[arg(
+            short = 'd',
+            long = "directory",
+            action = clap::ArgAction::Append,
+            help = "Local directory with PKGBUILD file or path to a build script file"
+        )]
+        pkgbuild_dirs: Vec<PathBuf>,
         pkg_names: Vec<String>,
     },
+
+    #[command(
+        name = "validate-pkgbuild",
+        about = "Check a PKGBUILD file without building it",
+        long_about = indoc! {"
+            Checks a PKGBUILD file without building its packages: The package meta data
+            must be retrievable with makepkg --printsrcinfo, pkgname and pkgver must be
+            set, and arch must contain the current architecture or \"any\". Checksum
+            arrays must have as many entries as the corresponding source arrays. Missing
+            checksums only lead to a warning. No repository is required.
+        "}
+    )]
+    ValidatePkgbuild {
+        #[arg(
+            short = 'd',
+            long = "directory",
+            help = "Local directory with PKGBUILD file or path to a PKGBUILD file"
+        )]
+        pkgbuild_dir: PathBuf,
+    },
+
+    #[command(
+        name = "verify-build",
+        about = "Verify that the build of a package is reproducible",
+        long_about = indoc! {"
+            Rebuilds a package of a repository in a temporary directory and compares the
+            built package file with the package file in the repository. The differences of
+            the package meta data and of the contained files are reported. The repository
+            is not changed. The package is built from AUR, or from the PKGBUILD file in a
+            local directory if that is submitted.
+        "}
+    )]
+    VerifyBuild {
+        #[arg(short = 'r', long = "repo", help = "Repository")]
+        repo_name: String,
+        #[command(flatten)]
+        build_args: BuildArgs,
+        #[arg(
+            short = 'd',
+            long = "directory",
+            help = "Local directory with PKGBUILD file"
+        )]
+        pkgbuild_dir: Option<PathBuf>,
+        pkg_name: String,
+    },
+}
+
+/// Options that control how packages are built. They are shared by all
+/// commands that build packages
+#[derive(clap::Args)]
+pub struct BuildArgs {
+    #[arg(
+        short = 'A',
+        long = "ignorearch",
+        help = "Ignore field arch in PKGBUILD"
+    )]
+    pub ignore_arch: bool,
+    #[arg(
+        short = 'n',
+        long = "nochroot",
+        help = "Don't build packages in chroot environment"
+    )]
+    pub no_chroot: bool,
+    #[arg(
+        long = "namcap",
+        help = "Check PKGBUILD files and built packages with namcap"
+    )]
+    pub namcap: bool,
+    #[arg(
+        long = "namcap-strict",
+        help = "Check with namcap and let builds fail if namcap reports errors or warnings"
+    )]
+    pub namcap_strict: bool,
+    #[arg(
+        long = "skip-pgp-check",
+        help = "Don't verify PGP signatures of source files"
+    )]
+    pub skip_pgp_check: bool,
+    #[arg(
+        long = "compress-level",
+        help = "Compression level for package files (only with --nochroot)"
+    )]
+    pub compress_level: Option<u32>,
+    #[arg(
+        long = "compress-threads",
+        help = "Number of threads to compress package files (only with --nochroot)"
+    )]
+    pub compress_threads: Option<u32>,
+    #[arg(
+        long = "max-log-lines",
+        default_value_t = 50,
+        help = "Number of lines of the build output that are printed again if a build fails"
+    )]
+    pub max_log_lines: usize,
+    #[arg(long = "tmpfs", help = "Build packages in a tmpfs")]
+    pub tmpfs: bool,
+    #[arg(
+        long = "refresh-clones",
+        help = "Clone AUR package repositories from scratch instead of updating cached clones"
+    )]
+    pub refresh_clones: bool,
+    #[arg(
+        long = "also-copy",
+        help = "Additional directory where copies of the built package files are placed"
+    )]
+    pub also_copy_dir: Option<PathBuf>,
+    #[arg(
+        long = "exclude-arch",
+        action = clap::ArgAction::Append,
+        help = "Skip PKGBUILD files that declare this architecture"
+    )]
+    pub exclude_archs: Vec<String>,
+    #[arg(
+        long = "skip-chroot-update",
+        help = "Build in the existing chroot container without updating it"
+    )]
+    pub skip_chroot_update: bool,
+    #[arg(
+        long = "tolerate-chroot-update-failure",
+        help = "Build in the existing chroot container if its update fails"
+    )]
+    pub tolerate_chroot_update_failure: bool,
+    #[arg(
+        long = "keep-chroot-on-failure",
+        help = "Keep the copy of the chroot container if a build fails"
+    )]
+    pub keep_chroot_on_failure: bool,
+    #[arg(
+        long = "hold-version",
+        help = "Don't update the sources of VCS packages (makepkg --holdver)"
+    )]
+    pub hold_version: bool,
+    #[arg(
+        long = "noextract",
+        help = "Don't extract source files but use the existing ones (makepkg --noextract)"
+    )]
+    pub no_extract: bool,
+    #[arg(
+        long = "noprepare",
+        help = "Don't run the prepare() function of PKGBUILD files (makepkg --noprepare)"
+    )]
+    pub no_prepare: bool,
+    #[arg(
+        long = "nobuild",
+        help = "Only prepare the sources, don't build packages (makepkg --nobuild)"
+    )]
+    pub no_build: bool,
+}
+
+impl BuildArgs {
+    /// Checks that the build options fit to each other and converts them into
+    /// the options that control the builds
+    pub fn build_opts(&self) -> anyhow::Result<BuildOpts> {
+        if self.no_chroot && (self.namcap || self.namcap_strict) {
+            return Err(anyhow!(
+                "namcap checks are only possible for builds in a chroot container. Thus, they cannot be combined with '-n/--nochroot'"
+            ));
+        }
+        if self.no_chroot && self.skip_chroot_update {
+            return Err(anyhow!(
+                "If '-n/--nochroot' is set, setting '--skip-chroot-update' does not make sense"
+            ));
+        }
+        if self.no_chroot && self.tolerate_chroot_update_failure {
+            return Err(anyhow!(
+                "If '-n/--nochroot' is set, setting '--tolerate-chroot-update-failure' does not make sense"
+            ));
+        }
+        if self.no_chroot && self.keep_chroot_on_failure {
+            return Err(anyhow!(
+                "If '-n/--nochroot' is set, setting '--keep-chroot-on-failure' does not make sense"
+            ));
+        }
+        if !self.no_chroot && self.no_extract {
+            return Err(anyhow!(
+                "'--noextract' is only possible for builds with '-n/--nochroot'"
+            ));
+        }
+        if self.tmpfs && (self.no_extract || self.no_build) {
+            return Err(anyhow!(
+                "'--noextract' and '--nobuild' are not possible for builds in a tmpfs, since it is removed after the build"
+            ));
+        }
+        if !self.no_chroot && (self.compress_level.is_some() || self.compress_threads.is_some()) {
+            return Err(anyhow!(
+                "'--compress-level' and '--compress-threads' are only possible for builds with '-n/--nochroot'"
+            ));
+        }
+        if let Some(also_copy_dir) = &self.also_copy_dir {
+            if !also_copy_dir.is_dir() {
+                return Err(anyhow!(
+                    "Directory '{}' of '--also-copy' does not exist",
+                    also_copy_dir.display()
+                ));
+            }
+        }
+
+        Ok(BuildOpts {
+            no_chroot: self.no_chroot,
+            ignore_arch: self.ignore_arch,
+            namcap: self.namcap || self.namcap_strict,
+            namcap_strict: self.namcap_strict,
+            skip_pgp_check: self.skip_pgp_check,
+            compress_level: self.compress_level,
+            compress_threads: self.compress_threads,
+            max_log_lines: self.max_log_lines,
+            tmpfs: self.tmpfs,
+            refresh_clones: self.refresh_clones,
+            also_copy_dir: self.also_copy_dir.clone(),
+            exclude_archs: parse_archs(&self.exclude_archs)?,
+            skip_chroot_update: self.skip_chroot_update,
+            tolerate_chroot_update_failure: self.tolerate_chroot_update_failure,
+            keep_chroot_on_failure: self.keep_chroot_on_failure,
+            hold_version: self.hold_version,
+            no_extract: self.no_extract,
+            no_prepare: self.no_prepare,
+            no_build: self.no_build,
+            ..Default::default()
+        })
+    }
+}
+
+/// Converts the architecture names `archs` into architectures. Unknown
+/// architectures lead to an error
+fn parse_archs(archs: &[String]) -> anyhow::Result<Vec<Arch>> {
+    archs
+        .iter()
+        .map(|arch| match Arch::from(arch) {
+            Arch::Unknown => Err(anyhow!("Architecture '{}' is not supported", arch)),
+            arch => Ok(arch),
+        })
+        .collect()
 }
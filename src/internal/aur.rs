@@ -2,28 +2,35 @@
 //
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use crate::internal::{cfg, common::*};
+use crate::internal::{cfg, common::*, git};
 use alpm::vercmp;
 use anyhow::{anyhow, Context};
 use arch_msgs::*;
-use const_format::concatcp;
-use duct::cmd;
+use chrono::{DateTime, Utc};
 use regex::Regex;
+use scopeguard::defer;
 use std::{
     cmp::Eq,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt::Display,
+    fs,
     hash::Hash,
     path::{Path, PathBuf},
-    str::from_utf8,
+    thread,
 };
 
-/// Names of optional dependencies
-const PKG_NAME_GIT: &str = "git";
+/// Path of the AUR web api (relative to the AUR base URL)
+const AUR_INFO_PATH: &str = "rpc/?v=5&type=info";
 
-/// AUR URI's
-const AUR_URI: &str = "https://aur.archlinux.org/";
-const AUR_INFO_URI: &str = concatcp!(AUR_URI, "rpc/?v=5&type=info");
+/// Maximum number of packages per request to the AUR web api. Larger requests
+/// could exceed the maximum length of URIs
+const AUR_INFO_BATCH_SIZE: usize = 100;
+
+/// Sub path of the cache directory where clones of AUR package repositories are
+/// kept
+const AUR_CLONES_SUB_PATH: &str = "aur";
+/// Suffix of the lock files of cached clones of AUR package repositories
+const CLONE_LOCK_FILE_SUFFIX: &str = ".lock";
 
 /// Structures to store the result of an AUR web api call
 #[derive(serde::Deserialize, Debug, Default)]
@@ -43,6 +50,14 @@ struct AurItem {
     version: String,
     #[serde(rename = "OutOfDate")]
     out_of_date: Option<u32>,
+    #[serde(rename = "Maintainer")]
+    maintainer: Option<String>,
+    #[serde(rename = "LastModified")]
+    last_modified: i64,
+    #[serde(rename = "NumVotes")]
+    num_votes: u32,
+    #[serde(rename = "Popularity")]
+    popularity: f64,
 }
 
 /// Mapping between package names and the corresponding packages bases. In case
@@ -56,6 +71,10 @@ pub type PkgName2Base = HashMap<String, String>;
 struct PkgInfo {
     pkg_base: String,
     version: String,
+    maintainer: Option<String>,
+    last_modified: Option<DateTime<Utc>>,
+    num_votes: u32,
+    popularity: f64,
 }
 type PkgInfos = HashMap<String, PkgInfo>;
 
@@ -65,6 +84,10 @@ pub struct PkgUpd<'a> {
     pub old_version: &'a str,
     pub new_version: &'a str,
     pub pkg_base: &'a str,
+    pub maintainer: Option<&'a str>,
+    pub last_modified: Option<DateTime<Utc>>,
+    pub num_votes: u32,
+    pub popularity: f64,
 }
 
 /// Types and variables to store data retrieve from the AUR web interface.
@@ -78,19 +101,28 @@ pub struct PkgUpd<'a> {
 ///     pkg_name2 -> pkg_base1
 ///   In this case pkg_infos would only contain an entry for pkg_base1
 pub struct AurData {
+    aur_url: String,
     pkg_name2base: PkgName2Base,
     pkg_infos: PkgInfos,
 }
 
 impl AurData {
-    /// Creates an instance of AurData and retrieves information from AUR about
-    /// the packages in pkg_names. If check_exists is true, error messages are
-    /// printed for packages that could not be found in AUR
-    pub fn new<S>(pkg_names: &[S], check_exists: bool) -> anyhow::Result<AurData>
+    /// Creates an instance of AurData and retrieves information from the AUR
+    /// instance at `aur_url` about the packages in pkg_names. If check_exists is
+    /// true, error messages are printed for packages that could not be found in
+    /// AUR. The information is requested in batches. At most
+    /// `cfg::max_parallel()` requests are executed in parallel
+    pub fn new<S, T>(aur_url: T, pkg_names: &[S], check_exists: bool) -> anyhow::Result<AurData>
     where
         S: AsRef<str> + Display + Eq + Hash,
+        T: AsRef<str>,
     {
         let mut aur_data = AurData {
+            aur_url: if aur_url.as_ref().ends_with('/') {
+                aur_url.as_ref().to_string()
+            } else {
+                format!("{}/", aur_url.as_ref())
+            },
             pkg_name2base: PkgName2Base::new(),
             pkg_infos: PkgInfos::new(),
         };
@@ -98,19 +130,17 @@ impl AurData {
         if !pkg_names.is_empty() {
             let err_msg = "Cannot retrieve package information from AUR".to_string();
 
-            // Assemble URI
-            let mut aur_uri: String = AUR_INFO_URI.to_string();
-            for pkg_name in pkg_names {
-                aur_uri = format!("{}&arg[]={}", aur_uri, pkg_name);
-            }
-
-            // Request package information from AUR
-            let response = reqwest::blocking::get(aur_uri).with_context(|| err_msg.clone())?;
-            if response.status() != reqwest::StatusCode::OK {
-                return Err(anyhow!("HTTP error from AUR: {}", response.status()).context(err_msg));
+            // Request package information from AUR in batches
+            let pkg_names_str: Vec<&str> = pkg_names.iter().map(AsRef::as_ref).collect();
+            let batches: Vec<&[&str]> = pkg_names_str.chunks(AUR_INFO_BATCH_SIZE).collect();
+            let mut items: Vec<AurItem> = vec![];
+            for result in parallel_map(&batches, |batch| {
+                request_pkg_infos(&aur_data.aur_url, batch)
+            }) {
+                items.extend(result.with_context(|| err_msg.clone())?);
             }
 
-            for item in &response.json::<AurHeader>().with_context(|| err_msg)?.items {
+            for item in &items {
                 aur_data
                     .pkg_name2base
                     .insert(item.name.clone(), item.pkg_base.clone());
@@ -121,6 +151,10 @@ impl AurData {
                         PkgInfo {
                             pkg_base: item.pkg_base.clone(),
                             version: item.version.clone(),
+                            maintainer: item.maintainer.clone(),
+                            last_modified: DateTime::from_timestamp(item.last_modified, 0),
+                            num_votes: item.num_votes,
+                            popularity: item.popularity,
                         },
                     );
 
@@ -128,6 +162,11 @@ impl AurData {
                     if item.out_of_date.is_some() {
                         warning!("AUR package '{}' is flagged as out-of-date", &item.name);
                     }
+
+                    // Warn in case package is orphaned
+                    if item.maintainer.is_none() {
+                        warning!("AUR package '{}' is orphaned", &item.name);
+                    }
                 }
             }
 
@@ -150,8 +189,15 @@ impl AurData {
     /// Clones package repositories to dir. If pkg_names is Some(...) only
     /// packages are cloned whose names are contained in Some(pkg_names).
     /// Otherwise, all package repositories are cloned where the package base is
-    /// part of self.pkg_infos
-    pub fn clone_pkg_repos<P, S>(&self, pkg_names: Option<&[S]>, dir: P) -> Vec<PathBuf>
+    /// part of self.pkg_infos. Clones of package repositories are cached. If
+    /// refresh is true, cached clones are replaced by fresh clones. At most
+    /// `cfg::max_parallel()` package repositories are cloned in parallel
+    pub fn clone_pkg_repos<P, S>(
+        &self,
+        pkg_names: Option<&[S]>,
+        dir: P,
+        refresh: bool,
+    ) -> Vec<PathBuf>
     where
         P: AsRef<Path>,
         S: AsRef<str> + Display + Eq + Hash,
@@ -170,9 +216,20 @@ impl AurData {
             None => self.pkg_infos.keys().map(AsRef::as_ref).collect(),
         };
 
+        // Since repositories are cloned in parallel, each repository must only
+        // be cloned once
+        let mut seen: HashSet<&str> = HashSet::new();
+        let to_be_cloned_pkg_names: Vec<&str> = to_be_cloned_pkg_names
+            .into_iter()
+            .filter(|pkg_name| seen.insert(pkg_name))
+            .collect();
+
+        let dir = dir.as_ref();
         let mut pkg_repo_dirs: Vec<PathBuf> = vec![];
-        for pkg_name in to_be_cloned_pkg_names {
-            match clone_pkg_repo(pkg_name, &dir) {
+        for result in parallel_map(&to_be_cloned_pkg_names, |pkg_name| {
+            clone_pkg_repo(&self.aur_url, pkg_name, dir, refresh)
+        }) {
+            match result {
                 Ok(dir) => {
                     pkg_repo_dirs.push(dir);
                 }
@@ -185,6 +242,14 @@ impl AurData {
         pkg_repo_dirs
     }
 
+    /// Returns the names of all packages with their package bases
+    pub fn pkg_name2base(&self) -> Vec<(&str, &str)> {
+        self.pkg_name2base
+            .iter()
+            .map(|(pkg_name, pkg_base)| (pkg_name.as_str(), pkg_base.as_str()))
+            .collect()
+    }
+
     /// Filter packages that are not tied to a specific version from all
     /// packages. These packages are identified by their suffix. If their
     /// name ends with one of the VCS suffixes maintained in the repman
@@ -226,11 +291,14 @@ impl AurData {
     /// - version currently available in AUR (which is of course greater than their
     ///   other version)
     /// - package base
+    /// - current maintainer in AUR (None if the package is orphaned)
+    /// - time of last modification in AUR
+    /// - number of votes and popularity in AUR
     ///
     /// Package base is required to be able to clone the package repository lateron
     pub fn pkg_updates<'a>(
         &'a self,
-        db_pkgs: &'static repodb_parser::Pkgs,
+        db_pkgs: &'a repodb_parser::Pkgs,
     ) -> anyhow::Result<Vec<PkgUpd<'a>>> {
         let mut pkg_upds: Vec<PkgUpd> = vec![];
 
@@ -251,6 +319,10 @@ impl AurData {
                     old_version: db_pkg.version.as_str(),
                     new_version: pkg_info.version.as_str(),
                     pkg_base: pkg_info.pkg_base.as_str(),
+                    maintainer: pkg_info.maintainer.as_deref(),
+                    last_modified: pkg_info.last_modified,
+                    num_votes: pkg_info.num_votes,
+                    popularity: pkg_info.popularity,
                 })
             }
         }
@@ -259,47 +331,133 @@ impl AurData {
     }
 }
 
-/// Clones the package repository for pkg_base from AUR to dir
-fn clone_pkg_repo<P, S>(pkg_base: S, dir: P) -> anyhow::Result<PathBuf>
+/// Clones the package repository for pkg_base from the AUR instance at aur_url
+/// to dir. To save network traffic, a clone of the package repository is kept
+/// in the cache directory. If such a clone exists, it is updated and dir is
+/// cloned from it. If refresh is true or if the update fails, the cached clone
+/// is replaced by a fresh clone from AUR
+fn clone_pkg_repo<P, S>(
+    aur_url: &str,
+    pkg_base: S,
+    dir: P,
+    refresh: bool,
+) -> anyhow::Result<PathBuf>
 where
     P: AsRef<Path>,
     S: AsRef<str> + Display,
 {
     let err_msg = format!("Cannot clone package '{}' from AUR", pkg_base);
 
-    // Package git must be installed to be able to clone packages from AUR
-    if !is_pkg_installed(PKG_NAME_GIT).with_context(|| err_msg.clone())? {
-        return Err(anyhow!(
-            "Cloning a package from AUR requires package {} being installed",
-            PKG_NAME_GIT
-        ))
-        .context(err_msg);
+    // Clones are cached per AUR instance since different repositories can use
+    // different instances
+    let cache_dir = ensure_dir(
+        cache_dir()
+            .with_context(|| err_msg.clone())?
+            .join(AUR_CLONES_SUB_PATH)
+            .join(clones_dir_name(aur_url)),
+    )
+    .with_context(|| err_msg.clone())?;
+    let cached_repo_dir = cache_dir.join(pkg_base.as_ref());
+
+    // Other processes can use the same cached clone. Thus, it is locked while
+    // it is updated and cloned
+    let lock_file = cache_dir.join(format!("{}{}", pkg_base, CLONE_LOCK_FILE_SUFFIX));
+    let locked = wait_for_lock(&lock_file, |pid| {
+        msg!(
+            "Cached repository of package {} is used by process {}. Waiting ...",
+            pkg_base,
+            pid
+        )
+    })
+    .with_context(|| err_msg.clone())?;
+    defer! {
+        if locked {
+            fs::remove_file(&lock_file).unwrap_or_else(|_| panic!("Cannot remove lock file '{}'", lock_file.display()));
+        }
     }
 
-    msg!("Cloning repository of package {} from AUR ...", pkg_base);
+    let mut is_cached = cached_repo_dir.exists() && !refresh;
+    if is_cached {
+        msg!("Updating repository of package {} from AUR ...", pkg_base);
+        if let Err(err) = git::pull(&cached_repo_dir) {
+            warning!("{:?}", err);
+            is_cached = false;
+        }
+    }
+    if !is_cached {
+        if cached_repo_dir.exists() {
+            fs::remove_dir_all(&cached_repo_dir).with_context(|| err_msg.clone())?;
+        }
+        msg!("Cloning repository of package {} from AUR ...", pkg_base);
+        git::clone(
+            format!("{}{}.git", aur_url, pkg_base),
+            &cached_repo_dir,
+            None,
+        )
+        .with_context(|| err_msg.clone())?;
+    }
 
     let pkg_repo_dir = dir.as_ref().join(pkg_base.as_ref());
 
-    let output = cmd!(
-        "git",
-        "clone",
-        format!("{}{}.git", AUR_URI, pkg_base),
+    git::clone(
+        cached_repo_dir.to_str().with_context(|| err_msg.clone())?,
         &pkg_repo_dir,
+        None,
     )
-    .stdout_null()
-    .stderr_capture()
-    .unchecked()
-    .run()
-    .with_context(|| err_msg.clone())?;
+    .with_context(|| err_msg)?;
 
-    if output.status.success() {
-        Ok(pkg_repo_dir)
-    } else {
-        Err(anyhow!(
-            "git clone: {}",
-            from_utf8(&output.stderr)
-                .unwrap_or_else(|_| panic!("Cannot retrieve stderr for 'git clone ...'"))
-        )
-        .context(err_msg))
+    Ok(pkg_repo_dir)
+}
+
+/// Derives the name of the directory where clones from the AUR instance at
+/// `aur_url` are cached. That's the URL without scheme, with "%" and "/" being
+/// percent-encoded. Thus, different AUR instances never share a directory
+fn clones_dir_name(aur_url: &str) -> String {
+    aur_url
+        .split_once("://")
+        .map_or(aur_url, |(_, url)| url)
+        .trim_end_matches('/')
+        .replace('%', "%25")
+        .replace('/', "%2F")
+}
+
+/// Applies `f` to all `items` and returns the results in the order of the
+/// items. At most `cfg::max_parallel()` items are processed in parallel
+fn parallel_map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let mut results: Vec<R> = Vec::with_capacity(items.len());
+    for chunk in items.chunks(cfg::max_parallel()) {
+        thread::scope(|scope| {
+            let handles: Vec<_> = chunk.iter().map(|item| scope.spawn(|| f(item))).collect();
+            for handle in handles {
+                results.push(
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| panic!("Cannot join parallel AUR task")),
+                );
+            }
+        });
+    }
+    results
+}
+
+/// Requests information about the packages `pkg_names` from the web api of the
+/// AUR instance at `aur_url` with one request
+fn request_pkg_infos(aur_url: &str, pkg_names: &[&str]) -> anyhow::Result<Vec<AurItem>> {
+    // Assemble URI
+    let mut aur_uri: String = format!("{}{}", aur_url, AUR_INFO_PATH);
+    for pkg_name in pkg_names {
+        aur_uri = format!("{}&arg[]={}", aur_uri, pkg_name);
+    }
+
+    let response = reqwest::blocking::get(aur_uri)?;
+    if response.status() != reqwest::StatusCode::OK {
+        return Err(anyhow!("HTTP error from AUR: {}", response.status()));
     }
+
+    Ok(response.json::<AurHeader>()?.items)
 }
@@ -4,9 +4,11 @@
 
 use crate::internal::common::*;
 use anyhow::{anyhow, Context};
-use serde::Deserialize;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Deserializer};
 use std::{
     fmt::Display,
+    path::{Path, PathBuf},
     {collections::BTreeMap, fs},
 };
 
@@ -19,10 +21,37 @@ const CFG_VAR_DB: &str = "$db";
 const CFG_REPOS_FILE: &str = "repos.conf";
 const CFG_FILE_PATH: &str = "/etc/repman.conf";
 
+/// Default base URL of the AUR
+const AUR_URL_DEFAULT: &str = "https://aur.archlinux.org/";
+
+/// Default maximum number of parallel AUR requests and clones
+const MAX_PARALLEL_DEFAULT: usize = 4;
+
+/// Maximum number of parallel AUR requests and clones that was set via the
+/// command line
+static MAX_PARALLEL: OnceCell<usize> = OnceCell::new();
+
 /// To store of configuration file
 #[derive(Debug, Deserialize)]
 pub struct Cfg {
     pub vcs_suffixes: Vec<String>,
+    #[serde(default = "aur_url_default")]
+    pub aur_url: String,
+    #[serde(default = "max_parallel_default")]
+    pub max_parallel: usize,
+}
+
+fn aur_url_default() -> String {
+    AUR_URL_DEFAULT.to_string()
+}
+
+fn max_parallel_default() -> usize {
+    MAX_PARALLEL_DEFAULT
+}
+
+/// Path of the global configuration file
+pub fn cfg_file() -> &'static Path {
+    Path::new(CFG_FILE_PATH)
 }
 
 /// Retrieves repman config from configuration file
@@ -33,15 +62,85 @@ pub fn cfg() -> anyhow::Result<Cfg> {
     .with_context(|| "Cannot parse configuration file")
 }
 
+/// Returns the maximum number of AUR requests and clones of AUR package
+/// repositories that are executed in parallel. A value set via
+/// `set_max_parallel()` takes precedence over the configuration file. If the
+/// configuration file cannot be read, the default is used
+pub fn max_parallel() -> usize {
+    MAX_PARALLEL
+        .get()
+        .copied()
+        .unwrap_or_else(|| {
+            cfg()
+                .map(|cfg| cfg.max_parallel)
+                .unwrap_or(MAX_PARALLEL_DEFAULT)
+        })
+        .max(1)
+}
+
+/// Sets the maximum number of parallel AUR requests and clones, overriding the
+/// configuration file. It can only be set once
+pub fn set_max_parallel(max_parallel: usize) {
+    MAX_PARALLEL.get_or_init(|| max_parallel);
+}
+
 // To store content for one repository from repositories configuration file
 #[derive(Clone, Debug, Deserialize)]
 pub struct CfgRepo {
     #[serde(alias = "DBName")]
     pub db_name: Option<String>,
-    #[serde(alias = "Server")]
-    pub server: String,
+    #[serde(alias = "Server", deserialize_with = "one_or_many")]
+    pub server: Vec<String>,
     #[serde(alias = "SignDB")]
     pub sign_db: bool,
+    #[serde(alias = "AurURL")]
+    pub aur_url: Option<String>,
+    #[serde(default, alias = "StorePKGBUILDs")]
+    pub store_pkgbuilds: bool,
+    #[serde(default, alias = "Retries")]
+    pub retries: u32,
+    #[serde(default, alias = "SigMode")]
+    pub sig_mode: SigMode,
+    #[serde(default, alias = "TrustedKeys")]
+    pub trusted_keys: Vec<String>,
+    #[serde(default, alias = "Tmpfs")]
+    pub tmpfs: bool,
+    #[serde(alias = "MakepkgConf")]
+    pub makepkg_conf: Option<PathBuf>,
+    #[serde(alias = "PacmanConf")]
+    pub pacman_conf: Option<PathBuf>,
+    #[serde(alias = "DBKey")]
+    pub db_key: Option<String>,
+    #[serde(default, alias = "ConfirmDeletions")]
+    pub confirm_deletions: bool,
+    #[serde(alias = "TransferConcurrency")]
+    pub transfer_concurrency: Option<u32>,
+    #[serde(alias = "ChrootSigLevel")]
+    pub chroot_sig_level: Option<String>,
+    #[serde(alias = "ChrootMirror")]
+    pub chroot_mirror: Option<String>,
+    #[serde(default, alias = "PkgKeys")]
+    pub pkg_keys: BTreeMap<String, String>,
+}
+
+/// Server URLs can either be maintained as single string or as array of strings.
+/// In the latter case, the first URL is the primary server, all other URLs are
+/// fallbacks
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(url) => vec![url],
+        OneOrMany::Many(urls) => urls,
+    })
 }
 
 // To store content from repositories configuration file
@@ -57,28 +156,136 @@ where
         .cloned()
 }
 
+/// Path of the repositories configuration file
+pub fn repos_file() -> anyhow::Result<PathBuf> {
+    Ok(config_dir()?.join(CFG_REPOS_FILE))
+}
+
 /// Retrieves repository configurations from the configuration file and returns
 /// them as B-tree map
 pub fn repos() -> anyhow::Result<CfgRepos> {
     let err_msg = "Cannot read repositories configuration file";
 
-    let mut repos: CfgRepos = toml::from_str(
-        &fs::read_to_string(config_dir().context(err_msg)?.join(CFG_REPOS_FILE))
-            .context(err_msg)?,
-    )
-    .context("Cannot parse configuration file")?;
+    let mut repos: CfgRepos =
+        toml::from_str(&fs::read_to_string(repos_file().context(err_msg)?).context(err_msg)?)
+            .context("Cannot parse configuration file")?;
 
     // Replace variables for architecture, repository name and
     // (if specified) DB name with their corresponding values
     for (name, repo) in repos.iter_mut() {
-        repo.server = repo
-            .server
-            .replace(CFG_VAR_ARCH, &arch()?.to_string())
-            .replace(CFG_VAR_REPO, name);
+        // Repository and DB names are used to assemble paths of directories and
+        // files (e.g., the lock file). Thus, they must be valid file names
+        if !is_valid_name(name) {
+            return Err(anyhow!(
+                "Repository name '{}' is invalid. Names must not be empty or start with '.', and must only contain letters, digits and the characters '-', '_', '.', '+' and '@'",
+                name
+            ));
+        }
         if let Some(db_name) = &repo.db_name {
-            repo.server = repo.server.replace(CFG_VAR_DB, db_name)
+            if !is_valid_name(db_name) {
+                return Err(anyhow!(
+                    "DB name '{}' of repository {} is invalid. Names must not be empty or start with '.', and must only contain letters, digits and the characters '-', '_', '.', '+' and '@'",
+                    db_name,
+                    name
+                ));
+            }
+        }
+        if repo.server.is_empty() {
+            return Err(anyhow!("No server is configured for repository {}", name));
+        }
+        if repo.transfer_concurrency == Some(0) {
+            return Err(anyhow!(
+                "TransferConcurrency of repository {} must be at least 1",
+                name
+            ));
+        }
+        if let Some(db_key) = &repo.db_key {
+            if !is_valid_key_id(db_key) {
+                return Err(anyhow!(
+                    "DBKey '{}' of repository {} is invalid. It must be a fingerprint (40 hexadecimal digits) or a long key ID (16 hexadecimal digits)",
+                    db_key,
+                    name
+                ));
+            }
+        }
+        if let Some(sig_level) = &repo.chroot_sig_level {
+            if sig_level.trim().is_empty() || sig_level.contains(['\n', '\r']) {
+                return Err(anyhow!(
+                    "ChrootSigLevel of repository {} must be a non-empty, single-line value",
+                    name
+                ));
+            }
+        }
+        if let Some(mirror) = &repo.chroot_mirror {
+            if !is_valid_mirror_url(mirror) {
+                return Err(anyhow!(
+                    "ChrootMirror '{}' of repository {} is invalid. It must be an http, https, ftp or file URL such as 'https://mirror.example.com/archlinux/$repo/os/$arch'",
+                    mirror,
+                    name
+                ));
+            }
+        }
+        for (pattern, key) in &repo.pkg_keys {
+            if glob::Pattern::new(pattern).is_err() {
+                return Err(anyhow!(
+                    "Package name pattern '{}' of PkgKeys of repository {} is invalid",
+                    pattern,
+                    name
+                ));
+            }
+            if key.trim().is_empty() {
+                return Err(anyhow!(
+                    "Key for package name pattern '{}' of PkgKeys of repository {} is empty",
+                    pattern,
+                    name
+                ));
+            }
+        }
+        for server in repo.server.iter_mut() {
+            *server = server
+                .replace(CFG_VAR_ARCH, &arch()?.to_string())
+                .replace(CFG_VAR_REPO, name);
+            if let Some(db_name) = &repo.db_name {
+                *server = server.replace(CFG_VAR_DB, db_name)
+            }
         }
     }
 
     Ok(repos)
 }
+
+/// Checks if `key` identifies a PGP key unambiguously. I.e., it must either be
+/// a fingerprint (40 hexadecimal digits) or a long key ID (16 hexadecimal
+/// digits). Spaces (as in the fingerprints that gpg prints) are ignored
+fn is_valid_key_id(key: &str) -> bool {
+    let key = key.replace(' ', "");
+    (key.len() == 16 || key.len() == 40) && key.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Checks if `name` can be used as name of a repository or a DB. Such names
+/// become part of paths. Thus, they must not contain path separators or white
+/// space, and they must not start with '.' (which excludes "." and "..")
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.+@".contains(c))
+}
+
+/// Checks if `mirror` is a valid URL of a pacman mirror. I.e., it must have
+/// one of the schemes that pacman supports for servers, and (except for local
+/// mirrors) a host. The pacman variables $repo and $arch are allowed
+fn is_valid_mirror_url(mirror: &str) -> bool {
+    if mirror.trim() != mirror || mirror.contains(char::is_whitespace) {
+        return false;
+    }
+    match url::Url::parse(mirror) {
+        Ok(url) => match url.scheme() {
+            "http" | "https" | "ftp" => url.host_str().is_some_and(|host| !host.is_empty()),
+            "file" => true,
+            _ => false,
+        },
+        Err(_) => false,
+    }
+}
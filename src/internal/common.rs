@@ -3,17 +3,22 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use anyhow::{anyhow, Context};
+use arch_msgs::*;
 use cached::proc_macro::cached;
+use dialoguer::Confirm;
 use duct::cmd;
 use once_cell::sync::OnceCell;
 use std::{
     env,
     error::Error,
+    ffi::{OsStr, OsString},
     fmt::{self, Display, Formatter},
-    fs, io,
+    fs, io, iter,
     path::{Path, PathBuf},
     process,
     str::from_utf8,
+    thread,
+    time::Duration,
 };
 
 /// Names of optional dependencies
@@ -21,7 +26,7 @@ const PKG_NAME_GPG: &str = "gnupg";
 
 /// Supported architectures
 #[allow(non_camel_case_types)]
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Arch {
     any,
     aarch64,
@@ -55,15 +60,44 @@ where
         match arch.as_ref() {
             "any" => Arch::any,
             "aarch64" => Arch::aarch64,
-            "arm" => Arch::armv7h,
+            "arm" | "armv7h" => Arch::armv7h,
             "x86_64" => Arch::x86_64,
             &_ => Arch::Unknown,
         }
     }
 }
 
-/// Retrieves architecture of the system repman is running on
+/// Output formats
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+    Csv,
+}
+
+/// Signature modes for package files. Detached binary signatures (*.sig) are
+/// always created, since repo-add and pacman require them. With `Armored`,
+/// ASCII-armored detached signatures (*.asc) are created in addition
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SigMode {
+    #[default]
+    Detached,
+    Armored,
+}
+
+/// Architecture that was set explicitly and that is used instead of the
+/// architecture of the system repman is running on
+static ARCH: OnceCell<Arch> = OnceCell::new();
+
+/// Retrieves architecture of the system repman is running on. If an architecture
+/// was set explicitly via `set_arch()`, that architecture is returned instead
 pub fn arch() -> anyhow::Result<Arch> {
+    if let Some(arch) = ARCH.get() {
+        return Ok(*arch);
+    }
+
     match Arch::from(env::consts::ARCH) {
         Arch::Unknown => Err(anyhow!(format!(
             "Architecture of this system ({}) is not supported",
@@ -73,8 +107,26 @@ pub fn arch() -> anyhow::Result<Arch> {
     }
 }
 
+/// Sets the architecture that is used instead of the architecture of the system
+/// repman is running on (e.g., to determine the repository directory via the
+/// variable `$arch` in the configuration). It can only be set once
+pub fn set_arch<S>(arch: S) -> anyhow::Result<()>
+where
+    S: AsRef<str>,
+{
+    match Arch::from(arch.as_ref()) {
+        Arch::Unknown | Arch::any => {
+            Err(anyhow!("Architecture '{}' is not supported", arch.as_ref()))
+        }
+        arch => ARCH
+            .set(arch)
+            .map_err(|_| anyhow!("Architecture has already been set")),
+    }
+}
+
 /// File suffixes
 pub const SIG_SUFFIX: &str = ".sig";
+pub const ARMORED_SIG_SUFFIX: &str = ".asc";
 
 /// File and directory names
 const CACHE_SUB_PATH: &str = ".cache";
@@ -83,6 +135,9 @@ const LOCKS_SUB_PATH: &str = "locks";
 const TMP_SUB_PATH: &str = "tmp";
 pub const REPMAN_SUB_PATH: &str = "repman";
 
+/// Delay between two attempts to acquire a lock that is held by another process
+const LOCK_RETRY_DELAY_SECS: u64 = 2;
+
 /// Path of cache directory. Often that's "~/.cache". The retrieval of the
 /// cache directory is only done once. The result is buffered in a static
 /// variable.
@@ -106,6 +161,16 @@ pub fn cache_dir() -> anyhow::Result<&'static Path> {
         .as_path())
 }
 
+/// File to which a summary of the changes of repositories is written
+static CHANGE_SUMMARY_FILE: OnceCell<PathBuf> = OnceCell::new();
+
+/// Returns the file to which a summary of the changes of repositories is
+/// written, or `None` if no such summary is requested (see
+/// `set_change_summary_file()`)
+pub fn change_summary_file() -> Option<&'static Path> {
+    CHANGE_SUMMARY_FILE.get().map(PathBuf::as_path)
+}
+
 /// Path of config directory. Often that's "~/.config". The retrieval of the
 /// config directory is only done once. The result is buffered in a static
 /// variable.
@@ -129,6 +194,79 @@ pub fn config_dir() -> anyhow::Result<&'static Path> {
         .as_path())
 }
 
+/// Indicates whether all confirmation prompts shall be answered with their
+/// default automatically
+static ASSUME_YES: OnceCell<bool> = OnceCell::new();
+
+/// Asks the user for confirmation with `prompt`, where `default` is the
+/// preselected answer. If confirmation prompts shall be answered automatically
+/// (see `set_assume_yes()`), the user is not asked and `default` is returned
+pub fn confirm<S>(prompt: S, default: bool) -> anyhow::Result<bool>
+where
+    S: Into<String>,
+{
+    if ASSUME_YES.get().is_some_and(|assume_yes| *assume_yes) {
+        return Ok(default);
+    }
+
+    Confirm::new()
+        .with_prompt(prompt)
+        .default(default)
+        .show_default(true)
+        .interact()
+        .with_context(|| "Cannot ask user for confirmation")
+}
+
+/// Creates the file `file` with the ID of the current process as content. The
+/// content is written to a temporary file first, which is then hard linked to
+/// `file`. Thus, the file is created atomically: Other processes never see an
+/// empty or partially written file. Returns false if `file` exists already
+pub fn create_pid_file<P>(file: P) -> anyhow::Result<bool>
+where
+    P: AsRef<Path>,
+{
+    let file = file.as_ref();
+    let err_msg = format!("Cannot create file '{}'", file.display());
+
+    let tmp_file = file.with_file_name(format!(
+        ".{}.{}.tmp",
+        file.file_name()
+            .unwrap_or_else(|| panic!("Cannot determine name of file '{}'", file.display()))
+            .to_string_lossy(),
+        process::id()
+    ));
+    fs::write(&tmp_file, process::id().to_string()).with_context(|| err_msg.clone())?;
+    let result = fs::hard_link(&tmp_file, file);
+    fs::remove_file(&tmp_file).with_context(|| err_msg.clone())?;
+
+    match result {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+        Err(err) => Err(err).with_context(|| err_msg),
+    }
+}
+
+/// Assembles a line in CSV format from `fields`. Fields that contain commas,
+/// double quotes or line breaks are enclosed in double quotes, and double
+/// quotes in such fields are doubled
+pub fn csv_record<S>(fields: &[S]) -> String
+where
+    S: AsRef<str>,
+{
+    fields
+        .iter()
+        .map(|field| {
+            let field = field.as_ref();
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
 /// Create directory `dir` if it does not exist
 pub fn ensure_dir<P>(dir: P) -> anyhow::Result<PathBuf>
 where
@@ -172,23 +310,312 @@ where
         .with_context(|| format!("Cannot check if package '{}' is installed", pkg_name))
 }
 
-/// Retrieve the process ID from the file `file`
-pub fn pid_from_file<P>(file: P) -> anyhow::Result<u32>
+/// Formats a duration in a human readable way (e.g. "1h 02m 03s")
+pub fn fmt_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!(
+            "{}h {:02}m {:02}s",
+            secs / 3600,
+            secs % 3600 / 60,
+            secs % 60
+        )
+    } else if secs >= 60 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{:.1}s", duration.as_secs_f64())
+    }
+}
+
+/// Installs the package files `pkg_files` on the local system with pacman. If
+/// repman is not running as root, pacman is run via sudo or su
+pub fn install_pkgs<P>(pkg_files: &[P]) -> anyhow::Result<()>
+where
+    P: AsRef<Path>,
+{
+    let mut args: Vec<&OsStr> = vec![OsStr::new("-U"), OsStr::new("--noconfirm")];
+    args.extend(
+        pkg_files
+            .iter()
+            .map(|pkg_file| pkg_file.as_ref().as_os_str()),
+    );
+
+    run_as_root("pacman", &args).with_context(|| "Cannot install packages")
+}
+
+/// Checks if a process with the ID `pid` is running
+pub fn is_process_running(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+/// Checks if `path` is a mount point. The check is done based on the mounts of
+/// the current process as listed in /proc/self/mounts
+pub fn is_mount_point<P>(path: P) -> anyhow::Result<bool>
 where
     P: AsRef<Path>,
 {
     let err_msg = format!(
-        "Cannot retrieve PID from file '{}'",
-        file.as_ref().display()
+        "Cannot check if '{}' is a mount point",
+        path.as_ref().display()
     );
-    fs::read_to_string(file)
-        .with_context(|| err_msg.clone())?
+
+    if !path.as_ref().exists() {
+        return Ok(false);
+    }
+    let path = path
+        .as_ref()
+        .canonicalize()
+        .with_context(|| err_msg.clone())?;
+
+    // Spaces etc. are escaped as octal numbers in /proc/self/mounts. Since
+    // repman does not use such characters in the paths of its mount points,
+    // that's ignored
+    Ok(fs::read_to_string("/proc/self/mounts")
+        .with_context(|| err_msg)?
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .any(|mount_point| Path::new(mount_point) == path))
+}
+
+/// Mounts a tmpfs file system of size `size` (in the format of the size option
+/// of tmpfs, e.g. "75%" or "20G") at directory `dir`, which must exist already.
+/// If repman is not running as root, "mount" is run via sudo or su
+pub fn mount_tmpfs<P>(dir: P, size: &str) -> anyhow::Result<()>
+where
+    P: AsRef<Path>,
+{
+    let size_opt = format!("size={}", size);
+    run_as_root(
+        "mount",
+        &[
+            OsStr::new("-t"),
+            OsStr::new("tmpfs"),
+            OsStr::new("-o"),
+            OsStr::new(&size_opt),
+            OsStr::new("tmpfs"),
+            dir.as_ref().as_os_str(),
+        ],
+    )
+    .with_context(|| format!("Cannot mount tmpfs at '{}'", dir.as_ref().display()))
+}
+
+/// Moves (i.e., renames) the file or directory `from` to `to` with root
+/// privileges. If repman is not running as root, "mv" is run via sudo or su
+pub fn move_as_root<P, Q>(from: P, to: Q) -> anyhow::Result<()>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    run_as_root(
+        "mv",
+        &[
+            OsStr::new("-T"),
+            from.as_ref().as_os_str(),
+            to.as_ref().as_os_str(),
+        ],
+    )
+    .with_context(|| {
+        format!(
+            "Cannot move '{}' to '{}'",
+            from.as_ref().display(),
+            to.as_ref().display()
+        )
+    })
+}
+
+/// Retrieve the process ID from the file `file`. If the file does not contain
+/// a valid process ID (e.g., since it is empty because the process that wrote it
+/// crashed), `None` is returned
+pub fn pid_from_file<P>(file: P) -> anyhow::Result<Option<u32>>
+where
+    P: AsRef<Path>,
+{
+    Ok(fs::read_to_string(file.as_ref())
+        .with_context(|| {
+            format!(
+                "Cannot retrieve PID from file '{}'",
+                file.as_ref().display()
+            )
+        })?
+        .trim()
         .parse::<u32>()
-        .with_context(|| err_msg)
+        .ok())
+}
+
+/// Imports the public keys with IDs or fingerprints contained in `keys` from a
+/// key server into the keyring of the current user. Only keys that are not
+/// contained in the keyring already are imported
+pub fn recv_pgp_keys<S>(keys: &[S]) -> anyhow::Result<()>
+where
+    S: AsRef<str>,
+{
+    let err_msg = "Cannot import PGP keys";
+
+    // GPG package must be installed to import keys
+    if !is_pkg_installed(PKG_NAME_GPG).with_context(|| err_msg)? {
+        return Err(anyhow!(
+            "Importing PGP keys requires package {} being installed",
+            PKG_NAME_GPG
+        ))
+        .context(err_msg);
+    }
+
+    let missing_keys: Vec<&str> = keys
+        .iter()
+        .map(AsRef::as_ref)
+        .filter(|key| {
+            !cmd!("gpg", "--list-keys", key)
+                .stdout_null()
+                .stderr_null()
+                .unchecked()
+                .run()
+                .is_ok_and(|output| output.status.success())
+        })
+        .collect();
+    if missing_keys.is_empty() {
+        return Ok(());
+    }
+
+    msg!("Importing PGP key(s) {} ...", missing_keys.join(", "));
+
+    let mut args: Vec<&str> = vec!["--recv-keys"];
+    args.extend(&missing_keys);
+    let output = cmd("gpg", &args)
+        .stdout_null()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .with_context(|| err_msg)?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!(format!("gpg: {}", from_utf8(&output.stderr).unwrap())).context(err_msg))
+    }
+}
+
+/// Updates the public keys contained in the keyring of the current user from a
+/// key server. This is required to get expired keys renewed or revoked keys
+/// marked as such
+pub fn refresh_pgp_keys() -> anyhow::Result<()> {
+    let err_msg = "Cannot refresh PGP keys";
+
+    // GPG package must be installed to refresh keys
+    if !is_pkg_installed(PKG_NAME_GPG).with_context(|| err_msg)? {
+        return Err(anyhow!(
+            "Refreshing PGP keys requires package {} being installed",
+            PKG_NAME_GPG
+        ))
+        .context(err_msg);
+    }
+
+    msg!("Refreshing PGP keys ...");
+
+    let output = cmd!("gpg", "--refresh-keys")
+        .stdout_null()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .with_context(|| err_msg)?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!(format!("gpg: {}", from_utf8(&output.stderr).unwrap())).context(err_msg))
+    }
 }
 
-/// Signs file `file` with `gpg` using key `gpg_key`
-pub fn sign_file<P, S>(file: P, gpg_key: S) -> anyhow::Result<()>
+/// Removes the file or directory `path` (recursively) with root privileges.
+/// This is required for files and directories that are owned by root, such as
+/// chroot containers. If repman is not running as root, "rm" is run via sudo or
+/// su
+pub fn remove_as_root<P>(path: P) -> anyhow::Result<()>
+where
+    P: AsRef<Path>,
+{
+    let err_msg = format!("Cannot remove '{}'", path.as_ref().display());
+
+    if sudo::check() == sudo::RunningAs::Root {
+        return if path.as_ref().is_dir() {
+            fs::remove_dir_all(path.as_ref()).with_context(|| err_msg)
+        } else {
+            fs::remove_file(path.as_ref()).with_context(|| err_msg)
+        };
+    }
+
+    run_as_root("rm", &[OsStr::new("-rdf"), path.as_ref().as_os_str()]).with_context(|| err_msg)
+}
+
+/// Executes `program` with arguments `args` with root privileges. If repman is
+/// not running as root, the program is run via sudo or su
+fn run_as_root(program: &str, args: &[&OsStr]) -> anyhow::Result<()> {
+    let err_msg = format!("Cannot execute {} as root", program);
+
+    let (root_cmd, root_args): (&str, Vec<OsString>) = if sudo::check() == sudo::RunningAs::Root {
+        (program, args.iter().map(|arg| arg.to_os_string()).collect())
+    } else if is_pkg_installed("sudo").with_context(|| err_msg.clone())? {
+        (
+            "sudo",
+            iter::once(OsStr::new(program))
+                .chain(args.iter().copied())
+                .map(OsStr::to_os_string)
+                .collect(),
+        )
+    } else {
+        // su passes one command string to the shell. Thus, program and arguments
+        // are quoted to be passed unchanged
+        let command = iter::once(OsStr::new(program))
+            .chain(args.iter().copied())
+            .map(shell_quote)
+            .collect::<Vec<String>>()
+            .join(" ");
+        (
+            "su",
+            vec![
+                OsString::from("root"),
+                OsString::from("-c"),
+                OsString::from(command),
+            ],
+        )
+    };
+
+    let output = cmd(root_cmd, &root_args)
+        .stdout_null()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .with_context(|| err_msg.clone())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!("{}: {}", program, from_utf8(&output.stderr).unwrap()).context(err_msg))
+    }
+}
+
+/// Quotes `arg` for the shell (in single quotes), so that it is passed as one
+/// word even if it contains white space or special characters
+fn shell_quote(arg: &OsStr) -> String {
+    format!("'{}'", arg.to_string_lossy().replace('\'', r"'\''"))
+}
+
+/// Lets all confirmation prompts be answered with their default automatically.
+/// It can only be set once
+pub fn set_assume_yes() {
+    ASSUME_YES.get_or_init(|| true);
+}
+
+/// Lets a summary of the changes of repositories be written to `file`. The file
+/// is created (or truncated if it exists already). It can only be set once
+pub fn set_change_summary_file(file: PathBuf) -> anyhow::Result<()> {
+    fs::write(&file, "")
+        .with_context(|| format!("Cannot create change summary file '{}'", file.display()))?;
+    CHANGE_SUMMARY_FILE.get_or_init(|| file);
+    Ok(())
+}
+
+/// Signs file `file` with `gpg` using key `gpg_key`. Depending on `sig_mode`,
+/// an ASCII-armored signature file is created in addition to the binary one
+pub fn sign_file<P, S>(file: P, gpg_key: S, sig_mode: SigMode) -> anyhow::Result<()>
 where
     P: AsRef<Path>,
     S: AsRef<str>,
@@ -208,28 +635,43 @@ where
         return Err(anyhow!("GPG key is not set").context(err_msg));
     }
 
-    let output = cmd!(
-        "gpg",
-        "--yes",
-        "-u",
-        gpg_key.as_ref(),
-        "--output",
-        file.as_ref().to_str().unwrap().to_string() + SIG_SUFFIX,
-        "--detach-sign",
-        "--pinentry-mode=loopback",
-        file.as_ref().to_str().unwrap(),
-    )
-    .stdout_null()
-    .stderr_capture()
-    .unchecked()
-    .run()
-    .with_context(|| err_msg.clone())?;
+    let mut suffixes = vec![SIG_SUFFIX];
+    if sig_mode == SigMode::Armored {
+        suffixes.push(ARMORED_SIG_SUFFIX);
+    }
+    for suffix in suffixes {
+        let mut args: Vec<&OsStr> = vec![
+            OsStr::new("--yes"),
+            OsStr::new("-u"),
+            OsStr::new(gpg_key.as_ref()),
+        ];
+        if suffix == ARMORED_SIG_SUFFIX {
+            args.push(OsStr::new("--armor"));
+        }
+        let sig_file = file.as_ref().to_str().unwrap().to_string() + suffix;
+        args.extend([
+            OsStr::new("--output"),
+            OsStr::new(&sig_file),
+            OsStr::new("--detach-sign"),
+            OsStr::new("--pinentry-mode=loopback"),
+            file.as_ref().as_os_str(),
+        ]);
 
-    if output.status.success() {
-        Ok(())
-    } else {
-        Err(anyhow!(format!("gpg: {}", from_utf8(&output.stderr).unwrap())).context(err_msg))
+        let output = cmd("gpg", &args)
+            .stdout_null()
+            .stderr_capture()
+            .unchecked()
+            .run()
+            .with_context(|| err_msg.clone())?;
+
+        if !output.status.success() {
+            return Err(
+                anyhow!(format!("gpg: {}", from_utf8(&output.stderr).unwrap())).context(err_msg),
+            );
+        }
     }
+
+    Ok(())
 }
 
 /// Assemble the path for the temporary directory for the current process.
@@ -241,6 +683,161 @@ pub fn tmp_dir() -> anyhow::Result<PathBuf> {
         .join(format!("{}", process::id())))
 }
 
+/// Removes temporary directories of repman processes that are no longer
+/// running. Such directories are left over if a process was interrupted
+pub fn remove_stale_tmp_dirs() -> anyhow::Result<()> {
+    let err_msg = "Cannot remove stale temporary directories";
+
+    let tmp_root_dir = cache_dir().with_context(|| err_msg)?.join(TMP_SUB_PATH);
+    if !tmp_root_dir.exists() {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(&tmp_root_dir).with_context(|| err_msg)? {
+        let path = entry.with_context(|| err_msg)?.path();
+        if let Some(pid) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.parse::<u32>().ok())
+        {
+            if pid != process::id() && !is_process_running(pid) {
+                fs::remove_dir_all(&path).with_context(|| err_msg)?;
+                msg!("Removed stale temporary directory '{}'", path.display());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Unmounts the file system that is mounted at directory `dir`. If repman is not
+/// running as root, "umount" is run via sudo or su
+pub fn unmount<P>(dir: P) -> anyhow::Result<()>
+where
+    P: AsRef<Path>,
+{
+    run_as_root("umount", &[dir.as_ref().as_os_str()])
+        .with_context(|| format!("Cannot unmount '{}'", dir.as_ref().display()))
+}
+
+/// Verifies the signature of file `file` (i.e., the binary signature file next
+/// to it) with `gpg`. The verification only succeeds if the signature is valid
+/// and if it was created with the key `trusted_key` (a fingerprint or a long key
+/// ID). A fingerprint must match exactly, a long key ID must match the last 16
+/// digits of a fingerprint. The key is imported from a key server if it is not
+/// contained in the keyring of the current user
+pub fn verify_file<P, S>(file: P, trusted_key: S) -> anyhow::Result<()>
+where
+    P: AsRef<Path>,
+    S: AsRef<str>,
+{
+    let err_msg = format!(
+        "Cannot verify signature of file '{}'",
+        file.as_ref().display()
+    );
+
+    let sig_file = PathBuf::from(file.as_ref().to_string_lossy().to_string() + SIG_SUFFIX);
+    if !sig_file.is_file() {
+        return Err(
+            anyhow!("Signature file '{}' does not exist", sig_file.display()).context(err_msg),
+        );
+    }
+
+    let trusted_key = trusted_key.as_ref().replace(' ', "").to_uppercase();
+    if trusted_key.len() != 16 && trusted_key.len() != 40 {
+        return Err(anyhow!(
+            "Trusted key {} is neither a fingerprint nor a long key ID",
+            trusted_key
+        )
+        .context(err_msg));
+    }
+
+    recv_pgp_keys(&[&trusted_key]).with_context(|| err_msg.clone())?;
+
+    // The status output of gpg contains the fingerprints of the signing key and
+    // of its primary key in the VALIDSIG line, if the signature is valid
+    let output = cmd!(
+        "gpg",
+        "--status-fd",
+        "1",
+        "--verify",
+        &sig_file,
+        file.as_ref()
+    )
+    .stdout_capture()
+    .stderr_capture()
+    .unchecked()
+    .run()
+    .with_context(|| err_msg.clone())?;
+    if !output.status.success() {
+        return Err(anyhow!("gpg: {}", from_utf8(&output.stderr).unwrap()).context(err_msg));
+    }
+
+    let is_trusted = from_utf8(&output.stdout)
+        .with_context(|| err_msg.clone())?
+        .lines()
+        .filter_map(|line| line.strip_prefix("[GNUPG:] VALIDSIG "))
+        .flat_map(|fields| {
+            let fields: Vec<&str> = fields.split_whitespace().collect();
+            [fields.first().copied(), fields.last().copied()]
+        })
+        .flatten()
+        .map(str::to_uppercase)
+        .any(|fingerprint| {
+            fingerprint.len() == 40
+                && (fingerprint == trusted_key
+                    || (trusted_key.len() == 16 && fingerprint[24..] == trusted_key))
+        });
+    if !is_trusted {
+        return Err(anyhow!(
+            "Signature was not created with the trusted key {}",
+            trusted_key
+        )
+        .context(err_msg));
+    }
+
+    Ok(())
+}
+
+/// Creates the lock file `file` (see `create_pid_file()`). If another process
+/// holds the lock, it is waited until that lock is removed. `on_wait` is called
+/// with the ID of that process before waiting starts. Locks of processes that
+/// are no longer running are removed. Returns true if the lock was created, and
+/// false if the current process holds it already
+pub fn wait_for_lock<P, F>(file: P, on_wait: F) -> anyhow::Result<bool>
+where
+    P: AsRef<Path>,
+    F: FnOnce(u32),
+{
+    let file = file.as_ref();
+    let err_msg = format!("Cannot create lock file '{}'", file.display());
+
+    let mut on_wait = Some(on_wait);
+    loop {
+        if create_pid_file(file).with_context(|| err_msg.clone())? {
+            return Ok(true);
+        }
+
+        let pid = match pid_from_file(file) {
+            Ok(pid) => pid,
+            // The lock was removed in the meantime
+            Err(_) if !file.exists() => continue,
+            Err(err) => return Err(err).with_context(|| err_msg),
+        };
+        match pid {
+            Some(pid) if pid == process::id() => return Ok(false),
+            Some(pid) if is_process_running(pid) => {
+                if let Some(on_wait) = on_wait.take() {
+                    on_wait(pid);
+                }
+                thread::sleep(Duration::from_secs(LOCK_RETRY_DELAY_SECS));
+            }
+            // The lock is stale
+            _ => fs::remove_file(file).with_context(|| err_msg.clone())?,
+        }
+    }
+}
+
 /// This private function is called by is_pkg_installed. It is required since
 /// the call of 'pacman -Q <PKG_NAME>' shall be cached due to performance
 /// reasons. But is_pkg_installed cannot by used together with the cached macro
@@ -2,10 +2,28 @@
 //
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::{collections::HashMap, fmt};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+/// Dependency between two packages of a repository: Package `from` depends on
+/// package `to`
+#[derive(serde::Serialize)]
+pub struct DepEdge<'a> {
+    pub from: &'a str,
+    pub to: &'a str,
+}
 
 pub struct DepPkgs<'a>(Vec<&'a str>);
 
+impl<'a> DepPkgs<'a> {
+    /// Returns an iterator over the names of the dependent packages
+    pub fn iter(&self) -> impl Iterator<Item = &&'a str> {
+        self.0.iter()
+    }
+}
+
 impl fmt::Display for DepPkgs<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut s = "".to_string();
@@ -56,4 +74,40 @@ impl<'a> Deps<'a> {
     pub fn get(&self, pkg_name: &str) -> Option<&DepPkgs> {
         self.0.get(pkg_name)
     }
+
+    /// Returns the dependencies between the packages whose names are contained in
+    /// `pkg_names` as edges, sorted by the names of the dependent packages
+    pub fn edges(&self, pkg_names: &HashSet<&str>) -> Vec<DepEdge> {
+        let mut edges: Vec<DepEdge> = self
+            .0
+            .iter()
+            .filter(|(dep, _)| pkg_names.contains(*dep))
+            .flat_map(|(dep, dep_pkgs)| {
+                dep_pkgs
+                    .0
+                    .iter()
+                    .filter(|pkg_name| pkg_names.contains(*pkg_name))
+                    .map(|pkg_name| DepEdge {
+                        from: pkg_name,
+                        to: dep,
+                    })
+            })
+            .collect();
+        edges.sort_by(|a, b| (a.from, a.to).cmp(&(b.from, b.to)));
+        edges.dedup_by(|a, b| (a.from, a.to) == (b.from, b.to));
+        edges
+    }
+}
+
+/// Renders the packages `pkg_names` and the dependencies `edges` between them as
+/// graph `name` in the DOT language of Graphviz
+pub fn to_dot(name: &str, pkg_names: &[&str], edges: &[DepEdge]) -> String {
+    let mut dot = format!("digraph \"{}\" {{\n", name);
+    for pkg_name in pkg_names {
+        dot += &format!("    \"{}\";\n", pkg_name);
+    }
+    for edge in edges {
+        dot += &format!("    \"{}\" -> \"{}\";\n", edge.from, edge.to);
+    }
+    dot + "}\n"
 }
@@ -0,0 +1,169 @@
+// SPDX-FileCopyrightText: 2019-2024 Michael Picht <mipi@fsfe.org>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use crate::internal::common::*;
+use anyhow::{anyhow, Context};
+use duct::cmd;
+use std::{path::Path, str::from_utf8};
+
+/// Names of optional dependencies
+const PKG_NAME_GIT: &str = "git";
+
+/// Clones the git repository at `url` to `dir`. If `rev` is `Some(...)`, the
+/// corresponding revision (i.e., a branch, a tag or a commit) is checked out
+/// after cloning
+pub fn clone<P, S>(url: S, dir: P, rev: Option<&str>) -> anyhow::Result<()>
+where
+    P: AsRef<Path>,
+    S: AsRef<str>,
+{
+    let err_msg = format!("Cannot clone git repository '{}'", url.as_ref());
+
+    // Package git must be installed to be able to clone repositories
+    if !is_pkg_installed(PKG_NAME_GIT).with_context(|| err_msg.clone())? {
+        return Err(anyhow!(
+            "Cloning a git repository requires package {} being installed",
+            PKG_NAME_GIT
+        ))
+        .context(err_msg);
+    }
+
+    let output = cmd!("git", "clone", url.as_ref(), dir.as_ref())
+        .stdout_null()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .with_context(|| err_msg.clone())?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git clone: {}",
+            from_utf8(&output.stderr)
+                .unwrap_or_else(|_| panic!("Cannot retrieve stderr for 'git clone ...'"))
+        )
+        .context(err_msg));
+    }
+
+    if let Some(rev) = rev {
+        let output = cmd!("git", "checkout", rev)
+            .dir(dir.as_ref())
+            .stdout_null()
+            .stderr_capture()
+            .unchecked()
+            .run()
+            .with_context(|| err_msg.clone())?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "git checkout: {}",
+                from_utf8(&output.stderr)
+                    .unwrap_or_else(|_| panic!("Cannot retrieve stderr for 'git checkout ...'"))
+            )
+            .context(err_msg));
+        }
+    }
+
+    Ok(())
+}
+
+/// Updates the clone of a git repository in `dir` from its remote repository.
+/// Only fast-forward updates are done. Thus, the update fails if the histories
+/// have diverged
+pub fn pull<P>(dir: P) -> anyhow::Result<()>
+where
+    P: AsRef<Path>,
+{
+    let err_msg = format!("Cannot update git repository '{}'", dir.as_ref().display());
+
+    let output = cmd!("git", "pull", "--ff-only")
+        .dir(dir.as_ref())
+        .stdout_null()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .with_context(|| err_msg.clone())?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git pull: {}",
+            from_utf8(&output.stderr)
+                .unwrap_or_else(|_| panic!("Cannot retrieve stderr for 'git pull ...'"))
+        )
+        .context(err_msg));
+    }
+
+    Ok(())
+}
+
+/// Returns the ID of the commit that `rev` (a branch or a tag) refers to in the
+/// remote git repository at `url`, without cloning the repository. If `rev` is
+/// `None`, the commit of HEAD is returned
+pub fn remote_commit<S>(url: S, rev: Option<&str>) -> anyhow::Result<String>
+where
+    S: AsRef<str>,
+{
+    let err_msg = format!(
+        "Cannot determine commit of git repository '{}'",
+        url.as_ref()
+    );
+
+    // Package git must be installed to be able to access repositories
+    if !is_pkg_installed(PKG_NAME_GIT).with_context(|| err_msg.clone())? {
+        return Err(anyhow!(
+            "Accessing a git repository requires package {} being installed",
+            PKG_NAME_GIT
+        ))
+        .context(err_msg);
+    }
+
+    let output = cmd!("git", "ls-remote", url.as_ref(), rev.unwrap_or("HEAD"))
+        .stdout_capture()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .with_context(|| err_msg.clone())?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git ls-remote: {}",
+            from_utf8(&output.stderr)
+                .unwrap_or_else(|_| panic!("Cannot retrieve stderr for 'git ls-remote ...'"))
+        )
+        .context(err_msg));
+    }
+
+    // Output lines have the format "<COMMIT>\t<REF>". For annotated tags, the
+    // line of the dereferenced tag ("<REF>^{}") contains the commit
+    let stdout = from_utf8(&output.stdout).with_context(|| err_msg.clone())?;
+    let mut lines: Vec<(&str, &str)> = stdout
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .collect();
+    lines.sort_by_key(|(_, git_ref)| !git_ref.ends_with("^{}"));
+    lines
+        .first()
+        .map(|(commit, _)| commit.to_string())
+        .ok_or_else(|| anyhow!("Revision '{}' not found", rev.unwrap_or("HEAD")))
+        .with_context(|| err_msg)
+}
+
+/// Splits a git URL into the URL of the repository and an optional revision.
+/// The revision can either be appended as fragment (`<URL>#<BRANCH>`) or as
+/// query parameter (`<URL>?ref=<REVISION>`)
+pub fn split_url(url: &str) -> (&str, Option<&str>) {
+    if let Some((repo_url, rev)) = url.rsplit_once('#') {
+        return (repo_url, Some(rev).filter(|rev| !rev.is_empty()));
+    }
+    if let Some((repo_url, rev)) = url.rsplit_once("?ref=") {
+        return (repo_url, Some(rev).filter(|rev| !rev.is_empty()));
+    }
+    (url, None)
+}
+
+/// Derives a directory name from the URL of a git repository. I.e., the last
+/// path segment of the URL without the suffix ".git"
+pub fn dir_name_from_url(url: &str) -> &str {
+    let name = url
+        .trim_end_matches('/')
+        .rsplit(['/', ':'])
+        .next()
+        .unwrap_or(url);
+    name.strip_suffix(".git").unwrap_or(name)
+}
@@ -4,9 +4,10 @@
 
 mod aur;
 pub mod cfg;
-mod common;
+pub mod common;
 mod deps;
+mod git;
 mod pkg;
-mod pkgbuild;
+pub mod pkgbuild;
 pub mod repo;
 mod server;
@@ -2,9 +2,13 @@
 //
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use crate::internal::{common::*, pkgbuild::PkgBuild};
+use crate::internal::{
+    common::*,
+    pkgbuild::{BuildOpts, PkgBuild},
+};
 use anyhow::{anyhow, Context};
 use arch_msgs::*;
+use duct::cmd;
 use glob::glob;
 use lazy_static::lazy_static;
 use regex::Regex;
@@ -14,6 +18,40 @@ use std::{
     path::{Path, PathBuf},
 };
 
+/// Supported extensions of package files. I.e., uncompressed package files and
+/// all compressors that are supported by makepkg (see PKGEXT in makepkg.conf(5))
+pub const PKG_EXTS: [&str; 10] = [
+    ".pkg.tar",
+    ".pkg.tar.gz",
+    ".pkg.tar.bz2",
+    ".pkg.tar.xz",
+    ".pkg.tar.zst",
+    ".pkg.tar.lzo",
+    ".pkg.tar.lrz",
+    ".pkg.tar.lz4",
+    ".pkg.tar.lz",
+    ".pkg.tar.Z",
+];
+
+/// Name of the file within a package file that contains the package meta data
+const PKGINFO_FILE_NAME: &str = ".PKGINFO";
+
+/// Suffix of the name of the debug package that makepkg builds for a package base
+const DEBUG_PKG_SUFFIX: &str = "-debug";
+
+// Regular expression pattern that matches the supported extensions of package
+// files (as capture group)
+lazy_static! {
+    static ref RE_PKG_EXTS: String = format!(
+        "({})",
+        PKG_EXTS
+            .iter()
+            .map(|ext| regex::escape(ext))
+            .collect::<Vec<String>>()
+            .join("|")
+    );
+}
+
 // Regular expression to check if a file could be a package file wrt. its path
 // and to extract:
 //   (1) Path of package directory
@@ -24,12 +62,15 @@ use std::{
 //   (6) Suffix of package file
 // from package file path
 lazy_static! {
-    static ref RE_PKG_FILE: Regex =
-        Regex::new(r"^(.*/)?(.+)-([^-]+)-([^-]+)-([^-]+)(\.pkg\.tar\.[^\.]+)$").unwrap();
+    static ref RE_PKG_FILE: Regex = Regex::new(&format!(
+        r"^(.*/)?(.+)-([^-]+)-([^-]+)-([^-]+){}$",
+        *RE_PKG_EXTS
+    ))
+    .unwrap();
 }
 
 /// Package file
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Pkg(PathBuf);
 
 impl AsRef<Path> for Pkg {
@@ -63,35 +104,49 @@ impl Pkg {
     /// Builds packages from a PKGBUILD file. From one PKGBUILD file, multiple
     /// packages can be built (in case of [split packages](https://man.archlinux.org/man/PKGBUILD.5#PACKAGE_SPLITTING))
     /// Packages are either built via makechrootpkg or makepkg, depending on
-    /// `no_chroot`.
+    /// `opts.no_chroot`.
     /// After being built, package files are first stored in `pkg_dir`. Finally,
     /// they are moved to the repository directory `repo_dir`.
     /// If `sign` is `Some(...)`, package files are signed (`Some(true)`) or not
     /// (`Some(false)`). If `sign` is `None`, package files are only signed if
     /// there is a package file of an package version in `repo_dir` that is
-    /// signed
+    /// signed. `gpg_key` returns the key that a package is signed with for its
+    /// name. `sig_mode` determines which signature files are created.
+    /// If `build_dir` is `Some(...)`, the build takes place in this directory
+    /// (see `PkgBuild::build_with_makechrootpkg` and `PkgBuild::build_with_makepkg`).
+    /// `progress` contains the position of the PKGBUILD file and the total
+    /// number of PKGBUILD files of the current run. It is only used for messages.
     /// Function returns a vector of Pkg instance corresponding to the packages
     /// that were built
-    pub fn build<P, S>(
+    pub fn build<P, G>(
         pkgbuild: &PkgBuild,
-        no_chroot: bool,
-        ignore_arch: bool,
+        opts: &BuildOpts,
         sign: Option<bool>,
-        gpg_key: Option<S>,
+        gpg_key: G,
+        sig_mode: SigMode,
         repo_dir: P,
         chroot_dir: P,
         pkg_dir: P,
+        build_dir: Option<P>,
+        progress: (usize, usize),
     ) -> anyhow::Result<Vec<Pkg>>
     where
         P: AsRef<Path> + Copy,
-        S: AsRef<str>,
+        G: Fn(&str) -> Option<String>,
     {
         let err_msg = format!(
             "Cannot build packages from {}'",
             pkgbuild.as_ref().display()
         );
 
-        if sign.is_some() && sign.unwrap() && gpg_key.is_none() {
+        if sign.is_some()
+            && sign.unwrap()
+            && pkgbuild
+                .pkg_names()
+                .with_context(|| err_msg.clone())?
+                .iter()
+                .any(|pkg_name| gpg_key(pkg_name).is_none())
+        {
             return Err(anyhow!(
                 "Cannot built packages since they shall be signed but GPG_KEY is not set"
             ));
@@ -103,20 +158,42 @@ impl Pkg {
             return Err(anyhow!("PKGBUILD does not define any package").context(err_msg));
         }
 
-        msg!("Building package(s) from '{}'", pkgbuild.as_ref().display());
+        // Import the PGP keys that are required to verify the source files
+        if !opts.skip_pgp_check {
+            let keys = pkgbuild.valid_pgp_keys().with_context(|| err_msg.clone())?;
+            if !keys.is_empty() {
+                recv_pgp_keys(&keys).with_context(|| err_msg.clone())?;
+            }
+        }
+
+        msg!(
+            "[{}/{}] Building package(s) from '{}'",
+            progress.0,
+            progress.1,
+            pkgbuild.as_ref().display()
+        );
 
         // Build packages either with makepkg or makechrootpkg. Resulting package
         // files are stored in `pkg_dir`
-        if no_chroot {
+        if opts.no_chroot {
             pkgbuild
-                .build_with_makepkg(ignore_arch, pkg_dir)
+                .build_with_makepkg(opts, pkg_dir, build_dir)
                 .with_context(|| err_msg.clone())?
         } else {
             pkgbuild
-                .build_with_makechrootpkg(ignore_arch, repo_dir, chroot_dir, pkg_dir)
+                .build_with_makechrootpkg(opts, repo_dir, chroot_dir, pkg_dir, build_dir)
                 .with_context(|| err_msg.clone())?
         };
 
+        // With --nobuild, makepkg stops after the sources were prepared
+        if opts.no_build {
+            msg!(
+                "Sources of '{}' prepared. No packages were built (--nobuild)",
+                pkgbuild.as_ref().display()
+            );
+            return Ok(vec![]);
+        }
+
         // Process packages: Collect built packages, remove old package files,
         // copy new files to repository directory, and sign them
         let mut pkgs: Vec<Pkg> = vec![];
@@ -133,16 +210,30 @@ impl Pkg {
             match Pkg::from_file_ignore_version(&pkg_file) {
                 Err(_) => {
                     // If a package that was supposed to be built was not built:
-                    // Just print an error message but continue with the packages
-                    // that were built.
+                    // Just print a message but continue with the packages that
+                    // were built.
                     // Background: If in the makepkg options the option "debug"
-                    // is set, the package list might contain a package of name
-                    // "...-debug" which might not be built in some cases causing
-                    // this error.
-                    error!(
-                        "Package \"{}\" was not built and thus not added to the repository",
-                        pkg_file.as_path().display()
-                    );
+                    // is set, the package list contains a package of name
+                    // "<PKGBASE>-debug". makepkg does not build it if there are
+                    // no debug symbols (e.g., for architecture independent
+                    // packages). Thus, a missing debug package is only reported
+                    // as warning if it was expected, and not at all otherwise
+                    if is_debug_pkg_file(pkgbuild, &pkg_file).with_context(|| err_msg.clone())? {
+                        if pkgbuild
+                            .is_debug_pkg_expected()
+                            .with_context(|| err_msg.clone())?
+                        {
+                            warning!(
+                                "Debug package \"{}\" was not built (presumably, there are no debug symbols) and thus not added to the repository",
+                                pkg_file.as_path().display()
+                            );
+                        }
+                    } else {
+                        error!(
+                            "Package \"{}\" was not built and thus not added to the repository",
+                            pkg_file.as_path().display()
+                        );
+                    }
                     continue;
                 }
                 Ok(mut pkg) => {
@@ -183,13 +274,24 @@ impl Pkg {
 
                     // Sign package file if required
                     if to_be_signed {
-                        if gpg_key.as_ref().is_none() {
+                        let Some(gpg_key) = gpg_key(&pkg.name().with_context(|| err_msg.clone())?)
+                        else {
                             return Err(anyhow!("GPG_KEY is not set").context(err_msg));
-                        }
-                        pkg.sign(gpg_key.as_ref().unwrap())
+                        };
+                        pkg.sign(gpg_key, sig_mode)
                             .with_context(|| err_msg.clone())?;
                     }
 
+                    // Place a copy of the package files in an additional
+                    // directory if requested. Since the package is in the
+                    // repository directory already, a failure does not let the
+                    // build fail
+                    if let Some(also_copy_dir) = &opts.also_copy_dir {
+                        if let Err(err) = pkg.copy_to_dir(also_copy_dir) {
+                            warning!("{:?}", err);
+                        }
+                    }
+
                     pkgs.push(pkg);
                 }
             }
@@ -198,6 +300,71 @@ impl Pkg {
         Ok(pkgs)
     }
 
+    /// Copies package file and its signature files (if there are any) to `dir`
+    fn copy_to_dir<P>(&self, dir: P) -> anyhow::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let err_msg = format!(
+            "Cannot copy package file '{}' to '{}'",
+            self.as_ref().display(),
+            dir.as_ref().display()
+        );
+
+        if !dir.as_ref().is_dir() {
+            return Err(anyhow!("'{}' is not a directory", dir.as_ref().display()))
+                .context(err_msg);
+        }
+
+        for suffix in ["", SIG_SUFFIX, ARMORED_SIG_SUFFIX] {
+            let file = PathBuf::from(self.as_ref().to_string_lossy().to_string() + suffix);
+            if !file.is_file() {
+                continue;
+            }
+            fs::copy(
+                &file,
+                dir.as_ref().join(file.file_name().unwrap_or_else(|| {
+                    panic!("Cannot extract file name from path of package file")
+                })),
+            )
+            .with_context(|| err_msg.clone())?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the part of the file path of the package file that corresponds to
+    /// capture group `group` of `RE_PKG_FILE` (e.g., 2 for the package name)
+    fn file_name_part(&self, group: usize) -> anyhow::Result<String> {
+        let path = self
+            .as_ref()
+            .to_str()
+            .ok_or_else(|| anyhow!("File path cannot be converted into a string"))?;
+        RE_PKG_FILE
+            .captures(path)
+            .ok_or_else(|| anyhow!("'{}' is not a valid package file", path))?
+            .get(group)
+            .map(|part| part.as_str().to_string())
+            .ok_or_else(|| anyhow!("'{}' does not contain the requested part", path))
+    }
+
+    /// Returns the paths of the files that are contained in the package file
+    /// (incl. meta data files such as .PKGINFO)
+    pub fn file_list(&self) -> anyhow::Result<Vec<String>> {
+        Ok(cmd!("bsdtar", "-tf", self.as_ref())
+            .stderr_null()
+            .read()
+            .with_context(|| {
+                format!(
+                    "Cannot list files of package file '{}'",
+                    self.as_ref().display()
+                )
+            })?
+            .lines()
+            .map(str::to_string)
+            .collect())
+    }
+
     /// Creates a Pkg instance from meta data such as package name and version
     /// The different genertic type `S` and `T` are used to supprot different
     /// string type in one call
@@ -223,6 +390,37 @@ impl Pkg {
         )))
     }
 
+    /// Creates Pkg instances from all package files in `local_dir` that contain a
+    /// package with name `name` and architecture `arch`, irrespective of the
+    /// package version
+    pub fn from_meta_data_ignore_version<P, S, T>(
+        name: S,
+        arch: S,
+        local_dir: P,
+        pkg_ext: T,
+    ) -> Vec<Pkg>
+    where
+        P: AsRef<Path>,
+        S: AsRef<str> + Display,
+        T: AsRef<str> + Display,
+    {
+        let pattern = format!(
+            "{}/{}-*-*-{}{}",
+            local_dir.as_ref().display(),
+            name,
+            arch,
+            pkg_ext
+        );
+        glob(&pattern)
+            .unwrap_or_else(|_| panic!("Pattern '{}' is not supported", pattern))
+            .flatten()
+            .filter(|file| file.is_file())
+            .filter_map(|file| Pkg::try_from(file).ok())
+            // The pattern also matches packages whose names start with `name-`
+            .filter(|pkg| pkg.name().is_ok_and(|pkg_name| pkg_name == name.as_ref()))
+            .collect()
+    }
+
     /// Creates a package from a package file stored in the directiory
     /// `file.parent()` and having the same package name, architecture and file
     /// extension as `file`. `file` must be a package file.
@@ -241,6 +439,96 @@ impl Pkg {
             .with_context(|| err_msg)
     }
 
+    /// Imports the (pre-built) package file `file` into the repository directory
+    /// `repo_dir`. Package name, version and architecture are read from the meta
+    /// data of the package (.PKGINFO) and not from the file name. Thus, files
+    /// whose names do not follow the naming convention of package files can be
+    /// imported as well. In `repo_dir`, the file is stored under the name that
+    /// complies with the convention. Files of other versions of the package are
+    /// removed from `repo_dir`. If `sign` is true, the package file is signed
+    /// according to `sig_mode` with the key that `gpg_key` returns for the name
+    /// of the package. Otherwise, existing signature files of `file` are
+    /// imported as well
+    pub fn import<P, G>(
+        file: P,
+        repo_dir: P,
+        sign: bool,
+        gpg_key: G,
+        sig_mode: SigMode,
+    ) -> anyhow::Result<Pkg>
+    where
+        P: AsRef<Path>,
+        G: Fn(&str) -> Option<String>,
+    {
+        let err_msg = format!("Cannot import package file '{}'", file.as_ref().display());
+
+        if !file.as_ref().is_file() {
+            return Err(anyhow!(
+                "Package file '{}' does not exist",
+                file.as_ref().display()
+            ));
+        }
+
+        // Determine the file extension. The longest matching extension is taken
+        // since ".pkg.tar" is a prefix of all other extensions
+        let file_name = file
+            .as_ref()
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .unwrap_or_else(|| panic!("Cannot extract file name from path of package file"));
+        let Some(pkg_ext) = PKG_EXTS
+            .iter()
+            .filter(|ext| file_name.ends_with(*ext))
+            .max_by_key(|ext| ext.len())
+        else {
+            return Err(anyhow!("File has no supported package file extension").context(err_msg));
+        };
+
+        let (name, version, pkg_arch) = pkg_info(&file).with_context(|| err_msg.clone())?;
+        if pkg_arch != Arch::any.to_string() && pkg_arch != arch()?.to_string() {
+            return Err(anyhow!(
+                "Architecture of package ({}) does not fit to the architecture of the repository",
+                pkg_arch
+            )
+            .context(err_msg));
+        }
+
+        // Remove files of other package versions from repository directory
+        for pkg in Pkg::from_meta_data_ignore_version(&name, &pkg_arch, &repo_dir, pkg_ext) {
+            pkg.remove_from_dir(&repo_dir)
+                .with_context(|| err_msg.clone())?;
+        }
+
+        // Copy package file (and signature file, if required) to repository
+        // directory
+        let new_file = repo_dir
+            .as_ref()
+            .join(format!("{}-{}-{}{}", name, version, pkg_arch, pkg_ext));
+        fs::copy(&file, &new_file).with_context(|| err_msg.clone())?;
+        let pkg = Pkg::try_from(new_file).with_context(|| err_msg.clone())?;
+        if sign {
+            let Some(gpg_key) = gpg_key(&name) else {
+                return Err(anyhow!("GPG_KEY is not set").context(err_msg));
+            };
+            pkg.sign(gpg_key, sig_mode)
+                .with_context(|| err_msg.clone())?;
+        } else {
+            for suffix in [SIG_SUFFIX, ARMORED_SIG_SUFFIX] {
+                let sig_file =
+                    PathBuf::from(file.as_ref().as_os_str().to_string_lossy().to_string() + suffix);
+                if sig_file.is_file() {
+                    fs::copy(
+                        sig_file,
+                        pkg.as_ref().to_string_lossy().to_string() + suffix,
+                    )
+                    .with_context(|| err_msg.clone())?;
+                }
+            }
+        }
+
+        Ok(pkg)
+    }
+
     /// Returns `true` if package file is signed, `false` otherwise
     pub fn is_signed(&self) -> bool {
         let sig_file_name = self
@@ -254,14 +542,24 @@ impl Pkg {
         Path::new(&sig_file_name).exists()
     }
 
+    /// Returns the entries of the package meta data (.PKGINFO) in the format
+    /// "<KEY> = <VALUE>". Comments are skipped
+    pub fn meta_data(&self) -> anyhow::Result<Vec<String>> {
+        Ok(pkginfo_content(self.as_ref())?
+            .lines()
+            .filter(|line| !line.starts_with('#') && !line.trim().is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
     /// Moves package file to `dir`
     fn move_to_dir<P>(&mut self, dir: P) -> anyhow::Result<()>
     where
         P: AsRef<Path>,
     {
         let err_msg = format!(
-            "Cannot move package file of '{}' to '{}'",
-            self.name(),
+            "Cannot move package file '{}' to '{}'",
+            self.as_ref().display(),
             dir.as_ref().display()
         );
 
@@ -289,38 +587,29 @@ impl Pkg {
     }
 
     /// Returns the name of the package that is stored in the package file
-    pub fn name(&self) -> String {
-        let captures = RE_PKG_FILE
-            .captures(self.as_ref().to_str()
-		      .unwrap_or_else(|| panic!("Cannot extract package name from file since file path cannot be converted into a string")))
-            .unwrap_or_else(|| panic!("Cannot extract package name from file since file is not a valid package file"));
-        captures
-            .get(2)
-            .unwrap_or_else(|| panic!("Cannot extract package name from file"))
-            .as_str()
-            .to_string()
+    pub fn name(&self) -> anyhow::Result<String> {
+        self.file_name_part(2).with_context(|| {
+            format!(
+                "Cannot extract package name from '{}'",
+                self.as_ref().display()
+            )
+        })
     }
 
     /// Returns the version of the package that is stored in the package file.
     /// The result is a concatenation of the package version and the package
     // release as maintained in the PKGBUILD file
-    pub fn version(&self) -> String {
-        let captures = RE_PKG_FILE
-            .captures(self.as_ref().to_str()
-		      .unwrap_or_else(|| panic!("Cannot extract package version from file since file path cannot be converted into a string")))
-            .unwrap_or_else(|| panic!("Cannot extract package version from file since file is not a valid package file"));
+    pub fn version(&self) -> anyhow::Result<String> {
+        let err_msg = format!(
+            "Cannot extract package version from '{}'",
+            self.as_ref().display()
+        );
 
-        format!(
+        Ok(format!(
             "{}-{}",
-            captures
-                .get(3)
-                .unwrap_or_else(|| panic!("Cannot extract package version from file"))
-                .as_str(),
-            captures
-                .get(4)
-                .unwrap_or_else(|| panic!("Cannot extract package release from file"))
-                .as_str()
-        )
+            self.file_name_part(3).with_context(|| err_msg.clone())?,
+            self.file_name_part(4).with_context(|| err_msg)?
+        ))
     }
 
     /// Removes all files belonging to package stored in package file from `dir`.
@@ -331,10 +620,11 @@ impl Pkg {
         P: AsRef<Path>,
     {
         let err_msg = format!(
-            "Cannot remove package files of {} from '{}'",
-            self.name(),
+            "Cannot remove package files of '{}' from '{}'",
+            self.as_ref().display(),
             dir.as_ref().display()
         );
+        let name = self.name().with_context(|| err_msg.clone())?;
 
         // Make sure dir exists and is a directory
         if !dir.as_ref().exists() {
@@ -348,10 +638,13 @@ impl Pkg {
         }
 
         // Regular expression to check if a path represents a package file or a
-        // signature file of a package file of self
+        // (binary or ASCII-armored) signature file of a package file of self
         let re_pkg_or_sig_file: Regex = Regex::new(&format!(
-            "^(.*/)?{}-([^-]+)-([^-]+)-([^-]+)(\\.pkg\\.tar\\.[^\\.]+)(.sig)?$",
-            self.name()
+            r"^(.*/)?{}-([^-]+)-([^-]+)-([^-]+){}({}|{})?$",
+            regex::escape(&name),
+            *RE_PKG_EXTS,
+            regex::escape(SIG_SUFFIX),
+            regex::escape(ARMORED_SIG_SUFFIX)
         ))
         .with_context(|| err_msg.clone())?;
 
@@ -382,19 +675,101 @@ impl Pkg {
         Ok(())
     }
 
-    /// Signs package file
-    pub fn sign<S>(&self, gpg_key: S) -> anyhow::Result<()>
+    /// Removes the (binary and ASCII-armored) signature files of the package
+    /// file, if there are any
+    pub fn remove_sig_files(&self) -> anyhow::Result<()> {
+        for suffix in [SIG_SUFFIX, ARMORED_SIG_SUFFIX] {
+            let sig_file = PathBuf::from(self.as_ref().to_string_lossy().to_string() + suffix);
+            if sig_file.is_file() {
+                fs::remove_file(&sig_file).with_context(|| {
+                    format!("Cannot remove signature file '{}'", sig_file.display())
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Signs package file. `sig_mode` determines which signature files are
+    /// created. Signing is skipped if all of these files exist already
+    pub fn sign<S>(&self, gpg_key: S, sig_mode: SigMode) -> anyhow::Result<()>
     where
         S: AsRef<str>,
     {
-        if self.is_signed() {
+        if self.is_signed()
+            && (sig_mode == SigMode::Detached
+                || Path::new(&(self.as_ref().to_string_lossy().to_string() + ARMORED_SIG_SUFFIX))
+                    .exists())
+        {
             return Ok(());
         }
 
-        sign_file(self.as_ref(), gpg_key)
+        sign_file(self.as_ref(), gpg_key, sig_mode)
+    }
+
+    /// Returns the sonames of the shared libraries that the package provides.
+    /// These are the entries of the provides array such as "libfoo.so=1-64"
+    pub fn sonames(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self
+            .meta_data()?
+            .iter()
+            .filter_map(|line| line.strip_prefix("provides = "))
+            .filter(|provide| is_soname(provide))
+            .map(str::to_string)
+            .collect())
+    }
+}
+
+/// Reads name, version (incl. release number) and architecture of the package
+/// stored in the package file `file` from its meta data (.PKGINFO)
+fn pkg_info<P>(file: P) -> anyhow::Result<(String, String, String)>
+where
+    P: AsRef<Path>,
+{
+    let err_msg = format!(
+        "Cannot read package meta data from '{}'",
+        file.as_ref().display()
+    );
+
+    let content = pkginfo_content(&file).with_context(|| err_msg.clone())?;
+
+    let (mut name, mut version, mut arch): (Option<&str>, Option<&str>, Option<&str>) =
+        (None, None, None);
+    for line in content.lines() {
+        match line.split_once(" = ") {
+            Some(("pkgname", value)) => name = Some(value),
+            Some(("pkgver", value)) => version = Some(value),
+            Some(("arch", value)) => arch = Some(value),
+            _ => (),
+        }
+    }
+
+    match (name, version, arch) {
+        (Some(name), Some(version), Some(arch)) => {
+            Ok((name.to_string(), version.to_string(), arch.to_string()))
+        }
+        _ => Err(anyhow!("{} is incomplete", PKGINFO_FILE_NAME).context(err_msg)),
     }
 }
 
+/// Reads the content of the package meta data file (.PKGINFO) from the package
+/// file `file`
+fn pkginfo_content<P>(file: P) -> anyhow::Result<String>
+where
+    P: AsRef<Path>,
+{
+    cmd!("bsdtar", "-xOf", file.as_ref(), PKGINFO_FILE_NAME)
+        .stderr_null()
+        .read()
+        .with_context(|| {
+            format!(
+                "Cannot read {} from '{}'",
+                PKGINFO_FILE_NAME,
+                file.as_ref().display()
+            )
+        })
+}
+
 /// Checks if a file exists that matches `pattern`
 fn file_exists_for_pattern(pattern: &str) -> bool {
     glob(pattern)
@@ -432,6 +807,23 @@ fn file_from_pattern(pattern: &str) -> anyhow::Result<PathBuf> {
     }
 }
 
+/// Checks if the package file `file` that was determined for `pkgbuild` belongs
+/// to the debug package that makepkg builds for the package base
+pub fn is_debug_pkg_file<P>(pkgbuild: &PkgBuild, file: P) -> anyhow::Result<bool>
+where
+    P: AsRef<Path>,
+{
+    let Some(captures) = RE_PKG_FILE.captures(file.as_ref().to_str().unwrap()) else {
+        return Ok(false);
+    };
+    let name = captures.get(2).unwrap().as_str();
+    if !name.ends_with(DEBUG_PKG_SUFFIX) {
+        return Ok(false);
+    }
+
+    Ok(name == format!("{}{}", pkgbuild.pkg_base()?, DEBUG_PKG_SUFFIX))
+}
+
 /// Creates a pattern from the file path of `file` where the version part is
 /// replaced by the wildcard `*`. `file` must be a package file.
 fn pattern_ignore_version<P>(file: P, dir: Option<P>) -> anyhow::Result<String>
@@ -452,7 +844,9 @@ where
         .unwrap();
     let dir_str: String = match dir {
         Some(dir) => dir.as_ref().to_str().unwrap().to_string() + "/",
-        None => captures.get(1).unwrap().as_str().to_string(),
+        None => captures
+            .get(1)
+            .map_or(String::new(), |dir| dir.as_str().to_string()),
     };
 
     // Not only pkgver is replaced by * but also pkgrel, since it turned out that
@@ -466,3 +860,75 @@ where
         captures.get(6).unwrap().as_str()
     ))
 }
+
+/// Checks if the entry `provide` of a provides array is a soname of a shared
+/// library (e.g., "libfoo.so=1-64")
+pub fn is_soname(provide: &str) -> bool {
+    provide
+        .split_once('=')
+        .is_some_and(|(name, _)| name.ends_with(".so"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn re_pkg_file_matches_all_extensions() {
+        for ext in PKG_EXTS {
+            let file = format!("/repo/python-foo-bar-1.2.3-4-x86_64{}", ext);
+            let captures = RE_PKG_FILE
+                .captures(&file)
+                .unwrap_or_else(|| panic!("'{}' is not matched", file));
+
+            assert_eq!(&captures[1], "/repo/");
+            assert_eq!(&captures[2], "python-foo-bar");
+            assert_eq!(&captures[3], "1.2.3");
+            assert_eq!(&captures[4], "4");
+            assert_eq!(&captures[5], "x86_64");
+            assert_eq!(&captures[6], ext);
+        }
+    }
+
+    #[test]
+    fn re_pkg_file_rejects_other_files() {
+        for file in [
+            "foo-1.0-1-x86_64.pkg.tar.zst.sig",
+            "foo-1.0-1-x86_64.pkg.tar.foo",
+            "foo-1.0-1-x86_64.tar.zst",
+            "foo-1.0-x86_64.pkg.tar.zst",
+            "repo.db.tar.zst",
+        ] {
+            assert!(!RE_PKG_FILE.is_match(file), "'{}' is matched", file);
+        }
+    }
+
+    #[test]
+    fn pattern_ignore_version_for_all_extensions() {
+        for ext in PKG_EXTS {
+            let file = PathBuf::from(format!("/repo/foo-1.0-1-any{}", ext));
+
+            assert_eq!(
+                pattern_ignore_version(&file, None).unwrap(),
+                format!("/repo/foo-*-*-any{}", ext)
+            );
+            assert_eq!(
+                pattern_ignore_version(&file, Some(&PathBuf::from("/other"))).unwrap(),
+                format!("/other/foo-*-*-any{}", ext)
+            );
+        }
+    }
+
+    #[test]
+    fn pattern_ignore_version_without_dir() {
+        assert_eq!(
+            pattern_ignore_version("foo-1.0-1-x86_64.pkg.tar.lrz", None).unwrap(),
+            "foo-*-*-x86_64.pkg.tar.lrz"
+        );
+    }
+
+    #[test]
+    fn pattern_ignore_version_rejects_other_files() {
+        assert!(pattern_ignore_version("/repo/foo-1.0-1-x86_64.pkg.tar.zst.sig", None).is_err());
+    }
+}
@@ -2,20 +2,130 @@
 //
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use crate::internal::aur::AurData;
+use crate::internal::{aur::AurData, common::*, git};
 use anyhow::{anyhow, Context};
 use arch_msgs::*;
 use duct::cmd;
+use lazy_static::lazy_static;
+use regex::Regex;
 use std::{
     cmp::Eq,
-    ffi::OsStr,
+    collections::VecDeque,
+    env,
+    ffi::{OsStr, OsString},
     fmt::Display,
+    fs,
     hash::Hash,
     io::{prelude::*, BufReader},
     path::{Path, PathBuf},
+    str::from_utf8,
 };
 
-const PKGBUILD_FILE_NAME: &str = "PKGBUILD";
+pub const PKGBUILD_FILE_NAME: &str = "PKGBUILD";
+const SRCINFO_FILE_NAME: &str = ".SRCINFO";
+
+/// Names of the checksum arrays that makepkg supports (see PKGBUILD(5))
+const CHECKSUM_KEYS: [&str; 8] = [
+    "b2sums",
+    "cksums",
+    "md5sums",
+    "sha1sums",
+    "sha224sums",
+    "sha256sums",
+    "sha384sums",
+    "sha512sums",
+];
+/// Protocols of sources from version control systems that makepkg supports
+/// (see PKGBUILD(5)). The checksums of such sources are usually skipped
+const VCS_PROTOCOLS: [&str; 5] = ["bzr", "fossil", "git", "hg", "svn"];
+const GIT_SUB_PATH: &str = "git";
+const LOCAL_SUB_PATH: &str = "local";
+
+/// Line that delimits the end of the build output that is printed again if a
+/// build fails
+const BUILD_LOG_DELIMITER: &str =
+    "--------------------------------------------------------------------------------";
+
+/// Editor that is used if neither EDITOR nor VISUAL is set
+const DEFAULT_EDITOR: &str = "vi";
+
+// Regular expression to identify errors and warnings in the output of namcap.
+// Such lines have the form "NAME E: ..." or "NAME W: ...". For the PKGBUILD
+// file, the form is "PKGBUILD (NAME) E: ..." or "PKGBUILD (NAME) W: ..."
+lazy_static! {
+    static ref RE_NAMCAP_ISSUE: Regex = Regex::new(r"^\S+( \(\S+\))? [EW]: ").unwrap();
+}
+
+/// Options that control how packages are built from PKGBUILD files
+#[derive(Clone, Default)]
+pub struct BuildOpts {
+    /// Build with makepkg instead of makechrootpkg
+    pub no_chroot: bool,
+    /// Ignore field arch in PKGBUILD
+    pub ignore_arch: bool,
+    /// Check PKGBUILD and built packages with namcap (only possible for builds
+    /// in a chroot container)
+    pub namcap: bool,
+    /// Let the build fail if namcap reports errors or warnings
+    pub namcap_strict: bool,
+    /// Do not verify PGP signatures of source files
+    pub skip_pgp_check: bool,
+    /// Refuse to build PKGBUILD files that do not have checksums for all
+    /// sources or that skip checksums of sources other than VCS sources
+    pub require_checksums: bool,
+    /// Install the built packages on the local system after they were added to
+    /// the repository
+    pub install_after: bool,
+    /// Compression level for package files (only possible for builds with
+    /// makepkg)
+    pub compress_level: Option<u32>,
+    /// Number of threads used to compress package files (only possible for
+    /// builds with makepkg)
+    pub compress_threads: Option<u32>,
+    /// Number of lines of the build output that are printed again if a build
+    /// fails
+    pub max_log_lines: usize,
+    /// Build in a directory that is backed by a tmpfs file system
+    pub tmpfs: bool,
+    /// Clone AUR package repositories from scratch instead of updating cached
+    /// clones
+    pub refresh_clones: bool,
+    /// Additional directory where copies of the built package files are placed
+    pub also_copy_dir: Option<PathBuf>,
+    /// Architectures for which no packages are built. PKGBUILD files whose
+    /// packages would be built for one of these architectures are skipped
+    pub exclude_archs: Vec<Arch>,
+    /// Use the chroot container as it is, i.e. without updating it before the
+    /// builds
+    pub skip_chroot_update: bool,
+    /// Continue with the chroot container as it is if its update fails,
+    /// instead of aborting
+    pub tolerate_chroot_update_failure: bool,
+    /// Keep the copy of the chroot container that was used for a failed build
+    /// (under a separate name) to be able to inspect it
+    pub keep_chroot_on_failure: bool,
+    /// Do not update the checked out sources of VCS packages (i.e., call makepkg
+    /// with --holdver)
+    pub hold_version: bool,
+    /// Only add packages that are not contained in the repository yet
+    pub only_new: bool,
+    /// Only rebuild packages with git sources if these have new commits since
+    /// the last build
+    pub since_commit: bool,
+    /// Do not extract the source files but use the existing sources (i.e., call
+    /// makepkg with --noextract)
+    pub no_extract: bool,
+    /// Do not run the prepare() function of the PKGBUILD file (i.e., call
+    /// makepkg with --noprepare)
+    pub no_prepare: bool,
+    /// Only download and extract the source files and run prepare(), but do not
+    /// build packages (i.e., call makepkg with --nobuild)
+    pub no_build: bool,
+    /// Timestamp (seconds since the epoch) that makepkg uses as build date and
+    /// as modification time of the packaged files instead of the current time
+    /// (i.e., SOURCE_DATE_EPOCH). This is required for reproducible builds
+    pub source_date_epoch: Option<i64>,
+}
 
 /// PKGBUILD file
 #[derive(Default)]
@@ -63,59 +173,168 @@ impl PkgBuild {
             .unwrap_or_else(|| panic!("Cannot determine parent directory of PKGBUILD file"))
     }
 
+    /// Opens the PKGBUILD file in the editor set in the environment variable
+    /// EDITOR (or VISUAL). If none of these variables is set, vi is used. The
+    /// function returns after the editor was closed
+    pub fn edit(&self) -> anyhow::Result<()> {
+        let err_msg = format!("Cannot edit PKGBUILD file '{}'", self.as_ref().display());
+
+        let editor = env::var("EDITOR")
+            .or_else(|_| env::var("VISUAL"))
+            .unwrap_or_else(|_| DEFAULT_EDITOR.to_string());
+
+        // The editor variable can contain arguments (e.g. "code --wait")
+        let mut args: Vec<OsString> = editor.split_whitespace().map(OsString::from).collect();
+        if args.is_empty() {
+            return Err(anyhow!("Editor is not set").context(err_msg));
+        }
+        let editor = args.remove(0);
+        args.push(self.as_ref().as_os_str().to_os_string());
+
+        let status = cmd(editor, &args)
+            .unchecked()
+            .run()
+            .with_context(|| err_msg.clone())?
+            .status;
+        if !status.success() {
+            return Err(anyhow!("Editor exited with {}", status).context(err_msg));
+        }
+
+        Ok(())
+    }
+
     /// Creates PKGBUILD file instances from package repositories which are
     /// cloned from AUR. If `pkg_names` is Some(...) only packages are considered
     /// whose names are contained in `Some(pkg_names)`. Otherwise, all package
     /// repositories are considered where package information has been retrieved
-    /// from AUR before
+    /// from AUR before. If `refresh_clones` is true, cached clones of the package
+    /// repositories are not updated but replaced by fresh clones
     pub fn from_aur<P, S>(
         aur_data: &AurData,
         pkg_names: Option<&[S]>,
         pkgbuild_dir: P,
+        refresh_clones: bool,
     ) -> anyhow::Result<Vec<PkgBuild>>
     where
         P: AsRef<Path>,
         S: AsRef<str> + Display + Eq + Hash,
     {
         let mut pkgbuilds: Vec<PkgBuild> = vec![];
-        for pkg_repo_dir in aur_data.clone_pkg_repos(pkg_names, pkgbuild_dir) {
+        for pkg_repo_dir in aur_data.clone_pkg_repos(pkg_names, pkgbuild_dir, refresh_clones) {
             pkgbuilds.push(PkgBuild::try_from(pkg_repo_dir.join(PKGBUILD_FILE_NAME))?);
         }
 
         Ok(pkgbuilds)
     }
 
-    /// Create PKGBUILD file instances from directory paths
-    pub fn from_dirs<P>(dirs: &[P]) -> anyhow::Result<Vec<PkgBuild>>
+    /// Create PKGBUILD file instances from paths. A path can either be a directory
+    /// that contains a PKGBUILD file, or a build script file. If the name of such
+    /// a file differs from "PKGBUILD", the directory of the file is copied to
+    /// `pkgbuild_dir` and the file is renamed to "PKGBUILD" in the copy, since
+    /// makepkg and makechrootpkg expect build scripts with that name
+    pub fn from_dirs<P, Q>(paths: &[P], pkgbuild_dir: Q) -> anyhow::Result<Vec<PkgBuild>>
     where
         P: AsRef<Path>,
+        Q: AsRef<Path>,
     {
         let mut pkgbuilds: Vec<PkgBuild> = vec![];
 
-        for dir in dirs {
-            // dir must exist, be a directory and contain a PKGBUILD file
-            if !dir.as_ref().exists() {
-                error!("'{}' does not exist", dir.as_ref().display());
+        for (i, path) in paths.iter().enumerate() {
+            let path = path.as_ref();
+
+            // path must exist and either be a directory that contains a PKGBUILD
+            // file or a file
+            if !path.exists() {
+                error!("'{}' does not exist", path.display());
                 continue;
             }
-            if !dir.as_ref().is_dir() {
-                error!("'{}' is not a directory", dir.as_ref().display());
+            if path.is_dir() {
+                pkgbuilds.push(PkgBuild::try_from(path.join(PKGBUILD_FILE_NAME))?);
+                continue;
+            }
+            if !path.is_file() {
+                error!("'{}' is neither a directory nor a file", path.display());
+                continue;
+            }
+
+            let file_name = path
+                .file_name()
+                .unwrap_or_else(|| panic!("Cannot retrieve file name of build script"))
+                .to_str()
+                .unwrap_or_else(|| panic!("Build script file name is not a proper string"));
+            if file_name == PKGBUILD_FILE_NAME {
+                pkgbuilds.push(PkgBuild::try_from(path.to_path_buf())?);
                 continue;
             }
 
-            pkgbuilds.push(PkgBuild::try_from(dir.as_ref().join(PKGBUILD_FILE_NAME))?);
+            let stage_dir = pkgbuild_dir
+                .as_ref()
+                .join(LOCAL_SUB_PATH)
+                .join(format!("{}-{}", i, file_name));
+            if let Err(err) = stage_build_script(path, &stage_dir) {
+                error!("{:?}", err);
+                continue;
+            }
+            pkgbuilds.push(PkgBuild::try_from(stage_dir.join(PKGBUILD_FILE_NAME))?);
         }
 
         Ok(pkgbuilds)
     }
 
-    /// Build packages from PKGBUILD file with makechrootpkg
+    /// Creates PKGBUILD file instances from git repositories. The repositories
+    /// are cloned from the URLs contained in `urls` to `pkgbuild_dir`. A URL can
+    /// contain a revision that is checked out after cloning, either as fragment
+    /// (`<URL>#<BRANCH>`) or as query parameter (`<URL>?ref=<REVISION>`). The
+    /// PKGBUILD file is expected in the root directory of the repository
+    pub fn from_git<P, S>(urls: &[S], pkgbuild_dir: P) -> anyhow::Result<Vec<PkgBuild>>
+    where
+        P: AsRef<Path>,
+        S: AsRef<str> + Display,
+    {
+        let mut pkgbuilds: Vec<PkgBuild> = vec![];
+
+        for url in urls {
+            let (repo_url, rev) = git::split_url(url.as_ref());
+            let repo_dir = pkgbuild_dir
+                .as_ref()
+                .join(GIT_SUB_PATH)
+                .join(git::dir_name_from_url(repo_url));
+
+            msg!("Cloning git repository {} ...", repo_url);
+
+            if let Err(err) = git::clone(repo_url, &repo_dir, rev) {
+                error!("{:?}", err);
+                continue;
+            }
+
+            pkgbuilds.push(PkgBuild::try_from(repo_dir.join(PKGBUILD_FILE_NAME))?);
+        }
+
+        Ok(pkgbuilds)
+    }
+
+    /// Returns the architectures the PKGBUILD file declares in its `arch` array
+    pub fn archs(&self) -> anyhow::Result<Vec<Arch>> {
+        Ok(self
+            .srcinfo_values("arch")?
+            .iter()
+            .map(Arch::from)
+            .collect())
+    }
+
+    /// Build packages from PKGBUILD file with makechrootpkg. If namcap checks are
+    /// requested in `opts`, errors and warnings reported by namcap are collected.
+    /// They only let the build fail if `opts.namcap_strict` is set. If
+    /// `build_dir` is `Some(...)`, the build takes place in this directory, which
+    /// must be a sub directory of `chroot_dir`, instead of the default copy of the
+    /// chroot container
     pub fn build_with_makechrootpkg<P>(
         &self,
-        ignore_arch: bool,
+        opts: &BuildOpts,
         repo_dir: P,
         chroot_dir: P,
         pkg_dir: P,
+        build_dir: Option<P>,
     ) -> anyhow::Result<()>
     where
         P: AsRef<Path>,
@@ -132,35 +351,109 @@ impl PkgBuild {
             OsStr::new("-D"),
             repo_dir.as_ref().as_os_str(),
             OsStr::new("-u"),
-            OsStr::new("--"),
-            OsStr::new("-c"),
+        ];
+        if let Some(build_dir) = &build_dir {
+            // Since the build directory exists already, makechrootpkg must be
+            // forced to synchronize it with the root container via -c
+            args.extend([
+                OsStr::new("-c"),
+                OsStr::new("-l"),
+                build_dir.as_ref().file_name().with_context(|| {
+                    format!(
+                        "Cannot extract copy name from '{}'",
+                        build_dir.as_ref().display()
+                    )
+                })?,
+            ]);
+        }
+        if opts.namcap {
+            args.extend([OsStr::new("-n")]);
+        }
+        args.extend([OsStr::new("--")]);
+        if !keeps_build_files(opts) {
+            args.extend([OsStr::new("-c")]);
+        }
+        args.extend([
             OsStr::new("--noconfirm"),
             OsStr::new("--needed"),
             OsStr::new("--syncdeps"),
-        ];
-        if ignore_arch {
+        ]);
+        if opts.ignore_arch {
             args.extend([OsStr::new("--ignorearch")]);
         }
+        if opts.skip_pgp_check {
+            args.extend([OsStr::new("--skippgpcheck")]);
+        }
+        if opts.hold_version {
+            args.extend([OsStr::new("--holdver")]);
+        }
+        args.extend(makepkg_step_args(opts));
 
-        let reader = cmd("makechrootpkg", &args)
+        let mut expr = cmd("makechrootpkg", &args)
             .dir(self.dir())
-            .env("PKGDEST", pkg_dir.as_ref())
+            .env("PKGDEST", pkg_dir.as_ref());
+        if let Some(epoch) = opts.source_date_epoch {
+            expr = expr.env("SOURCE_DATE_EPOCH", epoch.to_string());
+        }
+        let reader = expr
             .stderr_to_stdout()
             .stderr_capture()
             .reader()
             .with_context(|| err_msg.clone())?;
-        for line in BufReader::new(reader).lines() {
-            match line {
-                Ok(text) => println!("{}", text),
-                Err(err) => return Err(anyhow!(err).context(err_msg)),
+        let mut namcap_issues: Vec<String> = vec![];
+        if let Err(err) = print_build_output(reader, opts.max_log_lines, |text| {
+            if opts.namcap && RE_NAMCAP_ISSUE.is_match(text) {
+                namcap_issues.push(text.to_string());
+            }
+        }) {
+            // A copy in a separate build directory (i.e., a tmpfs) cannot be
+            // kept, since it is removed at the end of the run
+            if opts.keep_chroot_on_failure && build_dir.is_none() {
+                match self
+                    .pkg_base()
+                    .and_then(|pkg_base| keep_chroot_copy(chroot_dir.as_ref(), &pkg_base))
+                {
+                    Ok(kept_dir) => msg!(
+                        "Kept copy of chroot container at '{}'. Enter it with 'sudo arch-nspawn {} bash'",
+                        kept_dir.display(),
+                        kept_dir.display()
+                    ),
+                    Err(err) => warning!("{:?}", err),
+                }
+            }
+            return Err(err.context(err_msg));
+        }
+
+        if !namcap_issues.is_empty() {
+            if opts.namcap_strict {
+                return Err(anyhow!(
+                    "namcap reported {} error(s)/warning(s):\n{}",
+                    namcap_issues.len(),
+                    namcap_issues.join("\n")
+                )
+                .context(err_msg));
+            }
+            warning!(
+                "namcap reported {} error(s)/warning(s) for '{}':",
+                namcap_issues.len(),
+                self.as_ref().display()
+            );
+            for issue in &namcap_issues {
+                println!("    {}", issue);
             }
         }
 
         Ok(())
     }
 
-    /// Build packages from PKGBUILD file with makepkg
-    pub fn build_with_makepkg<P>(&self, ignore_arch: bool, pkg_dir: P) -> anyhow::Result<()>
+    /// Build packages from PKGBUILD file with makepkg. If `build_dir` is
+    /// `Some(...)`, the build takes place in this directory
+    pub fn build_with_makepkg<P>(
+        &self,
+        opts: &BuildOpts,
+        pkg_dir: P,
+        build_dir: Option<P>,
+    ) -> anyhow::Result<()>
     where
         P: AsRef<Path>,
     {
@@ -174,30 +467,121 @@ impl PkgBuild {
             OsStr::new("-u"),
             OsStr::new("SHELLOPTS"),
             OsStr::new("makepkg"),
-            OsStr::new("-c"),
+        ];
+        if !keeps_build_files(opts) {
+            args.extend([OsStr::new("-c")]);
+        }
+        args.extend([
             OsStr::new("--noconfirm"),
             OsStr::new("--needed"),
             OsStr::new("--syncdeps"),
-        ];
-        if ignore_arch {
+        ]);
+        if opts.ignore_arch {
             args.extend([OsStr::new("--ignorearch")]);
         }
+        if opts.skip_pgp_check {
+            args.extend([OsStr::new("--skippgpcheck")]);
+        }
+        if opts.hold_version {
+            args.extend([OsStr::new("--holdver")]);
+        }
+        args.extend(makepkg_step_args(opts));
 
-        let reader = cmd("env", &args)
+        let mut expr = cmd("env", &args)
             .dir(self.dir())
-            .env("PKGDEST", pkg_dir.as_ref())
+            .env("PKGDEST", pkg_dir.as_ref());
+        if let Some(build_dir) = &build_dir {
+            expr = expr.env("BUILDDIR", build_dir.as_ref());
+        }
+        if let Some(epoch) = opts.source_date_epoch {
+            expr = expr.env("SOURCE_DATE_EPOCH", epoch.to_string());
+        }
+
+        // Compression level and threads are passed to the compressors via their
+        // environment variables. These only take effect if the corresponding
+        // compression command in makepkg.conf does not set them explicitly
+        if let Some(level) = opts.compress_level {
+            expr = expr.env("ZSTD_CLEVEL", level.to_string());
+        }
+        if let Some(threads) = opts.compress_threads {
+            expr = expr.env("ZSTD_NBTHREADS", threads.to_string());
+        }
+        let xz_opts: Vec<String> = [
+            opts.compress_level.map(|level| format!("-{}", level)),
+            opts.compress_threads
+                .map(|threads| format!("-T{}", threads)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if !xz_opts.is_empty() {
+            expr = expr.env("XZ_OPT", xz_opts.join(" "));
+        }
+
+        let reader = expr
             .stderr_to_stdout()
             .stderr_capture()
             .reader()
             .with_context(|| err_msg.clone())?;
-        for line in BufReader::new(reader).lines() {
-            match line {
-                Ok(text) => println!("{}", text),
-                Err(err) => return Err(anyhow!(err).context(err_msg)),
+        print_build_output(reader, opts.max_log_lines, |_| ()).with_context(|| err_msg)
+    }
+
+    /// Returns true if makepkg is expected to build a debug package (i.e., a
+    /// package "<PKGBASE>-debug") from the PKGBUILD file, provided the option
+    /// "debug" is set in makepkg.conf. That is not the case if the PKGBUILD
+    /// file disables the options "debug" or "strip"
+    pub fn is_debug_pkg_expected(&self) -> anyhow::Result<bool> {
+        Ok(!self
+            .srcinfo_values("options")?
+            .iter()
+            .any(|option| option == "!debug" || option == "!strip"))
+    }
+
+    /// Returns the sources of the PKGBUILD file that are not protected by a
+    /// checksum, as messages. I.e., sources of source arrays (incl.
+    /// architecture specific ones) without checksum arrays, and sources whose
+    /// checksum is "SKIP". Sources from version control systems are ignored,
+    /// since they cannot have checksums
+    pub fn missing_checksums(&self) -> anyhow::Result<Vec<String>> {
+        let err_msg = format!(
+            "Cannot check checksums of PKGBUILD file '{}'",
+            self.as_ref().display()
+        );
+
+        let srcinfo = self.generate_srcinfo().with_context(|| err_msg.clone())?;
+        let srcinfo = SrcInfo::parse(&srcinfo);
+
+        let mut missing: Vec<String> = vec![];
+
+        for SourceArray {
+            suffix,
+            sources,
+            checksums,
+        } in srcinfo.source_arrays()
+        {
+            if checksums.is_empty() {
+                missing.extend(
+                    sources
+                        .iter()
+                        .filter(|source| !is_vcs_source(source))
+                        .map(|source| format!("source{} '{}' has no checksum", suffix, source)),
+                );
+                continue;
+            }
+            for (key, sums) in checksums {
+                missing.extend(
+                    sources
+                        .iter()
+                        .zip(sums)
+                        .filter(|(source, sum)| *sum == "SKIP" && !is_vcs_source(source))
+                        .map(|(source, _)| {
+                            format!("{}{} skips the checksum of '{}'", key, suffix, source)
+                        }),
+                );
             }
         }
 
-        Ok(())
+        Ok(missing)
     }
 
     /// Returnes list of package files that would be build with a PKGBUILD file
@@ -226,4 +610,433 @@ impl PkgBuild {
 
         Ok(paths)
     }
+
+    /// Returns the package base of the PKGBUILD file
+    pub fn pkg_base(&self) -> anyhow::Result<String> {
+        self.srcinfo_values("pkgbase")?
+            .into_iter()
+            .next()
+            .ok_or_else(|| {
+                anyhow!(
+                    "PKGBUILD file '{}' does not define a package base",
+                    self.as_ref().display()
+                )
+            })
+    }
+
+    /// Returns the names of the packages that are built from the PKGBUILD file
+    pub fn pkg_names(&self) -> anyhow::Result<Vec<String>> {
+        self.srcinfo_values("pkgname")
+    }
+
+    /// Returns the current commits of the git repositories that are sources of
+    /// the PKGBUILD file (i.e., entries of the source array such as
+    /// "git+https://..."), as lines in the format "<URL> <COMMIT>". For sources
+    /// that refer to a branch or a tag (via the fragment "#branch=..." or
+    /// "#tag=..."), the commit of that branch or tag is determined, for sources
+    /// that refer to a commit, the commit itself, and otherwise the commit of
+    /// HEAD. If the PKGBUILD file has no git sources, an empty vector is
+    /// returned
+    pub fn vcs_source_commits(&self) -> anyhow::Result<Vec<String>> {
+        let err_msg = format!(
+            "Cannot determine commits of git sources of PKGBUILD file '{}'",
+            self.as_ref().display()
+        );
+
+        let mut commits: Vec<String> = vec![];
+        for source in self
+            .srcinfo_values("source")
+            .with_context(|| err_msg.clone())?
+        {
+            // Sources have the format "[<NAME>::]git+<URL>[#<FRAGMENT>]"
+            let source = source.split_once("::").map_or(source.as_str(), |(_, s)| s);
+            let Some(url) = source.strip_prefix("git+") else {
+                continue;
+            };
+            let (url, fragment) = url.split_once('#').unwrap_or((url, ""));
+            let url = url.split_once('?').map_or(url, |(url, _)| url);
+
+            let commit = match fragment.split_once('=') {
+                Some(("commit", commit)) => commit.to_string(),
+                Some(("branch" | "tag", rev)) => {
+                    git::remote_commit(url, Some(rev)).with_context(|| err_msg.clone())?
+                }
+                _ => git::remote_commit(url, None).with_context(|| err_msg.clone())?,
+            };
+            commits.push(format!("{} {}", source, commit));
+        }
+
+        Ok(commits)
+    }
+
+    /// Returns the package meta data (.SRCINFO) of the PKGBUILD file. If the
+    /// directory of the PKGBUILD file contains a .SRCINFO file (as the package
+    /// repositories in AUR do), its content is returned. Otherwise, the meta
+    /// data is generated from the PKGBUILD file
+    pub fn srcinfo(&self) -> anyhow::Result<String> {
+        let srcinfo_file = self.dir().join(SRCINFO_FILE_NAME);
+        if srcinfo_file.is_file() {
+            return fs::read_to_string(&srcinfo_file)
+                .with_context(|| format!("Cannot read '{}'", srcinfo_file.display()));
+        }
+        self.generate_srcinfo()
+    }
+
+    /// Generates the package meta data (.SRCINFO) from the PKGBUILD file via
+    /// makepkg
+    fn generate_srcinfo(&self) -> anyhow::Result<String> {
+        let err_msg = format!(
+            "Cannot generate package meta data from PKGBUILD file '{}'",
+            self.as_ref().display()
+        );
+
+        let output = cmd!("makepkg", "--printsrcinfo")
+            .dir(self.dir())
+            .stderr_capture()
+            .unchecked()
+            .run()
+            .with_context(|| err_msg.clone())?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "makepkg: {}",
+                from_utf8(&output.stderr)
+                    .unwrap_or_else(|_| panic!("Cannot retrieve stderr for 'makepkg ...'"))
+            )
+            .context(err_msg));
+        }
+
+        Ok(from_utf8(&output.stdout)
+            .with_context(|| err_msg)?
+            .to_string())
+    }
+
+    /// Returns the values of `key` from the package meta data (.SRCINFO) that is
+    /// generated from the PKGBUILD file
+    fn srcinfo_values(&self, key: &str) -> anyhow::Result<Vec<String>> {
+        let err_msg = format!(
+            "Cannot retrieve '{}' from PKGBUILD file '{}'",
+            key,
+            self.as_ref().display()
+        );
+
+        let prefix = format!("{} = ", key);
+        Ok(self
+            .generate_srcinfo()
+            .with_context(|| err_msg)?
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix(&prefix))
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// Returns the PGP keys that are allowed to sign the source files of the
+    /// PKGBUILD file (i.e., the content of the validpgpkeys array)
+    pub fn valid_pgp_keys(&self) -> anyhow::Result<Vec<String>> {
+        self.srcinfo_values("validpgpkeys")
+    }
+
+    /// Checks the PKGBUILD file without building it and returns the errors and
+    /// the warnings that were found. Checks:
+    ///   - the package meta data (.SRCINFO) can be generated (otherwise an
+    ///     error is returned instead of a result)
+    ///   - pkgname and pkgver are set
+    ///   - the packages can be built for the current architecture
+    ///   - each source array (incl. architecture specific ones) has a checksum
+    ///     array of the same length. A missing checksum array is only a warning
+    pub fn validate(&self) -> anyhow::Result<(Vec<String>, Vec<String>)> {
+        let err_msg = format!(
+            "Cannot validate PKGBUILD file '{}'",
+            self.as_ref().display()
+        );
+
+        let srcinfo = self.generate_srcinfo().with_context(|| err_msg.clone())?;
+        let srcinfo = SrcInfo::parse(&srcinfo);
+
+        let mut errors: Vec<String> = vec![];
+        let mut warnings: Vec<String> = vec![];
+
+        for key in ["pkgname", "pkgver"] {
+            if srcinfo.values(key).iter().all(|value| value.is_empty()) {
+                errors.push(format!("{} is not set", key));
+            }
+        }
+
+        let archs = srcinfo.values("arch");
+        let host_arch = arch().with_context(|| err_msg.clone())?.to_string();
+        if !archs
+            .iter()
+            .any(|arch| *arch == "any" || *arch == host_arch)
+        {
+            errors.push(format!(
+                "arch ({}) does not contain the current architecture {} or 'any'",
+                archs.join(" "),
+                host_arch
+            ));
+        }
+
+        for SourceArray {
+            suffix,
+            sources,
+            checksums,
+        } in srcinfo.source_arrays()
+        {
+            if checksums.is_empty() {
+                warnings.push(format!("source{} has no checksums", suffix));
+            }
+            for (key, sums) in checksums {
+                if sums.len() != sources.len() {
+                    errors.push(format!(
+                        "{}{} has {} entries, but source{} has {}",
+                        key,
+                        suffix,
+                        sums.len(),
+                        suffix,
+                        sources.len()
+                    ));
+                }
+            }
+        }
+
+        Ok((errors, warnings))
+    }
+
+    /// Returns the version of the packages of the PKGBUILD file in the format
+    /// [EPOCH:]PKGVER-PKGREL. For packages that build from version control
+    /// systems, this is the version before the pkgver() function is executed
+    pub fn version(&self) -> anyhow::Result<String> {
+        let value = |key: &str| -> anyhow::Result<Option<String>> {
+            Ok(self.srcinfo_values(key)?.into_iter().next())
+        };
+
+        let (Some(pkgver), Some(pkgrel)) = (value("pkgver")?, value("pkgrel")?) else {
+            return Err(anyhow!(
+                "PKGBUILD file '{}' does not define a complete version",
+                self.as_ref().display()
+            ));
+        };
+        Ok(match value("epoch")? {
+            Some(epoch) => format!("{}:{}-{}", epoch, pkgver, pkgrel),
+            None => format!("{}-{}", pkgver, pkgrel),
+        })
+    }
+}
+
+/// Source array of a PKGBUILD file together with its checksum arrays
+struct SourceArray<'a> {
+    /// Suffix of the array name. Source arrays can be architecture specific
+    /// (e.g., "source_x86_64"). Their checksum arrays have the same suffix
+    suffix: &'a str,
+    sources: Vec<&'a str>,
+    /// Non-empty checksum arrays as pairs of checksum key and checksums
+    checksums: Vec<(&'static str, Vec<&'a str>)>,
+}
+
+/// Entries (pairs of key and value) of the package meta data (.SRCINFO) of a
+/// PKGBUILD file
+struct SrcInfo<'a>(Vec<(&'a str, &'a str)>);
+
+impl<'a> SrcInfo<'a> {
+    /// Parses the package meta data `srcinfo` as generated by makepkg
+    fn parse(srcinfo: &'a str) -> Self {
+        SrcInfo(
+            srcinfo
+                .lines()
+                .filter_map(|line| line.trim().split_once(" = "))
+                .collect(),
+        )
+    }
+
+    /// Returns the source arrays (incl. architecture specific ones) with their
+    /// checksum arrays
+    fn source_arrays(&self) -> Vec<SourceArray<'a>> {
+        let mut suffixes: Vec<&str> = self
+            .0
+            .iter()
+            .filter_map(|(key, _)| key.strip_prefix("source"))
+            .collect();
+        suffixes.sort_unstable();
+        suffixes.dedup();
+
+        suffixes
+            .into_iter()
+            .map(|suffix| SourceArray {
+                suffix,
+                sources: self.values(&format!("source{}", suffix)),
+                checksums: CHECKSUM_KEYS
+                    .iter()
+                    .map(|key| (*key, self.values(&format!("{}{}", key, suffix))))
+                    .filter(|(_, sums)| !sums.is_empty())
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Returns the values of all entries with key `key`
+    fn values(&self, key: &str) -> Vec<&'a str> {
+        self.0
+            .iter()
+            .filter(|(k, _)| *k == key)
+            .map(|(_, value)| *value)
+            .collect()
+    }
+}
+
+/// Copies the directory of the build script `file` to `stage_dir` and renames
+/// the build script to "PKGBUILD" in there
+fn stage_build_script<P, Q>(file: P, stage_dir: Q) -> anyhow::Result<()>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let err_msg = format!(
+        "Cannot prepare build script '{}' for building",
+        file.as_ref().display()
+    );
+
+    let src_dir = file
+        .as_ref()
+        .parent()
+        .unwrap_or_else(|| panic!("Cannot determine parent directory of build script"));
+
+    fs::create_dir_all(stage_dir.as_ref()).with_context(|| err_msg.clone())?;
+    let output = cmd!("cp", "-a", src_dir.join("."), stage_dir.as_ref())
+        .stdout_null()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .with_context(|| err_msg.clone())?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "cp: {}",
+            from_utf8(&output.stderr)
+                .unwrap_or_else(|_| panic!("Cannot retrieve stderr for 'cp ...'"))
+        )
+        .context(err_msg));
+    }
+
+    fs::rename(
+        stage_dir.as_ref().join(
+            file.as_ref()
+                .file_name()
+                .unwrap_or_else(|| panic!("Cannot retrieve file name of build script")),
+        ),
+        stage_dir.as_ref().join(PKGBUILD_FILE_NAME),
+    )
+    .with_context(|| err_msg)?;
+
+    Ok(())
+}
+
+/// Prints the output of a build line by line and passes each line to
+/// `process_line`. If the build fails, the last `max_log_lines` lines of the
+/// output are printed again, since they most likely contain the cause of the
+/// failure
+fn print_build_output<R, F>(
+    reader: R,
+    max_log_lines: usize,
+    mut process_line: F,
+) -> anyhow::Result<()>
+where
+    R: Read,
+    F: FnMut(&str),
+{
+    let mut tail: VecDeque<String> = VecDeque::with_capacity(max_log_lines);
+    for line in BufReader::new(reader).lines() {
+        match line {
+            Ok(text) => {
+                println!("{}", text);
+                process_line(&text);
+                if max_log_lines > 0 {
+                    if tail.len() == max_log_lines {
+                        tail.pop_front();
+                    }
+                    tail.push_back(text);
+                }
+            }
+            Err(err) => {
+                if !tail.is_empty() {
+                    error!(
+                        "Build failed. Last {} line(s) of the build output:",
+                        tail.len()
+                    );
+                    println!("{}", BUILD_LOG_DELIMITER);
+                    for text in &tail {
+                        println!("{}", text);
+                    }
+                    println!("{}", BUILD_LOG_DELIMITER);
+                }
+                return Err(anyhow!(err));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns `true` if makepkg must not clean up the build files after the build
+/// (i.e., must not be called with `-c`). That's the case if the build stops
+/// after the sources were prepared (`--nobuild`), or if it continues with
+/// sources that were extracted before (`--noextract`)
+fn keeps_build_files(opts: &BuildOpts) -> bool {
+    opts.no_build || opts.no_extract
+}
+
+/// Returns the makepkg arguments that skip build steps as requested in `opts`
+fn makepkg_step_args(opts: &BuildOpts) -> Vec<&'static OsStr> {
+    [
+        (opts.no_extract, "--noextract"),
+        (opts.no_prepare, "--noprepare"),
+        (opts.no_build, "--nobuild"),
+    ]
+    .into_iter()
+    .filter(|(is_set, _)| *is_set)
+    .map(|(_, arg)| OsStr::new(arg))
+    .collect()
+}
+
+/// Name of the copy of the chroot container that makechrootpkg uses by default.
+/// Like makechrootpkg, the name is derived from the user who called sudo or
+/// from the current user. For root, it's "copy"
+fn chroot_copy_name() -> String {
+    let user = env::var("SUDO_USER")
+        .or_else(|_| env::var("USER"))
+        .unwrap_or_default();
+    if user.is_empty() || user == "root" {
+        "copy".to_string()
+    } else {
+        user
+    }
+}
+
+/// Keeps the default copy of the chroot container in `chroot_dir` after a
+/// failed build from the PKGBUILD file of `pkg_base`. The copy is renamed to
+/// "<COPY>-failed-<PKG_BASE>", since makechrootpkg would reuse it for the next
+/// build otherwise. A copy that was kept for a previous build of `pkg_base` is
+/// replaced. Returns the path of the kept copy
+fn keep_chroot_copy(chroot_dir: &Path, pkg_base: &str) -> anyhow::Result<PathBuf> {
+    let copy_name = chroot_copy_name();
+    let copy_dir = chroot_dir.join(&copy_name);
+    let kept_dir = chroot_dir.join(format!("{}-failed-{}", copy_name, pkg_base));
+    let err_msg = format!(
+        "Cannot keep copy of chroot container '{}'",
+        copy_dir.display()
+    );
+
+    if kept_dir.exists() {
+        remove_as_root(&kept_dir).with_context(|| err_msg.clone())?;
+    }
+    move_as_root(&copy_dir, &kept_dir).with_context(|| err_msg)?;
+
+    Ok(kept_dir)
+}
+
+/// Returns true if `source` (an entry of a source array in the format
+/// "[<NAME>::]<URL>") is a source from a version control system, such as
+/// "git+https://..." or "git://..."
+fn is_vcs_source(source: &str) -> bool {
+    let url = source.split_once("::").map_or(source, |(_, url)| url);
+    let protocol = url.split_once("://").map_or("", |(scheme, _)| {
+        scheme.split('+').next().unwrap_or_default()
+    });
+    VCS_PROTOCOLS.contains(&protocol)
 }
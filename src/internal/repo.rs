@@ -8,15 +8,16 @@ use crate::internal::{
     aur::AurData,
     cfg,
     common::*,
-    deps::Deps,
-    pkg::Pkg,
-    pkgbuild::PkgBuild,
-    server::{self, Server},
+    deps::{self, DepEdge, Deps},
+    pkg::{is_debug_pkg_file, is_soname, Pkg, PKG_EXTS},
+    pkgbuild::{BuildOpts, PkgBuild, PKGBUILD_FILE_NAME},
+    server::{self, Server, Snapshot},
 };
+use alpm::vercmp;
 use anyhow::{anyhow, Context};
 use arch_msgs::*;
+use chrono::{DateTime, Utc};
 use const_format::concatcp;
-use dialoguer::Confirm;
 use duct::cmd;
 use glob::glob;
 use lazy_static::lazy_static;
@@ -25,33 +26,202 @@ use regex::Regex;
 use scopeguard::defer;
 use std::{
     cmp::Eq,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     env,
-    ffi::OsStr,
-    fmt::Display,
+    error::Error,
+    ffi::{OsStr, OsString},
+    fmt::{self, Display, Formatter},
     fs::{self, File},
     hash::Hash,
     io::{prelude::*, BufReader, BufWriter, Write},
+    os::unix::fs::symlink,
     path::{Path, PathBuf},
     process,
     str::from_utf8,
+    time::{Duration, Instant},
 };
 use url::Url;
 
 /// File suffixes
 const DB_SUFFIX: &str = ".db";
+const FILES_SUFFIX: &str = ".files";
+/// Suffix of the DB archive if a new DB is created. Existing DB archives can
+/// have other suffixes (e.g., ".db.tar.gz"), depending on how they were created
 const DB_ARCHIVE_SUFFIX: &str = concatcp!(DB_SUFFIX, ".tar.xz");
+/// Suffix of backups of the DB archive that repo-add creates
+const DB_BACKUP_SUFFIX: &str = ".old";
 
 /// File and directory names
 const CHROOT_SUB_PATH: &str = "chroots";
 const CHROOT_ROOT_SUB_PATH: &str = "root";
+const CHROOT_LOCK_SUFFIX: &str = ".lock";
+/// Lock file of repman in the chroot directory. The name starts with a dot to
+/// avoid collisions with the lock files of the copies that makechrootpkg names
+/// after users
+const CHROOT_REPMAN_LOCK_FILE_NAME: &str = ".repman.lock";
+
+/// Maximum nesting level of Include directives in pacman.conf files
+const PACMAN_CONF_MAX_INCLUDE_DEPTH: usize = 10;
 const REPOS_SUB_PATH: &str = "repos";
 const PKG_SUB_PATH: &str = "pkg";
 const PKGBUILD_SUB_PATH: &str = "pkgbuild";
+const PKGBUILD_ARCHIVE_SUB_PATH: &str = "pkgbuilds";
+const EXPLICIT_SUB_PATH: &str = "explicit";
+const HISTORY_SUB_PATH: &str = "history";
+const READ_LOCK_INFIX: &str = ".read.";
+const DOWNLOAD_LOCK_SUFFIX: &str = ".download";
+const TMPFS_SUB_PATH: &str = "tmpfs";
+const VCS_SUB_PATH: &str = "vcs";
 const ADJUST_CHROOT_FILE_NAME: &str = "adjustchroot";
+const DB_STAGING_DIR_NAME: &str = ".repman-staging";
+
+/// Official repositories whose sections in the pacman.conf file for the chroot
+/// container get the configured mirror as first server
+const CHROOT_MIRROR_REPOS: [&str; 3] = ["core", "extra", "multilib"];
+
+/// Default signature level of the section of the current repository in the
+/// pacman.conf file for the chroot container
+const CHROOT_SIG_LEVEL_DEFAULT: &str = "Optional TrustAll";
+/// Size of the tmpfs for builds, relative to the physical memory. It is set
+/// explicitly to not depend on the default of the kernel
+const BUILD_TMPFS_SIZE: &str = "75%";
 
 /// Names of optional dependencies
 const PKG_NAME_DISTCC: &str = "distcc";
 
+/// Compression formats of DB archives that are supported. These are identified
+/// by the magic bytes at the beginning of the archive file
+const DB_SUPPORTED_FORMATS: [(&str, &[u8]); 2] = [
+    ("xz", &[0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00]),
+    ("gzip", &[0x1F, 0x8B]),
+];
+/// Compression formats of DB archives that repo-add can produce, but that are
+/// not supported, since the DB parser cannot decompress them. These are
+/// identified by the magic bytes at the beginning of the archive file, or (before
+/// the archive is read) by the suffix of its name
+const DB_UNSUPPORTED_FORMATS: [(&str, &str, &[u8]); 4] = [
+    ("zstd", ".zst", &[0x28, 0xB5, 0x2F, 0xFD]),
+    ("bzip2", ".bz2", &[0x42, 0x5A, 0x68]),
+    ("lz4", ".lz4", &[0x04, 0x22, 0x4D, 0x18]),
+    ("lzip", ".lz", &[0x4C, 0x5A, 0x49, 0x50]),
+];
+
+/// Determines for which packages an update is forced irrespective of their
+/// versions
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ForceUpdate {
+    /// Packages are only updated if a newer version is available
+    None,
+    /// Packages that are not tied to a specific version (i.e., that build from
+    /// version control systems) are updated in any case
+    NoVersion,
+    /// All packages are updated in any case
+    All,
+}
+
+/// Errors that can occur when reading the DB of a repository
+#[derive(Debug)]
+pub enum DbError {
+    /// DB does not exist
+    NotExist(String),
+    /// DB archive is corrupt or truncated
+    Corrupt(PathBuf, String),
+    /// DB archive has a format that is not supported
+    UnsupportedFormat(PathBuf, String),
+}
+impl Display for DbError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            DbError::NotExist(repo_name) => {
+                write!(f, "DB of repository {} does not exist", repo_name)
+            }
+            DbError::Corrupt(file, reason) => write!(
+                f,
+                "DB archive '{}' is corrupt or truncated: {}",
+                file.display(),
+                reason
+            ),
+            DbError::UnsupportedFormat(file, format) => write!(
+                f,
+                "DB archive '{}' has the unsupported format {}",
+                file.display(),
+                format
+            ),
+        }
+    }
+}
+impl Error for DbError {}
+
+/// Overview of a repository: server URL, whether the repository is remote,
+/// whether its DB exists in the local (cache) directory and at the server
+/// location (only for remote repositories), and the number of packages
+pub struct RepoDetails {
+    pub url: String,
+    pub is_remote: bool,
+    pub db_exists_locally: bool,
+    pub db_exists_remotely: Option<bool>,
+    pub pkg_count: Option<usize>,
+}
+
+/// Entry of the list of the packages of a repository
+#[derive(serde::Serialize)]
+struct ListEntry<'a> {
+    name: &'a str,
+    arch: &'a str,
+    version: &'a str,
+    signed: bool,
+    has_dependents: bool,
+    explicit: bool,
+}
+
+/// Build duration of the packages of one PKGBUILD file
+#[derive(serde::Serialize)]
+struct BuildTime {
+    pkgbuild: PathBuf,
+    success: bool,
+    duration_secs: f64,
+}
+
+/// Build durations of all PKGBUILD files processed in one run
+#[derive(serde::Serialize)]
+struct BuildTimes {
+    builds: Vec<BuildTime>,
+    total_secs: f64,
+}
+
+/// Record of one change of a repository, stored as one line of the history file
+/// of the repository
+#[derive(serde::Deserialize, serde::Serialize)]
+struct HistoryRecord {
+    timestamp: i64,
+    operation: String,
+    packages: Vec<HistoryPkg>,
+}
+
+/// Package that was changed by an operation on a repository
+#[derive(serde::Deserialize, serde::Serialize)]
+struct HistoryPkg {
+    name: String,
+    version: String,
+    /// Whether the package file is signed. This is only used for the change
+    /// summary and not recorded in the history. It is `None` for removed
+    /// packages
+    #[serde(skip)]
+    signed: Option<bool>,
+}
+
+impl TryFrom<&Pkg> for HistoryPkg {
+    type Error = anyhow::Error;
+
+    fn try_from(pkg: &Pkg) -> Result<Self, Self::Error> {
+        Ok(HistoryPkg {
+            name: pkg.name()?,
+            version: pkg.version()?,
+            signed: Some(pkg.is_signed()),
+        })
+    }
+}
+
 /// Creates lock file for a repository and registers the removal of such file when
 /// leaving the current scope
 macro_rules! lock {
@@ -63,15 +233,60 @@ macro_rules! lock {
     };
 }
 
+/// Creates a read lock for a repository and registers its removal when leaving
+/// the current scope. Other read locks can exist at the same time, but no
+/// (write) lock
+macro_rules! read_lock {
+    ($self:ident) => {
+        $self.read_lock()?;
+        defer! {
+            $self.read_unlock().unwrap_or_else(|_| panic!("Cannot remove read lock of repository {}", &$self.name));
+        }
+    };
+}
+
+/// Creates a lock for downloads of a remote repository and registers its
+/// removal when leaving the current scope. Several processes can hold read
+/// locks for a repository at the same time. The lock makes sure that they do
+/// not download into the local copy of the repository at the same time
+macro_rules! download_lock {
+    ($self:ident) => {
+        let download_locked = $self.is_remote() && $self.lock_download()?;
+        defer! {
+            if download_locked {
+                $self.unlock_download().unwrap_or_else(|_| panic!("Cannot remove download lock of repository {}", &$self.name));
+            }
+        }
+    };
+}
+
+/// Creates a lock for the chroot container of a repository if `$cond` is true,
+/// and registers its removal when leaving the current scope. The lock is
+/// independent of the lock of the repository. Thus, builds of different
+/// processes in the same chroot container are serialized even if the chroot
+/// directory is shared. If the current process holds the lock already, it is
+/// only removed when the outer scope is left
+macro_rules! chroot_lock {
+    ($self:ident, $cond:expr) => {
+        let chroot_locked = $cond && $self.lock_chroot()?;
+        defer! {
+            if chroot_locked {
+                $self.unlock_chroot().unwrap_or_else(|_| panic!("Cannot unlock chroot container of repository {}", &$self.name));
+            }
+        }
+    };
+}
+
 /// Executes a code block on the current repository. I.e., in case it is remote,
 /// the repository data (DB, packages, etc.) is downloaded, the code is executed
 /// on that data, and the changed data is uploaded. In case of a local repository
 /// the code block is executed directly on the repository data with copying it
 macro_rules! exec_on_repo {
     ($self:ident, $code:block) => {
-                $self.download()?;
+                $self.check_upload()?;
+                let snapshot = $self.download_for_change()?;
         $code
-                $self.upload()?;
+                $self.upload(&snapshot)?;
     };
 }
 
@@ -90,14 +305,37 @@ macro_rules! exec_with_tmp_data {
     }
 }
 
-/// Repository. This structure must be instantiated only once
+/// Repository. Data that is expensive to determine (such as the content of the
+/// repository DB) is retrieved only once per instance
 pub struct Repo {
     name: String,
     db_name: String,
     sign_db: bool,
+    aur_url: Option<String>,
+    store_pkgbuilds: bool,
+    retries: u32,
+    transfer_concurrency: Option<u32>,
+    sig_mode: SigMode,
+    trusted_keys: Vec<String>,
+    pkg_keys: BTreeMap<String, String>,
+    tmpfs: bool,
+    cfg_makepkg_conf: Option<PathBuf>,
+    cfg_pacman_conf: Option<PathBuf>,
+    db_key: Option<String>,
+    confirm_deletions: bool,
+    chroot_sig_level: String,
+    chroot_mirror: Option<String>,
+    url: Url,
     server: Box<dyn Server>,
     local_dir: PathBuf,
     chroot_dir: PathBuf,
+    db_pkgs: OnceCell<repodb_parser::Pkgs>,
+    gpg_key: OnceCell<Option<String>>,
+    is_db_signed: OnceCell<bool>,
+    lock_file: OnceCell<PathBuf>,
+    makepkg_conf: OnceCell<PathBuf>,
+    pacman_conf: OnceCell<PathBuf>,
+    pkg_ext: OnceCell<String>,
 }
 
 impl Repo {
@@ -108,14 +346,68 @@ impl Repo {
         S: AsRef<str> + Display + Eq + Hash,
     {
         let cfg_repo = cfg::repo(&name)?;
+        let server = server::new_with_fallbacks(
+            &server_urls(&name, &cfg_repo)?,
+            cfg_repo.retries,
+            cfg_repo.transfer_concurrency,
+        )?;
 
-        let url = Url::parse(cfg_repo.server.as_str())
-            .with_context(|| format!("Server URL of repository {} could not be parsed", &name))?;
+        Repo::with_server(name, cfg_repo, server)
+    }
 
-        let server = server::new(&url)?;
+    /// Creates an Repo instance for the given repositiory name based on the
+    /// repository configuration `cfg_repo`, but with `server` as server instead
+    /// of the one derived from the configured server URLs. This allows to inject
+    /// other implementations of the Server trait (e.g., a fake server that
+    /// records downloads and uploads)
+    pub fn with_server<S>(
+        name: S,
+        cfg_repo: cfg::CfgRepo,
+        server: Box<dyn Server>,
+    ) -> anyhow::Result<Repo>
+    where
+        S: AsRef<str> + Display + Eq + Hash,
+    {
+        let url = server_urls(&name, &cfg_repo)?[0].clone();
 
         let local_dir = if !server.is_remote() {
-            PathBuf::from(&url.path())
+            let local_dir = PathBuf::from(&url.path());
+
+            // To not silently create a new repository in case of a wrong path,
+            // only the last component of the path of a local repository is created
+            if !local_dir.exists() {
+                match local_dir.parent() {
+                    Some(parent) if parent.is_dir() => (),
+                    _ => {
+                        return Err(anyhow!(
+                            "Parent directory of '{}' does not exist. Please check the server URL of repository {}",
+                            local_dir.display(),
+                            &name
+                        ));
+                    }
+                }
+                warning!(
+                    "Directory '{}' of repository {} does not exist. It is created as new, empty repository",
+                    local_dir.display(),
+                    &name
+                );
+            }
+
+            // Repositories that share a directory would change each other's data.
+            // Operations on them are serialized since the locks are keyed on the
+            // directory (see `lock_file`), but the configuration is most likely
+            // wrong
+            let colliding_repo_names = repos_with_local_dir(&name, &local_dir);
+            if !colliding_repo_names.is_empty() {
+                warning!(
+                    "Repository {} uses the same directory '{}' as repository(ies) {}. Please check the server URLs",
+                    &name,
+                    local_dir.display(),
+                    colliding_repo_names.join(", ")
+                );
+            }
+
+            local_dir
         } else {
             cache_dir()
                 .with_context(|| {
@@ -139,6 +431,23 @@ impl Repo {
                 name.to_string()
             },
             sign_db: cfg_repo.sign_db,
+            aur_url: cfg_repo.aur_url,
+            store_pkgbuilds: cfg_repo.store_pkgbuilds,
+            retries: cfg_repo.retries,
+            transfer_concurrency: cfg_repo.transfer_concurrency,
+            sig_mode: cfg_repo.sig_mode,
+            trusted_keys: cfg_repo.trusted_keys,
+            pkg_keys: cfg_repo.pkg_keys,
+            tmpfs: cfg_repo.tmpfs,
+            cfg_makepkg_conf: cfg_repo.makepkg_conf,
+            cfg_pacman_conf: cfg_repo.pacman_conf,
+            db_key: cfg_repo.db_key,
+            confirm_deletions: cfg_repo.confirm_deletions,
+            chroot_sig_level: cfg_repo
+                .chroot_sig_level
+                .unwrap_or_else(|| CHROOT_SIG_LEVEL_DEFAULT.to_string()),
+            chroot_mirror: cfg_repo.chroot_mirror,
+            url,
             server,
             local_dir,
             chroot_dir: cache_dir()
@@ -150,103 +459,477 @@ impl Repo {
                 })?
                 .join(CHROOT_SUB_PATH)
                 .join(name.as_ref()),
+            db_pkgs: OnceCell::new(),
+            gpg_key: OnceCell::new(),
+            is_db_signed: OnceCell::new(),
+            lock_file: OnceCell::new(),
+            makepkg_conf: OnceCell::new(),
+            pacman_conf: OnceCell::new(),
+            pkg_ext: OnceCell::new(),
         })
     }
 
+    /// Returns the IDs of the (other) processes that currently hold a read lock
+    /// for the current repository. Read locks of processes that are no longer
+    /// running are stale and are removed
+    fn active_readers(&self) -> anyhow::Result<Vec<u32>> {
+        let err_msg = format!("Cannot determine read locks of repository {}", &self.name);
+
+        let pattern = format!(
+            "{}{}*",
+            glob::Pattern::escape(
+                &self
+                    .lock_file()
+                    .with_context(|| err_msg.clone())?
+                    .to_string_lossy()
+            ),
+            READ_LOCK_INFIX
+        );
+        let mut pids: Vec<u32> = vec![];
+        for read_lock_file in glob(&pattern).with_context(|| err_msg.clone())?.flatten() {
+            match pid_from_file(&read_lock_file).with_context(|| err_msg.clone())? {
+                Some(pid) if pid == process::id() => continue,
+                Some(pid) if is_process_running(pid) => pids.push(pid),
+                _ => fs::remove_file(&read_lock_file).with_context(|| err_msg.clone())?,
+            }
+        }
+
+        Ok(pids)
+    }
+
     /// Adds all packages whose names are contained in `pkg_names` to the current
-    /// repository. If `no_chroot` is true, building the new packages is not done via
-    /// `makepkg`, otherwise via `makechrootpkg`. If `clean_chroot` is true, the
-    /// chroot will be removed after all packages have been built. If `sign` is true,
-    /// the files of the new packages will be signed.
+    /// repository. Besides AUR packages, packages can be built from PKGBUILD files
+    /// stored in the directories `pkgbuild_dirs` or in the git repositories
+    /// `git_urls`, and the (pre-built) package files `pkg_files` can be imported
+    /// (see `import_pkgs()`). Built and imported packages are added to the DB
+    /// together and uploaded once. `opts` controls how packages are built (e.g., if
+    /// `opts.no_chroot` is true, building the new packages is not done via
+    /// `makechrootpkg`, but via `makepkg`, and if `opts.only_new` is true, AUR
+    /// packages that the repository contains already are skipped). If
+    /// `clean_chroot` is true, the chroot will be removed after all packages
+    /// have been built. If `sign` is true, the files of the new packages will be
+    /// signed. Build durations are printed
+    /// in the format `format`. If `edit` is true, PKGBUILD files from AUR are
+    /// opened in an editor, and the user is asked for confirmation before the
+    /// packages are built. If `as_deps` is true, the packages are recorded as
+    /// dependencies instead of explicitly added packages.
+    #[allow(clippy::too_many_arguments)]
     pub fn add<S>(
         &self,
         aur_pkg_names: &[S],
         pkgbuild_dirs: &[PathBuf],
-        no_chroot: bool,
-        ignore_arch: bool,
+        git_urls: &[S],
+        pkg_files: &[PathBuf],
+        opts: &BuildOpts,
         clean_chroot: bool,
         sign: bool,
+        format: Format,
+        edit: bool,
+        as_deps: bool,
     ) -> anyhow::Result<()>
     where
         S: AsRef<str> + Display + Eq + Hash,
     {
         let err_msg = format!("Cannot add packages to repository {}", &self.name);
 
-        if sign && self.gpg_key().is_none() {
+        if sign && self.gpg_key().is_none() && self.pkg_keys.is_empty() {
             return Err(anyhow!(
                 "New packages shall be signed but GPG key is not set"
             ));
         }
 
-        // Initialize AUR information from AUR web interface
-        let aur_data = AurData::new(aur_pkg_names, true).with_context(|| err_msg.clone())?;
-
-        exec_with_tmp_data!({
-            // Create tmp dirs for PKGBUILD scripts and package file
-            let (pkgbuild_dir, pkg_dir) = self
-                .ensure_pkg_tmp_dirs()
-                .with_context(|| err_msg.clone())?;
+        // If the DB is signed, all packages must be signed. Since unsigned
+        // packages would be refused after they were built, this is checked
+        // before anything is built
+        if self.sign_db && !sign {
+            return Err(anyhow!(
+                "Packages of repository {} must be signed since its DB is signed (see SignDB). Use '--sign'",
+                &self.name
+            )
+            .context(err_msg));
+        }
 
-            // Collect paths to PKGBUILD scripts ...
-            let mut pkgbuilds: Vec<PkgBuild> = vec![];
-            // ... from local directories ...
-            for pkgbuild in PkgBuild::from_dirs(pkgbuild_dirs).with_context(|| err_msg.clone())? {
-                pkgbuilds.push(pkgbuild);
+        let mut built_pkgs: Vec<Pkg> = vec![];
+        let mut imported_pkgs: Vec<Pkg> = vec![];
+        let mut pkgs_to_install: Vec<PathBuf> = vec![];
+        lock!(self);
+        exec_on_repo!(self, {
+            // If packages shall be signed, the DB must not contain unsigned
+            // packages already. Otherwise, the packages would be built only to
+            // be refused afterwards
+            if sign || self.sign_db {
+                self.verify_db_pkgs_signed()
+                    .with_context(|| err_msg.clone())?;
             }
-            // ... and by downloading package PKGBUILD files from AUR
-            for pkgbuild in PkgBuild::from_aur(&aur_data, Some(aur_pkg_names), pkgbuild_dir)
+
+            // If requested, only packages that are not contained in the
+            // repository yet are added. This is determined under the same lock
+            // and based on the same download as the addition itself
+            let aur_pkg_names: Vec<String> = if opts.only_new {
+                self.new_pkg_names(aur_pkg_names)
+                    .with_context(|| err_msg.clone())?
+            } else {
+                aur_pkg_names
+                    .iter()
+                    .map(|pkg_name| pkg_name.as_ref().to_string())
+                    .collect()
+            };
+
+            // Initialize AUR information from AUR web interface
+            let aur_data = AurData::new(
+                self.aur_url().with_context(|| err_msg.clone())?,
+                &aur_pkg_names,
+                true,
+            )
+            .with_context(|| err_msg.clone())?;
+
+            exec_with_tmp_data!({
+                // Create tmp dirs for PKGBUILD scripts and package file
+                let (pkgbuild_dir, pkg_dir) = self
+                    .ensure_pkg_tmp_dirs()
+                    .with_context(|| err_msg.clone())?;
+
+                // Collect paths to PKGBUILD scripts ...
+                let mut pkgbuilds: Vec<PkgBuild> = vec![];
+                // ... from local directories and build script files ...
+                for pkgbuild in PkgBuild::from_dirs(pkgbuild_dirs, &pkgbuild_dir)
+                    .with_context(|| err_msg.clone())?
+                {
+                    pkgbuilds.push(pkgbuild);
+                }
+                // ... from git repositories ...
+                for pkgbuild in
+                    PkgBuild::from_git(git_urls, &pkgbuild_dir).with_context(|| err_msg.clone())?
+                {
+                    pkgbuilds.push(pkgbuild);
+                }
+                // ... and by downloading package PKGBUILD files from AUR. If
+                // requested, these are opened in an editor for review before
+                // they are built
+                for pkgbuild in PkgBuild::from_aur(
+                    &aur_data,
+                    Some(&aur_pkg_names),
+                    pkgbuild_dir,
+                    opts.refresh_clones,
+                )
                 .with_context(|| err_msg.clone())?
-            {
-                pkgbuilds.push(pkgbuild);
-            }
+                {
+                    if edit {
+                        pkgbuild.edit().with_context(|| err_msg.clone())?;
+                        if !confirm(
+                            format!("Build packages from '{}'?", pkgbuild.as_ref().display()),
+                            true,
+                        )
+                        .with_context(|| err_msg.clone())?
+                        {
+                            continue;
+                        }
+                    }
+                    pkgbuilds.push(pkgbuild);
+                }
+
+                // If requested, refuse to build PKGBUILD files whose sources are
+                // not all protected by checksums
+                if opts.require_checksums {
+                    check_checksums(&pkgbuilds).with_context(|| err_msg.clone())?;
+                }
+
+                // Nothing was changed. Thus, nothing must be uploaded
+                if pkgbuilds.is_empty() && pkg_files.is_empty() {
+                    return Ok(());
+                }
 
-            if !pkgbuilds.is_empty() {
-                lock!(self);
-                exec_on_repo!(self, {
-                    // Create (empty) repository DB if no DB exists
-                    self.ensure_db().with_context(|| err_msg.clone())?;
+                // Create (empty) repository DB if no DB exists
+                self.ensure_db().with_context(|| err_msg.clone())?;
 
-                    if !no_chroot {
+                if !pkgbuilds.is_empty() {
+                    // The chroot container is locked from its update until all
+                    // packages were built
+                    chroot_lock!(self, !opts.no_chroot);
+                    if !opts.no_chroot {
                         // Create or update chroot container
-                        self.prepare_chroot().with_context(|| err_msg.clone())?;
+                        self.prepare_chroot(opts).with_context(|| err_msg.clone())?;
                     }
 
                     // Build packages
-                    let mut built_pkgs: Vec<Pkg> = vec![];
-                    for pkgbuild in pkgbuilds {
-                        match Pkg::build(
-                            &pkgbuild,
-                            no_chroot,
-                            ignore_arch,
-                            Some(sign),
-                            self.gpg_key(),
-                            &self.local_dir,
-                            &self.chroot_dir,
-                            &pkg_dir,
-                        ) {
-                            Err(err) => {
-                                error!("{:?}", err);
-                                continue;
-                            }
-                            Ok(pkgs) => built_pkgs.extend(pkgs),
-                        }
-                    }
-
-                    // Add the successfully built packages to respository DB
-                    self.add_pkgs_to_db(&built_pkgs)
+                    built_pkgs = self
+                        .build_pkgs(&pkgbuilds, opts, Some(sign), &pkg_dir, format)
                         .with_context(|| err_msg.clone())?;
 
+                    // With --nobuild, nothing was built. Thus, nothing is added
+                    // to the repository and nothing must be uploaded
+                    if opts.no_build {
+                        return Ok(());
+                    }
+
                     if clean_chroot {
                         self.remove_chroot_dir().with_context(|| err_msg.clone())?;
                     }
-                });
-            }
+                }
+
+                // Import pre-built package files
+                imported_pkgs = self.import_pkgs(pkg_files, sign);
+
+                // Determine the packages that depend on changed sonames. Failing
+                // to do so must not discard the built packages
+                let soname_dependents = match self.soname_dependents(&built_pkgs) {
+                    Ok(dependents) => dependents,
+                    Err(err) => {
+                        warning!("{:?}", err);
+                        BTreeSet::new()
+                    }
+                };
+
+                // Add the successfully built and imported packages to
+                // respository DB with one call of repo-add
+                let pkgs: Vec<Pkg> = built_pkgs
+                    .iter()
+                    .chain(imported_pkgs.iter())
+                    .cloned()
+                    .collect();
+
+                // If packages shall be signed, the built and imported packages
+                // must be signed before they are added, and afterwards all
+                // packages of the DB must be signed
+                if sign || self.sign_db {
+                    self.verify_pkgs_signed(&pkgs)
+                        .with_context(|| err_msg.clone())?;
+                }
+                self.add_pkgs_to_db(&pkgs)
+                    .with_context(|| err_msg.clone())?;
+                if sign || self.sign_db {
+                    self.verify_db_pkgs_signed()
+                        .with_context(|| err_msg.clone())?;
+                }
+                report_soname_dependents(&soname_dependents);
+
+                // Record whether the packages were added explicitly or as
+                // dependencies
+                self.set_explicit(
+                    &pkgs
+                        .iter()
+                        .map(Pkg::name)
+                        .collect::<anyhow::Result<Vec<String>>>()
+                        .with_context(|| err_msg.clone())?,
+                    !as_deps,
+                )
+                .with_context(|| err_msg.clone())?;
+
+                // Debug packages are not installed. They must be determined while
+                // the PKGBUILD files still exist
+                if opts.install_after {
+                    pkgs_to_install = built_pkgs
+                        .iter()
+                        .filter(|pkg| {
+                            !pkgbuilds
+                                .iter()
+                                .any(|pkgbuild| is_debug_pkg_file(pkgbuild, pkg).unwrap_or(false))
+                        })
+                        .map(|pkg| pkg.as_ref().to_path_buf())
+                        .collect();
+                }
+            });
         });
 
+        // The history is only recorded after the upload was successful
+        self.record_history(
+            "import",
+            imported_pkgs
+                .iter()
+                .flat_map(HistoryPkg::try_from)
+                .collect(),
+        );
+        self.record_history(
+            "add",
+            built_pkgs.iter().flat_map(HistoryPkg::try_from).collect(),
+        );
+
+        // If requested, install the built packages locally. Since they were added
+        // to the repository and uploaded already, a failed installation only
+        // leads to a warning
+        if !pkgs_to_install.is_empty() {
+            msg!("Installing built packages ...");
+            if let Err(err) = install_pkgs(&pkgs_to_install) {
+                warning!("{:?}", err);
+            }
+        }
+
         Ok(())
     }
 
-    /// Add packages to the DB of the current repository
+    /// Builds packages from the PKGBUILD files `pkgbuilds` and returns the
+    /// packages that were built successfully. Errors are printed, but do not stop
+    /// the processing of the remaining PKGBUILD files. The duration of each build
+    /// and the total duration are printed in the format `format`. If requested
+    /// via `opts` or the repository configuration, the builds take place in a
+    /// tmpfs. For builds in the chroot container, that's not the case if its
+    /// copy shall be kept after failed builds, since the tmpfs is removed at the
+    /// end of the run
+    fn build_pkgs(
+        &self,
+        pkgbuilds: &[PkgBuild],
+        opts: &BuildOpts,
+        sign: Option<bool>,
+        pkg_dir: &Path,
+        format: Format,
+    ) -> anyhow::Result<Vec<Pkg>> {
+        let mut tmpfs = opts.tmpfs || self.tmpfs;
+        if tmpfs && (opts.no_extract || opts.no_build) {
+            return Err(anyhow!(
+                "'--noextract' and '--nobuild' are not possible since repository {} is configured to build in a tmpfs, which is removed after the build",
+                &self.name
+            ));
+        }
+        if tmpfs && !opts.no_chroot && opts.keep_chroot_on_failure {
+            warning!("Packages are not built in a tmpfs since the copy of the chroot container shall be kept if a build fails");
+            tmpfs = false;
+        }
+        let build_dir = if tmpfs {
+            Some(self.mount_build_tmpfs(opts.no_chroot)?)
+        } else {
+            None
+        };
+        defer! {
+            if let Some(build_dir) = &build_dir {
+                if let Err(err) = self.unmount_build_tmpfs(build_dir) {
+                    warning!("{:?}", err);
+                }
+            }
+        }
+
+        if !opts.no_chroot {
+            self.check_chroot_db();
+        }
+        chroot_lock!(self, !opts.no_chroot);
+
+        let mut built_pkgs: Vec<Pkg> = vec![];
+        let mut build_times: Vec<BuildTime> = vec![];
+        let total_start = Instant::now();
+
+        for (i, pkgbuild) in pkgbuilds.iter().enumerate() {
+            // Skip PKGBUILD files that declare an excluded architecture
+            if !opts.exclude_archs.is_empty() {
+                match pkgbuild.archs() {
+                    Err(err) => {
+                        error!("{:?}", err);
+                        continue;
+                    }
+                    Ok(archs) => {
+                        if let Some(arch) =
+                            archs.iter().find(|arch| opts.exclude_archs.contains(arch))
+                        {
+                            msg!(
+                                "Skipping '{}' since architecture {} is excluded",
+                                pkgbuild.as_ref().display(),
+                                arch
+                            );
+                            continue;
+                        }
+                    }
+                }
+            }
+
+            let start = Instant::now();
+            let result = Pkg::build(
+                pkgbuild,
+                opts,
+                sign,
+                |pkg_name| self.pkg_gpg_key(pkg_name).map(str::to_string),
+                self.sig_mode,
+                self.local_dir.as_path(),
+                self.chroot_dir.as_path(),
+                pkg_dir,
+                build_dir.as_deref(),
+                (i + 1, pkgbuilds.len()),
+            );
+            let duration = start.elapsed();
+
+            build_times.push(BuildTime {
+                pkgbuild: pkgbuild.as_ref().to_path_buf(),
+                success: result.is_ok(),
+                duration_secs: duration.as_secs_f64(),
+            });
+            if format == Format::Text {
+                msg!(
+                    "Build from '{}' took {}",
+                    pkgbuild.as_ref().display(),
+                    fmt_duration(duration)
+                );
+
+                // Estimate the remaining time based on the average duration of
+                // the builds so far
+                let remaining = pkgbuilds.len() - (i + 1);
+                if remaining > 0 {
+                    let avg_secs = build_times
+                        .iter()
+                        .map(|build_time| build_time.duration_secs)
+                        .sum::<f64>()
+                        / build_times.len() as f64;
+                    msg!(
+                        "{} PKGBUILD file(s) remaining, estimated remaining time: {}",
+                        remaining,
+                        fmt_duration(Duration::from_secs_f64(avg_secs * remaining as f64))
+                    );
+                }
+            }
+
+            match result {
+                Err(err) => {
+                    error!("{:?}", err);
+                    continue;
+                }
+                Ok(pkgs) => {
+                    // Keep a copy of the PKGBUILD file to be able to show the
+                    // changes of the next version
+                    if self.store_pkgbuilds && !pkgs.is_empty() {
+                        if let Err(err) = pkgs[0]
+                            .version()
+                            .and_then(|version| self.store_pkgbuild(pkgbuild, version))
+                        {
+                            warning!("{:?}", err);
+                        }
+                    }
+                    built_pkgs.extend(pkgs)
+                }
+            }
+        }
+
+        let total = total_start.elapsed();
+        match format {
+            Format::Text => msg!("Total build time: {}", fmt_duration(total)),
+            Format::Json => println!(
+                "{}",
+                serde_json::to_string(&BuildTimes {
+                    builds: build_times,
+                    total_secs: total.as_secs_f64(),
+                })
+                .unwrap_or_else(|_| panic!("Cannot serialize build times"))
+            ),
+            // The total build time is not printed since CSV does not allow
+            // records of different types. It is the sum of the durations
+            Format::Csv => {
+                println!("{}", csv_record(&["pkgbuild", "success", "duration_secs"]));
+                for build_time in build_times {
+                    println!(
+                        "{}",
+                        csv_record(&[
+                            build_time.pkgbuild.to_string_lossy().to_string(),
+                            build_time.success.to_string(),
+                            build_time.duration_secs.to_string(),
+                        ])
+                    );
+                }
+            }
+        }
+
+        Ok(built_pkgs)
+    }
+
+    /// Add packages to the DB of the current repository. The DB is updated
+    /// incrementally, i.e. the existing entries are kept. Since repo-add unpacks
+    /// and re-packs the complete DB archive with each call, all packages are
+    /// added with one call. Thus, callers must collect the packages and must not
+    /// call this function per package
     fn add_pkgs_to_db(&self, pkgs: &[Pkg]) -> anyhow::Result<()> {
         if pkgs.is_empty() {
             return Ok(());
@@ -268,40 +951,41 @@ impl Repo {
             );
         }
 
-        // Assemble arguments for repo-add
-        let repo_file = &self
-            .local_dir
-            .join(self.db_name.clone() + DB_ARCHIVE_SUFFIX);
-        let mut args: Vec<&OsStr> = vec![OsStr::new("--remove"), OsStr::new("--verify")];
-        if self.sign_db {
-            args.extend([
-                OsStr::new("--sign"),
-                OsStr::new("--key"),
-                OsStr::new(
-                    self.gpg_key()
-                        .unwrap_or_else(|| panic!("GPG_KEY is not set")),
-                ),
-            ]);
-        }
-        args.push(repo_file.as_os_str());
-        args.extend(
-            pkgs.iter()
-                .map(|pkg| pkg.as_ref().as_os_str())
-                .collect::<Vec<&OsStr>>(),
-        );
+        // repo-add is executed on a staging copy of the DB. Thus, clients never
+        // see a partially written DB
+        self.update_db_staged(|repo_file| {
+            // Assemble arguments for repo-add
+            let mut args: Vec<&OsStr> = vec![OsStr::new("--remove"), OsStr::new("--verify")];
+            if self.sign_db {
+                args.extend([
+                    OsStr::new("--sign"),
+                    OsStr::new("--key"),
+                    OsStr::new(
+                        self.gpg_key()
+                            .unwrap_or_else(|| panic!("GPG_KEY is not set")),
+                    ),
+                ]);
+            }
+            args.push(repo_file.as_os_str());
+            args.extend(
+                pkgs.iter()
+                    .map(|pkg| pkg.as_ref().as_os_str())
+                    .collect::<Vec<&OsStr>>(),
+            );
 
-        // Execute repo-add ...
-        let output = cmd("repo-add", &args)
-            .stdout_null()
-            .stderr_capture()
-            .unchecked()
-            .run()
-            .with_context(|| err_msg.clone())?;
-        if output.status.success() {
-            Ok(())
-        } else {
-            Err(anyhow!("repo-add: {}", from_utf8(&output.stderr).unwrap()).context(err_msg))
-        }
+            // Execute repo-add ...
+            let output = cmd("repo-add", &args)
+                .stdout_null()
+                .stderr_capture()
+                .unchecked()
+                .run()?;
+            if output.status.success() {
+                Ok(())
+            } else {
+                Err(anyhow!("repo-add: {}", from_utf8(&output.stderr).unwrap()))
+            }
+        })
+        .with_context(|| err_msg)
     }
 
     /// Determines if a script for adjusting the chroot container of the current
@@ -331,15 +1015,101 @@ impl Repo {
         Ok(None)
     }
 
-    /// Returns true if chroot directory for the current rrepository exists,
-    /// otherwise false
+    /// Base URL of the AUR instance that is used for the current repository. The
+    /// URL can be set per repository. Otherwise, the URL from the global
+    /// configuration is used
+    fn aur_url(&self) -> anyhow::Result<String> {
+        match &self.aur_url {
+            Some(aur_url) => Ok(aur_url.clone()),
+            None => Ok(cfg::cfg()
+                .with_context(|| format!("Cannot determine AUR URL for repository {}", &self.name))?
+                .aur_url),
+        }
+    }
+
+    /// Returns the path of the directory where the tmpfs for builds is mounted.
+    /// For builds in the chroot container, it must be located in the chroot
+    /// directory since it is used as copy of the root container by makechrootpkg
+    fn build_tmpfs_dir(&self, no_chroot: bool) -> anyhow::Result<PathBuf> {
+        Ok(if no_chroot {
+            cache_dir()
+                .with_context(|| {
+                    format!(
+                        "Cannot assemble path of tmpfs directory for repository {}",
+                        &self.name
+                    )
+                })?
+                .join(TMPFS_SUB_PATH)
+                .join(&self.name)
+        } else {
+            self.chroot_dir.join(TMPFS_SUB_PATH)
+        })
+    }
+
+    /// Checks whether the chroot container can resolve packages of the current
+    /// repository. The pacman.conf file of the chroot container refers to the
+    /// local repository directory via a file:// server (see
+    /// `pacman_conf_for_chroot()`). Thus, the DB must exist there and must be
+    /// parseable. Otherwise, a warning is displayed, since dependencies that
+    /// are contained in the repository cannot be installed in the chroot
+    /// container. Nothing is changed
+    fn check_chroot_db(&self) {
+        let db_archive_file = self.db_archive_file();
+        let server = format!("file://{}", self.local_dir.display());
+
+        if !db_archive_file.exists() {
+            warning!(
+                "Chroot container cannot resolve packages of repository {} from {}: DB file '{}' does not exist",
+                &self.name,
+                server,
+                db_archive_file.display()
+            );
+            return;
+        }
+        match repodb_parser::parse(db_archive_file.as_path()) {
+            Err(err) => warning!(
+                "Chroot container cannot resolve packages of repository {} from {}: DB file '{}' cannot be parsed: {:?}",
+                &self.name,
+                server,
+                db_archive_file.display(),
+                err
+            ),
+            Ok(db) => msg!(
+                "Chroot container resolves packages of repository {} from {} ({} package(s)). Packages that are built in this run are only added after all builds",
+                &self.name,
+                server,
+                db.packages().count()
+            ),
+        }
+    }
+
+    /// Checks whether the current repository can be uploaded (i.e., whether the
+    /// required tools are installed and credentials are configured). This is done
+    /// before a remote repository is changed, so that a change does not fail only
+    /// after all the work was done
+    fn check_upload(&self) -> anyhow::Result<()> {
+        if !self.is_remote() {
+            return Ok(());
+        }
+
+        self.server
+            .check_upload()
+            .with_context(|| format!("Cannot change repository {}", &self.name))
+    }
+
+    /// Returns true if the chroot container (i.e., its root container) for the
+    /// current repository exists, otherwise false. The existence of the chroot
+    /// directory is not sufficient, since it contains the lock file of the
+    /// chroot container as well
     pub fn chroot_exists(&self) -> bool {
-        self.chroot_dir.exists()
+        self.chroot_dir.join(CHROOT_ROOT_SUB_PATH).exists()
     }
 
     /// Cleans up the current repository. I.e., checks if the repository DB and the
-    /// package files are consistent. Removes obsolete artefacts
-    pub fn clean_up(&self) -> anyhow::Result<()> {
+    /// package files are consistent. Removes obsolete artefacts. If
+    /// `prune_untracked` is true, files that repman does not recognize are
+    /// removed as well after the user confirmed it
+    pub fn clean_up(&self, prune_untracked: bool) -> anyhow::Result<()> {
         lock!(self);
         exec_on_repo!(self, {
             let err_msg = format!("Cannot clean up repository {}", &self.name);
@@ -347,7 +1117,9 @@ impl Repo {
 
             // Check #1: Do all packages contained in the repository DB have a
             // corresponding package file in the repository directory?
-            // -> Remove packages from the DB where that is not the case
+            // -> Remove packages from the DB where that is not the case. If there
+            //    are package files of other versions (i.e., DB and package files
+            //    are out of sync), the version skew is reported
             {
                 let mut to_be_deleted_pkg_names: Vec<&str> = vec![];
                 for db_pkg in db_pkgs.packages() {
@@ -360,10 +1132,28 @@ impl Repo {
                     )
                     .is_err()
                     {
-                        error!(
-                            "Package {} is in repository DB, but package file does not exist",
-                            db_pkg.name
+                        let skewed_pkgs = Pkg::from_meta_data_ignore_version(
+                            &db_pkg.name,
+                            &db_pkg.arch,
+                            &self.local_dir,
+                            self.pkg_ext().with_context(|| err_msg.clone())?,
                         );
+                        if skewed_pkgs.is_empty() {
+                            error!(
+                                "Package {} is in repository DB, but package file does not exist",
+                                db_pkg.name
+                            );
+                        } else {
+                            for pkg in skewed_pkgs {
+                                error!(
+                                    "Version skew for package {}: Repository DB contains version {}, but package file '{}' contains version {}",
+                                    db_pkg.name,
+                                    db_pkg.version,
+                                    pkg.as_ref().display(),
+                                    pkg.version().unwrap_or_else(|_| "?".to_string())
+                                );
+                            }
+                        }
                         to_be_deleted_pkg_names.push(&db_pkg.name);
                     }
                 }
@@ -385,14 +1175,23 @@ impl Repo {
                 {
                     if file.is_file() {
                         if let Ok(pkg) = Pkg::try_from(file.clone()) {
+                            let (pkg_name, pkg_version) =
+                                match pkg.name().and_then(|name| Ok((name, pkg.version()?))) {
+                                    Ok(name_version) => name_version,
+                                    Err(err) => {
+                                        error!("{:?}", err);
+                                        continue;
+                                    }
+                                };
+
                             // Package file must be removed if ...
                             // (a) the repository DB does not contain a package
                             //     of that name, or ...
                             // (b) it contains a package of that name, but this
                             //     has a version which is different from the
                             //     packages stored in the file
-                            if !db_pkgs.contains(&pkg.name())
-                                || (pkg.version() != db_pkgs.get(&pkg.name()).unwrap().version)
+                            if !db_pkgs.contains(&pkg_name)
+                                || (pkg_version != db_pkgs.get(&pkg_name).unwrap().version)
                             {
                                 if let Err(err) = fs::remove_file(&file) {
                                     error!(
@@ -411,32 +1210,41 @@ impl Repo {
                 }
             }
 
-            // Check #3: Do all *.sig files in the repository directory have a
-            // corresponding file in that directory?
-            // -> Remove *.sig files where that is not the case
-            {
-                let pattern = format!("{}/*.sig", &self.local_dir.display());
-                for sig_file in glob(&pattern)
-                    .unwrap_or_else(|_| panic!("Pattern '{}' is not supported", pattern))
-                    .flatten()
-                {
-                    if (sig_file.is_file() || sig_file.is_symlink())
-                        && !sig_file.with_extension("").exists()
-                    {
-                        if let Err(err) = fs::remove_file(&sig_file) {
-                            error!(
-                                "{:?}",
-                                anyhow!(err).context(format!(
-                                    "Cannot remove obsolete signature file '{}'",
-                                    sig_file.display()
-                                ))
-                            );
-                        } else {
-                            msg!("Removed obsolete signature file '{}'", &sig_file.display());
+            // Check #3 (optional): Are all files in the repository directory
+            // either DB files, package files or signature files?
+            // -> Remove files where that is not the case (e.g., left over
+            //    partial downloads)
+            if prune_untracked {
+                let untracked_files = self.untracked_files().with_context(|| err_msg.clone())?;
+                if untracked_files.is_empty() {
+                    msg!("No untracked files found");
+                } else {
+                    msg!("These files are not recognized by repman:");
+                    for file in &untracked_files {
+                        println!("  {}", file.display());
+                    }
+                    if confirm("Remove them?", false).with_context(|| err_msg.clone())? {
+                        for file in &untracked_files {
+                            if let Err(err) = fs::remove_file(file) {
+                                error!(
+                                    "{:?}",
+                                    anyhow!(err).context(format!(
+                                        "Cannot remove untracked file '{}'",
+                                        file.display()
+                                    ))
+                                );
+                            } else {
+                                msg!("Removed untracked file '{}'", file.display());
+                            }
                         }
                     }
                 }
             }
+
+            // Check #4: Do all *.sig and *.asc files in the repository directory
+            // have a corresponding file in that directory?
+            // -> Remove *.sig and *.asc files where that is not the case
+            self.remove_orphaned_sig_files();
         });
 
         Ok(())
@@ -528,6 +1336,44 @@ impl Repo {
         Ok(())
     }
 
+    /// Returns the path of the DB archive of the current repository. Depending on
+    /// how the DB was created, the archive can have different suffixes (e.g.,
+    /// ".db.tar.xz" or ".db.tar.gz"). Thus, the suffix is determined from the
+    /// archive file that exists in the local repository directory. Normally,
+    /// the DB file (i.e., "<DB-NAME>.db") is a symbolic link to the archive.
+    /// If there is no archive, the path for a new DB archive is returned
+    fn db_archive_file(&self) -> PathBuf {
+        if let Ok(target) = fs::read_link(self.local_dir.join(self.db_name.clone() + DB_SUFFIX)) {
+            if let Some(name) = target.file_name() {
+                if self.local_dir.join(name).exists() {
+                    return self.local_dir.join(name);
+                }
+            }
+        }
+
+        let prefix = format!("{}{}.tar.", &self.db_name, DB_SUFFIX);
+        let mut names: Vec<String> = fs::read_dir(&self.local_dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+            .filter(|name| {
+                name.starts_with(&prefix)
+                    && ![SIG_SUFFIX, ARMORED_SIG_SUFFIX, DB_BACKUP_SUFFIX]
+                        .iter()
+                        .any(|suffix| name.ends_with(suffix))
+            })
+            .collect();
+        names.sort();
+
+        match names.first() {
+            Some(name) => self.local_dir.join(name),
+            None => self
+                .local_dir
+                .join(self.db_name.clone() + DB_ARCHIVE_SUFFIX),
+        }
+    }
+
     /// Returns true if the DB of the current repository exists, false otherwise
     fn db_exists(&self) -> bool {
         self.local_dir
@@ -536,24 +1382,140 @@ impl Repo {
     }
 
     /// Retrieves content from the DB of the current repository. This is only done
-    /// once. The result is stored in a static variable
-    fn db_pkgs(&self) -> anyhow::Result<&'static repodb_parser::Pkgs> {
-        static DB_PKGS: OnceCell<repodb_parser::Pkgs> = OnceCell::new();
-        DB_PKGS.get_or_try_init(|| {
-            if !self.db_exists() {
-                return Err(anyhow!("DB of repository {} does not exist", &self.name));
+    /// once per instance
+    fn db_pkgs(&self) -> anyhow::Result<&repodb_parser::Pkgs> {
+        self.db_pkgs.get_or_try_init(|| {
+            let db_file = self.db_archive_file();
+            if !self.db_exists() || !db_file.exists() {
+                return Err(DbError::NotExist(self.name.clone()).into());
             }
 
-            repodb_parser::parse(
-                self.local_dir
-                    .join(self.db_name.clone() + DB_ARCHIVE_SUFFIX)
-                    .as_path(),
-            )
+            // Check the format of the DB archive before parsing it to be able to
+            // distinguish between unsupported formats and corrupt archives
+            let mut magic = [0u8; 6];
+            let len = File::open(&db_file)
+                .and_then(|mut file| file.read(&mut magic))
+                .map_err(|err| DbError::Corrupt(db_file.clone(), err.to_string()))?;
+            if let Some((format, _, _)) = DB_UNSUPPORTED_FORMATS
+                .iter()
+                .find(|(_, _, bytes)| magic[..len].starts_with(bytes))
+            {
+                return Err(DbError::UnsupportedFormat(db_file, format.to_string()).into());
+            }
+            if !DB_SUPPORTED_FORMATS
+                .iter()
+                .any(|(_, bytes)| magic[..len].starts_with(bytes))
+            {
+                return Err(DbError::Corrupt(
+                    db_file,
+                    "archive has an unknown format or is empty".to_string(),
+                )
+                .into());
+            }
+
+            repodb_parser::parse(db_file.as_path())
+                .map_err(|err| DbError::Corrupt(db_file.clone(), format!("{:#}", err)).into())
         })
     }
 
+    /// Prints the dependencies between the packages of the current repository
+    /// (i.e., the intra-repository dependency graph). If `dot` is true, the graph
+    /// is printed in the DOT language of Graphviz. Otherwise, the dependencies are
+    /// printed as list of edges in the format `format`
+    pub fn dep_graph(&self, dot: bool, format: Format) -> anyhow::Result<()> {
+        let err_msg = format!(
+            "Cannot determine dependency graph of repository {}",
+            &self.name
+        );
+
+        read_lock!(self);
+        self.download_db().with_context(|| err_msg.clone())?;
+
+        if !self.db_exists() {
+            return Err(DbError::NotExist(self.name.clone())).context(err_msg);
+        }
+
+        let deps = self.deps().with_context(|| err_msg.clone())?;
+        let mut pkg_names: Vec<&str> = self
+            .db_pkgs()
+            .with_context(|| err_msg.clone())?
+            .packages()
+            .map(|db_pkg| db_pkg.name.as_str())
+            .collect();
+        pkg_names.sort();
+        let edges = deps.edges(&pkg_names.iter().copied().collect::<HashSet<&str>>());
+
+        if dot {
+            print!("{}", deps::to_dot(&self.name, &pkg_names, &edges));
+            return Ok(());
+        }
+
+        match format {
+            Format::Text => {
+                for edge in &edges {
+                    println!("{} -> {}", edge.from, edge.to);
+                }
+            }
+            Format::Csv => {
+                println!("{}", csv_record(&["from", "to"]));
+                for edge in &edges {
+                    println!("{}", csv_record(&[edge.from, edge.to]));
+                }
+            }
+            Format::Json => {
+                #[derive(serde::Serialize)]
+                struct DepGraph<'a> {
+                    packages: &'a [&'a str],
+                    edges: &'a [DepEdge<'a>],
+                }
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&DepGraph {
+                        packages: &pkg_names,
+                        edges: &edges,
+                    })
+                    .with_context(|| err_msg)?
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the packages of the current repository that depend on a package
+    /// with a name contained in `pkg_names`, as pairs of the name of the package
+    /// they depend on and their name. The DB is only read. Thus, a read lock is
+    /// sufficient
+    fn dependents<S>(&self, pkg_names: &[S]) -> anyhow::Result<Vec<(String, String)>>
+    where
+        S: AsRef<str>,
+    {
+        let err_msg = format!(
+            "Cannot determine dependent packages in repository {}",
+            &self.name
+        );
+
+        read_lock!(self);
+        self.download_db().with_context(|| err_msg.clone())?;
+
+        if !self.db_exists() {
+            return Ok(vec![]);
+        }
+
+        let deps = self.deps().with_context(|| err_msg.clone())?;
+        Ok(pkg_names
+            .iter()
+            .filter_map(|pkg_name| deps.get(pkg_name.as_ref()).map(|deps| (pkg_name, deps)))
+            .flat_map(|(pkg_name, dep_pkgs)| {
+                dep_pkgs
+                    .iter()
+                    .map(|dep_pkg| (pkg_name.as_ref().to_string(), dep_pkg.to_string()))
+            })
+            .collect())
+    }
+
     // Retrieves dependencies from DB of the current repository
-    fn deps(&self) -> anyhow::Result<Deps<'static>> {
+    fn deps(&self) -> anyhow::Result<Deps<'_>> {
         Deps::new(self.db_pkgs().with_context(|| {
             format!(
                 "Cannot retrieve dependencies from DB for repository {}",
@@ -562,11 +1524,178 @@ impl Repo {
         })?)
     }
 
+    /// Collects details about the current repository. In case of a remote
+    /// repository, the DB is downloaded to check whether it exists at the server
+    /// location. The repository is not changed
+    pub fn details(&self) -> anyhow::Result<RepoDetails> {
+        let err_msg = format!("Cannot retrieve details of repository {}", &self.name);
+
+        let db_exists_locally = self.db_exists();
+        let db_exists_remotely = if self.is_remote() {
+            read_lock!(self);
+            self.download_db().with_context(|| err_msg.clone())?;
+            Some(self.db_exists())
+        } else {
+            None
+        };
+
+        let pkg_count = if self.db_exists() {
+            Some(
+                self.db_pkgs()
+                    .with_context(|| err_msg.clone())?
+                    .packages()
+                    .count(),
+            )
+        } else {
+            None
+        };
+
+        Ok(RepoDetails {
+            url: self.url.to_string(),
+            is_remote: self.is_remote(),
+            db_exists_locally,
+            db_exists_remotely,
+            pkg_count,
+        })
+    }
+
+    /// Returns `true` if the package `db_pkg` of the DB of the current repository
+    /// is signed. This is derived from the signature that is embedded in the DB or
+    /// from the signature file of the package, but not from the package file.
+    /// Thus, it also works if only the DB was downloaded (see `download_db()`)
+    fn is_db_pkg_signed(&self, db_pkg: &repodb_parser::pkg::Pkg) -> bool {
+        db_pkg.pgp_sig.is_some()
+            || self
+                .local_dir
+                .join(db_pkg.file_name.clone() + SIG_SUFFIX)
+                .exists()
+    }
+
+    /// Returns the names of the package files that are referenced by the DB of
+    /// the current repository but that do not exist in its local directory
+    fn missing_pkg_files(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self
+            .db_pkgs()
+            .with_context(|| {
+                format!(
+                    "Cannot determine missing package files of repository {}",
+                    &self.name
+                )
+            })?
+            .packages()
+            .filter(|db_pkg| !self.local_dir.join(&db_pkg.file_name).exists())
+            .map(|db_pkg| db_pkg.file_name.clone())
+            .collect())
+    }
+
+    /// Returns the path to the lock file for downloads of the repository
+    fn download_lock_file(&self) -> anyhow::Result<PathBuf> {
+        let mut download_lock_file = self.lock_file()?.into_os_string();
+        download_lock_file.push(DOWNLOAD_LOCK_SUFFIX);
+        Ok(PathBuf::from(download_lock_file))
+    }
+
     /// Downloads the files of the current repository to a local directory, if the
     /// repository is remote. If the function is called for a local repository, it
-    /// does not do anything
-    fn download(&self) -> anyhow::Result<()> {
-        self.server.download_repo(&self.local_dir)
+    /// does not do anything. A snapshot of the downloaded files is returned. It is
+    /// used lateron to only upload files that were changed
+    fn download(&self) -> anyhow::Result<Snapshot> {
+        download_lock!(self);
+        self.server.download_repo(&self.local_dir)?;
+
+        if !self.is_remote() {
+            return Ok(Snapshot::default());
+        }
+
+        // A tampered DB must not be used. Thus, if a trusted key is configured,
+        // the signature of the downloaded DB is verified before the DB is parsed
+        self.verify_db()?;
+        Snapshot::new(&self.local_dir).with_context(|| {
+            format!(
+                "Cannot take snapshot of local copy of repository {}",
+                &self.name
+            )
+        })
+    }
+
+    /// Downloads only the DB files and the signature files of the current
+    /// repository to a local directory, if the repository is remote. The package
+    /// files are not downloaded. Thus, this must only be used for operations
+    /// that read the DB but that do not change the repository. For large
+    /// repositories, this is much faster than a complete download
+    fn download_db(&self) -> anyhow::Result<()> {
+        download_lock!(self);
+        self.server.download_db(&self.local_dir, &self.db_name)?;
+
+        // A tampered DB must not be used. Thus, if a trusted key is configured,
+        // the signature of the downloaded DB is verified before the DB is parsed
+        if self.is_remote() {
+            self.verify_db()?;
+        }
+
+        Ok(())
+    }
+
+    /// Downloads the files of the current repository (see `download()`) before
+    /// the repository is changed. Changes are only possible if the download was
+    /// served by the primary server, since a fallback server can be outdated.
+    /// Changes are refused as well if the DB archive has an unsupported format,
+    /// since the DB could not be read after it was changed
+    fn download_for_change(&self) -> anyhow::Result<Snapshot> {
+        let snapshot = self.download()?;
+
+        let db_file = self.db_archive_file();
+        if let Some((format, suffix, _)) = DB_UNSUPPORTED_FORMATS
+            .iter()
+            .find(|(_, suffix, _)| db_file.exists() && db_file.to_string_lossy().ends_with(suffix))
+        {
+            return Err(anyhow!(
+                "Repository {} cannot be changed since its DB archive '{}' is compressed with {}, which is not supported. Recreate the DB with xz or gzip compression (i.e., as '{}{}.tar.xz' or '{}{}.tar.gz' instead of '*{}')",
+                &self.name,
+                db_file.display(),
+                format,
+                &self.db_name,
+                DB_SUFFIX,
+                &self.db_name,
+                DB_SUFFIX,
+                suffix
+            ));
+        }
+
+        if let Some(url) = self.server.fallback_url() {
+            return Err(anyhow!(
+                "Repository {} was downloaded from fallback server {}, which might be outdated. It cannot be changed until the primary server is available again",
+                &self.name,
+                url
+            ));
+        }
+
+        Ok(snapshot)
+    }
+
+    /// Generates the pacman.conf file that is used for builds in the chroot
+    /// container of the current repository and prints its content to stdout.
+    /// Nothing is built. This is meant to help analyzing build problems, e.g., if
+    /// packages of the repository cannot be found as dependencies in the chroot
+    /// container
+    pub fn dump_pacman_conf(&self) -> anyhow::Result<()> {
+        let err_msg = format!(
+            "Cannot dump pacman.conf file for chroot of repository {}",
+            &self.name
+        );
+
+        exec_with_tmp_data!({
+            print!(
+                "{}",
+                fs::read_to_string(
+                    self.pacman_conf_for_chroot()
+                        .with_context(|| err_msg.clone())?
+                )
+                .with_context(|| err_msg.clone())?
+            );
+        });
+
+        Ok(())
     }
 
     /// Create an empty DB for the current repository if no DB exists. A repository
@@ -583,19 +1712,12 @@ impl Repo {
 
         msg!("Creating empty repository DB ...");
 
-        let output = cmd!(
-            "repo-add",
-            "-n",
-            "-R",
-            &self
-                .local_dir
-                .join(self.db_name.clone() + DB_ARCHIVE_SUFFIX)
-        )
-        .stdout_null()
-        .stderr_capture()
-        .unchecked()
-        .run()
-        .with_context(|| err_msg.clone())?;
+        let output = cmd!("repo-add", "-n", "-R", self.db_archive_file())
+            .stdout_null()
+            .stderr_capture()
+            .unchecked()
+            .run()
+            .with_context(|| err_msg.clone())?;
 
         if output.status.success() {
             Ok(())
@@ -664,14 +1786,145 @@ impl Repo {
         }
     }
 
+    /// Retrieves the names of the packages of the current repository that were
+    /// added explicitly (i.e., not as dependencies of other packages)
+    fn explicit_pkg_names(&self) -> anyhow::Result<BTreeSet<String>> {
+        let explicit_pkgs_file = self.explicit_pkgs_file()?;
+        if !explicit_pkgs_file.exists() {
+            return Ok(BTreeSet::new());
+        }
+
+        Ok(fs::read_to_string(&explicit_pkgs_file)
+            .with_context(|| {
+                format!(
+                    "Cannot read explicitly added packages of repository {} from '{}'",
+                    &self.name,
+                    explicit_pkgs_file.display()
+                )
+            })?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect())
+    }
+
+    /// Path of the file that contains the names of the packages of the current
+    /// repository that were added explicitly. Normally, that is
+    /// `~/.cache/repman/explicit/<REPOSITORY>`
+    fn explicit_pkgs_file(&self) -> anyhow::Result<PathBuf> {
+        let err_msg = format!(
+            "Cannot determine file of explicitly added packages for repository {}",
+            &self.name
+        );
+        Ok(ensure_dir(
+            cache_dir()
+                .with_context(|| err_msg.clone())?
+                .join(EXPLICIT_SUB_PATH),
+        )
+        .with_context(|| err_msg)?
+        .join(&self.name))
+    }
+
+    /// Determines the packages of the other configured repositories that depend
+    /// on packages of the current repository with names contained in
+    /// `pkg_names`. The result maps the names of these packages to their
+    /// dependents, which are given as "<REPOSITORY>/<PACKAGE>". Repositories
+    /// that cannot be read are skipped with a warning
+    fn external_dependents<S>(&self, pkg_names: &[S]) -> BTreeMap<String, Vec<String>>
+    where
+        S: AsRef<str>,
+    {
+        let mut external_deps: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+        let repo_names: Vec<String> = match cfg::repos() {
+            Ok(cfg_repos) => cfg_repos.into_keys().collect(),
+            Err(err) => {
+                warning!(
+                    "{:?}",
+                    err.context("Cannot check dependencies of other repositories")
+                );
+                return external_deps;
+            }
+        };
+        for repo_name in repo_names
+            .iter()
+            .filter(|repo_name| **repo_name != self.name)
+        {
+            match Repo::new(repo_name).and_then(|repo| repo.dependents(pkg_names)) {
+                Ok(dependents) => {
+                    for (pkg_name, dependent) in dependents {
+                        external_deps
+                            .entry(pkg_name)
+                            .or_default()
+                            .push(format!("{}/{}", repo_name, dependent));
+                    }
+                }
+                Err(err) => warning!(
+                    "{:?}",
+                    err.context(format!(
+                        "Cannot check dependencies of repository {}",
+                        repo_name
+                    ))
+                ),
+            }
+        }
+
+        external_deps
+    }
+
+    /// Removes leftovers of builds: Copies of the chroot container that
+    /// makechrootpkg created besides the root container (including their lock
+    /// files) and temporary directories of repman processes that are no longer
+    /// running. The root container itself is kept
+    pub fn gc(&self) -> anyhow::Result<()> {
+        let err_msg = format!("Cannot remove build leftovers of repository {}", &self.name);
+
+        lock!(self);
+
+        if self.chroot_exists() {
+            chroot_lock!(self, true);
+            let keep = [
+                OsString::from(CHROOT_ROOT_SUB_PATH),
+                OsString::from(CHROOT_ROOT_SUB_PATH.to_string() + CHROOT_LOCK_SUFFIX),
+                OsString::from(CHROOT_REPMAN_LOCK_FILE_NAME),
+            ];
+            for entry in fs::read_dir(&self.chroot_dir).with_context(|| err_msg.clone())? {
+                let path = entry.with_context(|| err_msg.clone())?.path();
+                if path
+                    .file_name()
+                    .is_some_and(|file_name| keep.iter().any(|name| name == file_name))
+                {
+                    continue;
+                }
+                if is_mount_point(&path).with_context(|| err_msg.clone())? {
+                    unmount(&path).with_context(|| err_msg.clone())?;
+                }
+                remove_as_root(&path).with_context(|| err_msg.clone())?;
+                msg!("Removed '{}'", path.display());
+            }
+        }
+
+        // Remove tmpfs for builds without chroot container that was left over
+        // from an interrupted run
+        let build_dir = self
+            .build_tmpfs_dir(true)
+            .with_context(|| err_msg.clone())?;
+        if build_dir.exists() {
+            self.unmount_build_tmpfs(&build_dir)
+                .with_context(|| err_msg.clone())?;
+            msg!("Removed '{}'", build_dir.display());
+        }
+
+        remove_stale_tmp_dirs().with_context(|| err_msg)
+    }
+
     /// Retrieves the GPG key to be used to sign package files or the repository DB.
     /// First, it is tried to get it from the environment variable GPG_KEY. If that
     /// is not possible, it is tried to extract it from the relevant `makepkg.conf`
-    /// file. The retrievela is only done once. The result is stored in a static
-    /// variable
-    fn gpg_key(&self) -> Option<&'static str> {
-        static GPG_KEY: OnceCell<Option<String>> = OnceCell::new();
-        match GPG_KEY.get_or_init(|| match env::var("GPGKEY") {
+    /// file. The retrievela is only done once per instance
+    fn gpg_key(&self) -> Option<&str> {
+        match self.gpg_key.get_or_init(|| match env::var("GPGKEY") {
             Ok(value) => Some(value),
             _ => {
                 lazy_static! {
@@ -710,16 +1963,103 @@ impl Repo {
         }
     }
 
-    /// Returns true if the repository DB is signed, false otherwise. The
-    /// determination whether the DB is signed or not is only done once. The result
-    /// is stored in a static variable
+    /// Prints the history of the current repository. I.e., the changes that were
+    /// done with repman by adding, importing, updating and removing packages
+    pub fn history(&self) -> anyhow::Result<()> {
+        let err_msg = format!("Cannot show history of repository {}", &self.name);
+
+        let history_file = self.history_file().with_context(|| err_msg.clone())?;
+        if !history_file.exists() {
+            msg!("No history recorded for repository {}", &self.name);
+            return Ok(());
+        }
+
+        for line in
+            BufReader::new(File::open(&history_file).with_context(|| err_msg.clone())?).lines()
+        {
+            let line = line.with_context(|| err_msg.clone())?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: HistoryRecord = serde_json::from_str(&line).with_context(|| {
+                format!(
+                    "History file '{}' contains an invalid record",
+                    history_file.display()
+                )
+            })?;
+            let time = DateTime::from_timestamp(record.timestamp, 0)
+                .map_or("unknown".to_string(), |time| {
+                    time.format("%Y-%m-%d %H:%M UTC").to_string()
+                });
+            for pkg in &record.packages {
+                println!(
+                    "{}  {:<6}  {} {}",
+                    time, record.operation, pkg.name, pkg.version
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the path of the file where the history of the current repository
+    /// is recorded
+    fn history_file(&self) -> anyhow::Result<PathBuf> {
+        let err_msg = format!(
+            "Cannot determine history file for repository {}",
+            &self.name
+        );
+        Ok(ensure_dir(
+            cache_dir()
+                .with_context(|| err_msg.clone())?
+                .join(HISTORY_SUB_PATH),
+        )
+        .with_context(|| err_msg)?
+        .join(&self.name))
+    }
+
+    /// Imports the (pre-built) package files `pkg_files` into the local directory
+    /// of the current repository and returns the imported packages. Package
+    /// name, version and architecture are taken from the meta data of the
+    /// packages and not from the file names. If `sign` is true, the package files
+    /// are signed. If a package file cannot be imported, the error is printed and
+    /// the other files are imported nevertheless. The packages are not added to
+    /// the DB. That's left to the caller (see `add()`)
+    fn import_pkgs(&self, pkg_files: &[PathBuf], sign: bool) -> Vec<Pkg> {
+        let mut pkgs: Vec<Pkg> = vec![];
+        for pkg_file in pkg_files {
+            match Pkg::import(
+                pkg_file.as_path(),
+                &self.local_dir,
+                sign,
+                |pkg_name| self.pkg_gpg_key(pkg_name).map(str::to_string),
+                self.sig_mode,
+            ) {
+                Err(err) => error!("{:?}", err),
+                Ok(pkg) => pkgs.push(pkg),
+            }
+        }
+        pkgs
+    }
+
+    /// Returns true if the repository DB is signed, false otherwise. repo-add
+    /// creates the signature of the DB archive (`<DB>.db.tar.*.sig`) and a
+    /// symbolic link to it (`<DB>.db.sig`), but other tools might only create
+    /// one of both, or ASCII-armored signatures. Thus, the DB is considered
+    /// signed if a binary or ASCII-armored signature exists either for the DB
+    /// link or for the DB archive. The determination whether the DB is signed
+    /// or not is only done once per instance
     fn is_db_signed(&self) -> bool {
-        static IS_DB_SIGNED: OnceCell<bool> = OnceCell::new();
-        *IS_DB_SIGNED.get_or_init(|| {
-            let sig_file_name = self
-                .local_dir
-                .join(self.db_name.clone() + DB_SUFFIX + SIG_SUFFIX);
-            Path::new(&sig_file_name).exists()
+        *self.is_db_signed.get_or_init(|| {
+            let db_link = self.local_dir.join(self.db_name.clone() + DB_SUFFIX);
+            let db_archive = self.db_archive_file();
+            [db_link, db_archive].iter().any(|db_file| {
+                [SIG_SUFFIX, ARMORED_SIG_SUFFIX].iter().any(|suffix| {
+                    let mut sig_file = db_file.clone().into_os_string();
+                    sig_file.push(suffix);
+                    Path::new(&sig_file).exists()
+                })
+            })
         })
     }
 
@@ -728,88 +2068,329 @@ impl Repo {
         self.server.is_remote()
     }
 
-    ///  Prints a list of the packages of a repository incl. some of their meta data
-    pub fn list(&self) -> anyhow::Result<()> {
-        exec_on_repo!(self, {
-            if self.db_exists() {
-                // Retrieve dependencies and packages
-                let deps = self.deps()?;
-                let db_pkgs = self.db_pkgs().with_context(|| {
-                    format!("Cannot list packages of repository {}", &self.name)
-                })?;
+    ///  Prints a list of the packages of a repository incl. some of their meta
+    /// data in the output format `format`. If `depends_on` is `Some(...)`, only
+    /// the packages that depend on that package are listed
+    pub fn list(&self, format: Format, depends_on: Option<&str>) -> anyhow::Result<()> {
+        let err_msg = format!("Cannot list packages of repository {}", &self.name);
+
+        // Listing does not change the repository. Thus, a read lock is
+        // sufficient and the repository data is not uploaded
+        read_lock!(self);
+        self.download_db()?;
+
+        // Collect the data of the packages
+        let mut entries: Vec<ListEntry> = vec![];
+        if self.db_exists() {
+            let deps = self.deps()?;
+            let explicit_pkg_names = self.explicit_pkg_names().with_context(|| err_msg.clone())?;
+            let dependents: Option<Vec<&str>> = depends_on.map(|pkg_name| {
+                deps.get(pkg_name)
+                    .map(|dep_pkgs| dep_pkgs.iter().copied().collect())
+                    .unwrap_or_default()
+            });
+            for db_pkg in self.db_pkgs().with_context(|| err_msg.clone())?.packages() {
+                if let Some(dependents) = &dependents {
+                    if !dependents.contains(&db_pkg.name.as_str()) {
+                        continue;
+                    }
+                }
+                entries.push(ListEntry {
+                    name: &db_pkg.name,
+                    arch: &db_pkg.arch,
+                    version: &db_pkg.version,
+                    signed: self.is_db_pkg_signed(db_pkg),
+                    has_dependents: deps.contains_key(&db_pkg.name),
+                    explicit: explicit_pkg_names.contains(&db_pkg.name),
+                });
+            }
+        }
+
+        match format {
+            Format::Text => {
+                if !self.db_exists() {
+                    return Ok(());
+                }
+                if let Some(pkg_name) = depends_on {
+                    if entries.is_empty() {
+                        msg!(
+                            "Nothing in repository {} depends on {}",
+                            &self.name,
+                            pkg_name
+                        );
+                        return Ok(());
+                    }
+                }
 
                 // Determine max length of all package name and all architecture
                 // strings
-                let (max_name_len, max_arch_len) = db_pkgs
-                    .packages()
-                    .map(|db_pkg| (db_pkg.name.len(), db_pkg.arch.len()))
+                let (max_name_len, max_arch_len) = entries
+                    .iter()
+                    .map(|entry| (entry.name.len(), entry.arch.len()))
                     .fold((0, 0), |(x, y), (max_x, max_y)| {
                         (usize::max(x, max_x), usize::max(y, max_y))
                     });
 
                 println!(
-                    "{}  [{}]",
+                    "{}   [{}]",
                     if self.is_db_signed() { "s" } else { "-" },
                     &self.name
                 );
 
-                for db_pkg in db_pkgs.packages() {
+                for entry in &entries {
                     println!(
-                        "{0}{1} {2: <3$} {4: <5$} {6}",
-                        if self.pkg(&db_pkg.name)?.is_signed() {
-                            "s"
-                        } else {
-                            "-"
-                        },
-                        if deps.contains_key(&db_pkg.name) {
-                            "d"
-                        } else {
-                            "-"
-                        },
-                        db_pkg.arch,
+                        "{0}{1}{2} {3: <4$} {5: <6$} {7}",
+                        if entry.signed { "s" } else { "-" },
+                        if entry.has_dependents { "d" } else { "-" },
+                        if entry.explicit { "e" } else { "-" },
+                        entry.arch,
                         max_arch_len,
-                        db_pkg.name,
+                        entry.name,
                         max_name_len,
-                        db_pkg.version
+                        entry.version
                     );
                 }
             }
-        });
-
-        Ok(())
-    }
-
-    /// Creates a lock (i.e., a file with the current process ID)
-    fn lock(&self) -> anyhow::Result<()> {
+            Format::Json => {
+                #[derive(serde::Serialize)]
+                struct List<'a> {
+                    repo: &'a str,
+                    db_signed: bool,
+                    packages: &'a [ListEntry<'a>],
+                }
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&List {
+                        repo: &self.name,
+                        db_signed: self.is_db_signed(),
+                        packages: &entries,
+                    })
+                    .with_context(|| err_msg)?
+                );
+            }
+            Format::Csv => {
+                println!(
+                    "{}",
+                    csv_record(&["name", "arch", "version", "signed", "has_dependents"])
+                );
+                for entry in &entries {
+                    println!(
+                        "{}",
+                        csv_record(&[
+                            entry.name,
+                            entry.arch,
+                            entry.version,
+                            &entry.signed.to_string(),
+                            &entry.has_dependents.to_string(),
+                        ])
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Determines which of the PKGBUILD files `pkgbuilds` from local directories
+    /// must be built to update packages of the current repository. That is the
+    /// case if the version of a PKGBUILD file is newer than the version of its
+    /// packages in the repository DB, or if an update is forced via `force`. PKGBUILD
+    /// files whose packages are not contained in the repository are skipped. If
+    /// `no_confirm` is false, the user is asked for confirmation
+    fn local_pkgbuilds_to_be_updated(
+        &self,
+        pkgbuilds: Vec<PkgBuild>,
+        force: ForceUpdate,
+        no_confirm: bool,
+    ) -> anyhow::Result<Vec<PkgBuild>> {
+        let err_msg = format!(
+            "Cannot determine to-be-updated PKGBUILD files for repository {}",
+            &self.name
+        );
+
+        let db_pkgs = self.db_pkgs().with_context(|| err_msg.clone())?;
+        let mut upds: Vec<(PkgBuild, String, String)> = vec![];
+        for pkgbuild in pkgbuilds {
+            let new_version = pkgbuild.version().with_context(|| err_msg.clone())?;
+            let Some(old_version) = pkgbuild
+                .pkg_names()
+                .with_context(|| err_msg.clone())?
+                .iter()
+                .find_map(|pkg_name| db_pkgs.get(pkg_name))
+                .map(|db_pkg| db_pkg.version.clone())
+            else {
+                error!(
+                    "Packages of '{}' are not contained in repository {}. Use 'repman add' to add them",
+                    pkgbuild.as_ref().display(),
+                    &self.name
+                );
+                continue;
+            };
+            if force != ForceUpdate::None
+                || vercmp(old_version.as_str(), new_version.as_str()) == core::cmp::Ordering::Less
+            {
+                upds.push((pkgbuild, old_version, new_version));
+            }
+        }
+
+        if upds.is_empty() {
+            msg!("No updates available from local PKGBUILD files");
+            return Ok(vec![]);
+        }
+
+        if !no_confirm {
+            msg!("Updates available from local PKGBUILD files");
+            for (pkgbuild, old_version, new_version) in &upds {
+                println!(
+                    "    {} {} -> {}",
+                    pkgbuild.as_ref().display(),
+                    old_version,
+                    new_version
+                );
+            }
+            if !confirm("Continue?", true).with_context(|| err_msg.clone())? {
+                return Ok(vec![]);
+            }
+            println!();
+        }
+
+        Ok(upds.into_iter().map(|(pkgbuild, _, _)| pkgbuild).collect())
+    }
+
+    /// Creates a (write) lock (i.e., a file with the current process ID). This is
+    /// only possible if no other process holds a lock or a read lock
+    fn lock(&self) -> anyhow::Result<()> {
         let err_msg = format!("Cannot create lock for repository {}", &self.name);
         let lock_file = self.lock_file()?;
 
-        if lock_file.exists() {
-            let pid = pid_from_file(&lock_file).with_context(|| err_msg.clone())?;
-            return if pid != process::id() {
-                Err(anyhow!(
-                    "Lock file '{}' exists: repository {} is locked by process {}",
-                    lock_file.display(),
-                    &self.name,
-                    pid
-                ))
-            } else {
-                Ok(())
-            };
+        // The lock file is created atomically. If it exists already, another
+        // process holds the lock, or the file is corrupt. lock_holder() removes
+        // corrupt lock files, and the creation is tried again in that case
+        while !create_pid_file(&lock_file).with_context(|| err_msg.clone())? {
+            if let Some(pid) = self.lock_holder().with_context(|| err_msg.clone())? {
+                return if pid != process::id() {
+                    Err(anyhow!(
+                        "Lock file '{}' exists: repository {} is locked by process {}",
+                        lock_file.display(),
+                        &self.name,
+                        pid
+                    ))
+                } else {
+                    Ok(())
+                };
+            }
         }
 
-        let mut f = fs::File::create(lock_file).with_context(|| err_msg.clone())?;
-        write!(f, "{}", process::id()).with_context(|| err_msg)?;
+        // Changes are not allowed while the repository is read by other processes
+        let readers = self.active_readers().with_context(|| err_msg.clone())?;
+        if !readers.is_empty() {
+            fs::remove_file(&lock_file).with_context(|| err_msg)?;
+            return Err(anyhow!(
+                "Repository {} is read by process(es) {}",
+                &self.name,
+                readers
+                    .iter()
+                    .map(|pid| pid.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ));
+        }
 
         Ok(())
     }
 
-    /// Returns the path to lock file of the repository
+    /// Creates a lock for the chroot container of the current repository (i.e.,
+    /// a file with the current process ID in the chroot directory). If another
+    /// process holds the lock, it is waited until that lock is removed. Locks of
+    /// processes that are no longer running are removed. Returns true if the
+    /// lock was created, and false if the current process holds it already
+    fn lock_chroot(&self) -> anyhow::Result<bool> {
+        let err_msg = format!(
+            "Cannot create lock for chroot container of repository {}",
+            &self.name
+        );
+        let lock_file = ensure_dir(&self.chroot_dir)
+            .with_context(|| err_msg.clone())?
+            .join(CHROOT_REPMAN_LOCK_FILE_NAME);
+
+        wait_for_lock(&lock_file, |pid| {
+            msg!(
+                "Chroot container of repository {} is used by process {}. Waiting ...",
+                &self.name,
+                pid
+            )
+        })
+        .with_context(|| err_msg)
+    }
+
+    /// Creates the lock for downloads of the current repository. If another
+    /// process holds the lock, it is waited until that lock is removed. Returns
+    /// true if the lock was created, and false if the current process holds it
+    /// already
+    fn lock_download(&self) -> anyhow::Result<bool> {
+        let err_msg = format!("Cannot create download lock for repository {}", &self.name);
+
+        wait_for_lock(
+            self.download_lock_file().with_context(|| err_msg.clone())?,
+            |pid| {
+                msg!(
+                    "Repository {} is downloaded by process {}. Waiting ...",
+                    &self.name,
+                    pid
+                )
+            },
+        )
+        .with_context(|| err_msg)
+    }
+
+    /// Returns the path to lock file of the repository. The lock is keyed on the
+    /// local directory of the repository instead of its name. Thus, operations
+    /// on (misconfigured) repositories that share a directory are serialized.
+    /// The name of the lock file is the canonical path of the local directory
+    /// with "%" and "/" being percent-encoded. The path is determined only once,
+    /// since the local directory can be removed while the repository is locked
     fn lock_file(&self) -> anyhow::Result<PathBuf> {
-        let err_msg = format!("Cannot determine lock file for repository {}", &self.name);
-        Ok(ensure_dir(locks_dir().with_context(|| err_msg.clone())?)
-            .with_context(|| err_msg)?
-            .join(&self.name))
+        self.lock_file
+            .get_or_try_init(|| {
+                let err_msg = format!("Cannot determine lock file for repository {}", &self.name);
+                let local_dir =
+                    fs::canonicalize(&self.local_dir).with_context(|| err_msg.clone())?;
+                Ok(ensure_dir(locks_dir().with_context(|| err_msg.clone())?)
+                    .with_context(|| err_msg)?
+                    .join(
+                        local_dir
+                            .to_string_lossy()
+                            .replace('%', "%25")
+                            .replace('/', "%2F"),
+                    ))
+            })
+            .cloned()
+    }
+
+    /// Returns the ID of the process that holds the lock for the current
+    /// repository, or `None` if the repository is not locked. Lock files are
+    /// created atomically with their content. Thus, a lock file that does not
+    /// contain a valid process ID is corrupt. Since it would block the repository
+    /// permanently, such a lock is considered stale and the lock file is removed
+    fn lock_holder(&self) -> anyhow::Result<Option<u32>> {
+        let err_msg = format!("Cannot determine lock holder of repository {}", &self.name);
+        let lock_file = self.lock_file().with_context(|| err_msg.clone())?;
+
+        if !lock_file.exists() {
+            return Ok(None);
+        }
+
+        let pid = pid_from_file(&lock_file).with_context(|| err_msg.clone())?;
+        if pid.is_none() {
+            warning!(
+                "Lock file '{}' of repository {} is corrupt. It is considered stale and removed",
+                lock_file.display(),
+                &self.name
+            );
+            fs::remove_file(&lock_file).with_context(|| err_msg)?;
+        }
+
+        Ok(pid)
     }
 
     /// Creates a chroot container. First, a lock is created for the current
@@ -819,6 +2400,7 @@ impl Repo {
 
         // Since the repository will be changed it must be locked
         lock!(self);
+        chroot_lock!(self, true);
 
         exec_with_tmp_data!({
             self.create_chroot().with_context(|| err_msg)?;
@@ -827,17 +2409,19 @@ impl Repo {
         Ok(())
     }
 
-    /// Determines the path of the relevant makepkg.conf file. This is done in the
+    /// Determines the path of the relevant makepkg.conf file. If a path is set
+    /// explicitly in the repository configuration, that path is used. It is an
+    /// error if the file does not exist. Otherwise, the path is determined in the
     /// following sequence (assuming that `~/.config/repman` is the path to the
     /// repman config directory):
     /// 1) ~/.config/repman/makepkg-<REPOSITORY-NAME>.conf
     /// 2) ~/.config/repman/makepkg.conf
     /// 3) /etc/makepkg.conf
     ///
-    /// The determination is only donw once. The result is stored in a static variable
-    fn makepkg_conf(&self) -> anyhow::Result<&'static Path> {
-        static MAKEPKG_CONF: OnceCell<PathBuf> = OnceCell::new();
-        Ok(MAKEPKG_CONF
+    /// The determination is only donw once per instance
+    fn makepkg_conf(&self) -> anyhow::Result<&Path> {
+        Ok(self
+            .makepkg_conf
             .get_or_try_init(|| {
                 // Assemble path of makepkg.conf file to be used for building
                 // packages
@@ -845,6 +2429,17 @@ impl Repo {
                     "Cannot determine path to makepkg.conf for repository {}",
                     &self.name
                 );
+                if let Some(path) = &self.cfg_makepkg_conf {
+                    return if path.is_file() {
+                        Ok(path.clone())
+                    } else {
+                        Err(anyhow!(
+                            "makepkg.conf file '{}' configured for repository {} does not exist",
+                            path.display(),
+                            &self.name
+                        ))
+                    };
+                }
                 let config_dir = config_dir().with_context(|| err_msg.clone())?;
                 let paths: [PathBuf; 3] = [
                     config_dir.join("makepkg-".to_string() + &self.name + ".conf"),
@@ -864,20 +2459,122 @@ impl Repo {
             .as_path())
     }
 
-    /// Determines the path of the relevant pacman.conf file. This is done in the
+    /// Copies the current repository to the remote location `url`. If the
+    /// repository is remote, it is downloaded to its local cache directory first.
+    /// The repository DB is not changed
+    pub fn mirror<S>(&self, url: S) -> anyhow::Result<()>
+    where
+        S: AsRef<str> + Display,
+    {
+        let err_msg = format!("Cannot mirror repository {} to {}", &self.name, url);
+
+        let url = server::parse_url(url.as_ref()).with_context(|| err_msg.clone())?;
+        let server = server::new(&url, self.retries, self.transfer_concurrency)
+            .with_context(|| err_msg.clone())?;
+        if !server.is_remote() {
+            return Err(
+                anyhow!("Mirroring is only supported for remote locations").context(err_msg)
+            );
+        }
+        server.check_upload().with_context(|| err_msg.clone())?;
+
+        read_lock!(self);
+        self.download().with_context(|| err_msg.clone())?;
+
+        if !self.db_exists() {
+            return Err(anyhow!("Repository DB does not exist").context(err_msg));
+        }
+
+        self.upload_to(server.as_ref(), &[])
+            .with_context(|| err_msg)
+    }
+
+    /// Mounts a tmpfs for builds (either with or without chroot container,
+    /// depending on `no_chroot`) and returns the path of the mount point. If a
+    /// tmpfs is mounted there already (e.g., since a previous run was
+    /// interrupted), it is reused
+    fn mount_build_tmpfs(&self, no_chroot: bool) -> anyhow::Result<PathBuf> {
+        let err_msg = format!("Cannot mount tmpfs for builds of repository {}", &self.name);
+
+        let build_dir = ensure_dir(
+            self.build_tmpfs_dir(no_chroot)
+                .with_context(|| err_msg.clone())?,
+        )
+        .with_context(|| err_msg.clone())?;
+        if !is_mount_point(&build_dir).with_context(|| err_msg.clone())? {
+            msg!("Mounting tmpfs at '{}' ...", build_dir.display());
+            mount_tmpfs(&build_dir, BUILD_TMPFS_SIZE).with_context(|| err_msg)?;
+        }
+
+        Ok(build_dir)
+    }
+
+    /// Returns the names contained in `pkg_names` of those packages that are not
+    /// contained in the current repository yet. Only the names are compared.
+    /// I.e., packages from version control systems (such as "...-git") that are
+    /// contained in the repository are not considered as new, even if a newer
+    /// version could be built. The repository data must have been downloaded
+    /// before
+    fn new_pkg_names<S>(&self, pkg_names: &[S]) -> anyhow::Result<Vec<String>>
+    where
+        S: AsRef<str>,
+    {
+        let err_msg = format!(
+            "Cannot determine packages that are not contained in repository {}",
+            &self.name
+        );
+
+        if !self.db_exists() {
+            return Ok(pkg_names
+                .iter()
+                .map(|pkg_name| pkg_name.as_ref().to_string())
+                .collect());
+        }
+
+        let db_pkgs = self.db_pkgs().with_context(|| err_msg.clone())?;
+        let mut new_pkg_names: Vec<String> = vec![];
+        for pkg_name in pkg_names.iter().map(AsRef::as_ref) {
+            if db_pkgs.contains(pkg_name) {
+                msg!(
+                    "Package {} is already contained in repository {}. It is skipped",
+                    pkg_name,
+                    &self.name
+                );
+            } else {
+                new_pkg_names.push(pkg_name.to_string());
+            }
+        }
+
+        Ok(new_pkg_names)
+    }
+
+    /// Determines the path of the relevant pacman.conf file. If a path is set
+    /// explicitly in the repository configuration, that path is used. It is an
+    /// error if the file does not exist. Otherwise, the path is determined in the
     /// following sequence (assuming that `~/.config/repman` is the path to the
     /// repman config directory):
     /// 1) ~/.config/repman/pacman-<REPOSITORY-NAME>.conf
     /// 2) ~/.config/repman/pacman.conf
     /// 3) /etc/pacman.conf
     ///
-    /// The determination is only donw once. The result is stored in a static variable
-    fn pacman_conf(&self) -> anyhow::Result<&'static Path> {
-        static PACMAN_CONF: OnceCell<PathBuf> = OnceCell::new();
-        Ok(PACMAN_CONF
+    /// The determination is only donw once per instance
+    fn pacman_conf(&self) -> anyhow::Result<&Path> {
+        Ok(self
+            .pacman_conf
             .get_or_try_init(|| {
                 // Assemble path of pacman.conf file to be used for building
                 // packages
+                if let Some(path) = &self.cfg_pacman_conf {
+                    return if path.is_file() {
+                        Ok(path.clone())
+                    } else {
+                        Err(anyhow!(
+                            "pacman.conf file '{}' configured for repository {} does not exist",
+                            path.display(),
+                            &self.name
+                        ))
+                    };
+                }
                 let config_dir = config_dir().with_context(|| {
                     format!(
                         "Cannot determine path to pacman.conf for repository {}",
@@ -929,11 +2626,13 @@ impl Repo {
 
         // Copy all lines of pacman_conf_reader to pacman_conf_writer, except those
         // that (potentially) configure the current repository in pacman_conf_reader.
-        // Such a configuration could be there, but does not have to
+        // Such a configuration could be there, but does not have to. Include
+        // directives are replaced by the content of the included files, since
+        // these files might not exist in the chroot container. If a mirror is
+        // configured for the chroot container, it is added as first server to
+        // the sections of the official repositories. Thus, pacman prefers it
         let mut it_is_me = false;
-        for line in pacman_conf_reader.lines() {
-            let line = line.with_context(|| err_msg)?;
-
+        for line in expand_pacman_conf_includes(pacman_conf_reader, 0).with_context(|| err_msg)? {
             if line.starts_with(&format!("[{}]", &self.db_name)) {
                 it_is_me = true;
                 continue;
@@ -946,9 +2645,17 @@ impl Repo {
                 it_is_me = false;
             }
 
+            let is_official_section = CHROOT_MIRROR_REPOS
+                .iter()
+                .any(|repo| line.trim() == format!("[{}]", repo));
             pacman_conf_writer
                 .write((line + "\n").as_bytes())
                 .with_context(|| err_msg)?;
+            if let Some(mirror) = self.chroot_mirror.as_ref().filter(|_| is_official_section) {
+                pacman_conf_writer
+                    .write(format!("Server = {}\n", mirror).as_bytes())
+                    .with_context(|| err_msg)?;
+            }
         }
 
         // Add section for current repository with local repository directory as
@@ -956,8 +2663,9 @@ impl Repo {
         pacman_conf_writer
             .write(
                 format!(
-                    "\n[{}]\nSigLevel = Optional TrustAll\nServer = file://{}\n",
+                    "\n[{}]\nSigLevel = {}\nServer = file://{}\n",
                     &self.db_name,
+                    self.chroot_sig_level.trim(),
                     &self.local_dir.display()
                 )
                 .as_bytes(),
@@ -970,6 +2678,20 @@ impl Repo {
         Ok(pacman_conf_new)
     }
 
+    /// Path of the directory where the PKGBUILD files of built packages are stored.
+    /// Normally, that is `~/.cache/repman/pkgbuilds/<REPOSITORY>`
+    fn pkgbuild_archive_dir(&self) -> anyhow::Result<PathBuf> {
+        Ok(cache_dir()
+            .with_context(|| {
+                format!(
+                    "Cannot assemble path of PKGBUILD archive directory for repository {}",
+                    &self.name
+                )
+            })?
+            .join(PKGBUILD_ARCHIVE_SUB_PATH)
+            .join(&self.name))
+    }
+
     /// Creates a package instance for the package name `pkg_name`. The package meta
     /// data is retrieved from the repository DB. Thus, the repository must contain
     /// the package
@@ -1005,11 +2727,11 @@ impl Repo {
     }
 
     /// Determines the extension of package files from the relevant makepkg.conf
-    /// file. The determination is only donw once. The result is stored in a static
-    /// variable
-    fn pkg_ext(&self) -> anyhow::Result<&'static str> {
-        static PKG_EXT: OnceCell<String> = OnceCell::new();
-        Ok(PKG_EXT
+    /// file. The extension must be one of the supported extensions of package
+    /// files. The determination is only donw once per instance
+    fn pkg_ext(&self) -> anyhow::Result<&str> {
+        Ok(self
+            .pkg_ext
             .get_or_try_init(|| {
                 let err_msg = format!(
                     "Cannot determine package extension (PKG_EXT) for repository {}",
@@ -1028,7 +2750,12 @@ impl Repo {
 
                 #[allow(clippy::unnecessary_unwrap)]
                 if captures.is_some() && captures.as_ref().unwrap().get(1).is_some() {
-                    Ok(captures.unwrap().get(1).unwrap().as_str().to_string())
+                    let pkg_ext = captures.unwrap().get(1).unwrap().as_str();
+                    if !PKG_EXTS.contains(&pkg_ext) {
+                        return Err(anyhow!("Package extension '{}' is not supported", pkg_ext)
+                            .context(err_msg));
+                    }
+                    Ok(pkg_ext.to_string())
                 } else {
                     Err(anyhow!(err_msg))
                 }
@@ -1036,11 +2763,30 @@ impl Repo {
             .as_str())
     }
 
-    /// Determines the base names of packages to be updated
+    /// Returns the GPG key that package `pkg_name` is signed with. That's the key
+    /// of the entry of PkgKeys in the repository configuration whose package name
+    /// pattern matches `pkg_name`. If several patterns match, an exact match wins,
+    /// and the longest pattern otherwise. If no pattern matches, the default key
+    /// (see `gpg_key`) is returned
+    fn pkg_gpg_key(&self, pkg_name: &str) -> Option<&str> {
+        self.pkg_keys
+            .iter()
+            .filter(|(pattern, _)| {
+                glob::Pattern::new(pattern).is_ok_and(|pattern| pattern.matches(pkg_name))
+            })
+            .max_by_key(|(pattern, _)| (pattern.as_str() == pkg_name, pattern.len()))
+            .map(|(_, key)| key.as_str())
+            .or_else(|| self.gpg_key())
+    }
+
+    /// Determines the base names of packages to be updated. If `force` is
+    /// `ForceUpdate::NoVersion`, these are all packages that are not tied to a
+    /// specific version, if it is `ForceUpdate::All`, all packages, irrespective
+    /// of their versions
     fn pkgs_to_be_updated<'a>(
         &'a self,
         aur_data: &'a AurData,
-        force_no_version: bool,
+        force: ForceUpdate,
         no_confirm: bool,
     ) -> anyhow::Result<Vec<&'a str>> {
         let err_msg = format!(
@@ -1048,8 +2794,14 @@ impl Repo {
             &self.name
         );
 
-        if force_no_version {
-            let pkgs_upd = aur_data.pkg_name2base_no_version().context(err_msg)?;
+        if force != ForceUpdate::None {
+            let pkgs_upd = if force == ForceUpdate::All {
+                aur_data.pkg_name2base()
+            } else {
+                aur_data
+                    .pkg_name2base_no_version()
+                    .context(err_msg.clone())?
+            };
 
             if pkgs_upd.is_empty() {
                 msg!("No updates available");
@@ -1061,22 +2813,21 @@ impl Repo {
                 for pkg in &pkgs_upd {
                     println!("    {}", pkg.0);
                 }
-                if !Confirm::new()
-                    .with_prompt("Continue?")
-                    .default(true)
-                    .show_default(true)
-                    .interact()
-                    .unwrap()
-                {
+                if !confirm("Continue?", true).with_context(|| err_msg.clone())? {
                     return Ok(vec![]);
                 }
                 println!();
             }
 
-            Ok(pkgs_upd
+            // Split packages share their package base. Thus, bases can occur
+            // multiple times
+            let mut pkg_bases: Vec<&str> = pkgs_upd
                 .iter()
                 .map(|pkg_name2base| pkg_name2base.1)
-                .collect())
+                .collect();
+            pkg_bases.sort_unstable();
+            pkg_bases.dedup();
+            Ok(pkg_bases)
         } else {
             // Determine for which of these packages there are updates available
             // in AUR
@@ -1093,17 +2844,21 @@ impl Repo {
                 msg!("Updates available");
                 for pkg_upd in &pkgs_upd {
                     println!(
-                        "    {} {} -> {}",
-                        pkg_upd.name, pkg_upd.old_version, pkg_upd.new_version
+                        "    {} {} -> {} (maintainer: {}, last modified: {}, votes: {}, popularity: {:.2})",
+                        pkg_upd.name,
+                        pkg_upd.old_version,
+                        pkg_upd.new_version,
+                        pkg_upd.maintainer.unwrap_or("none"),
+                        pkg_upd
+                            .last_modified
+                            .map_or("unknown".to_string(), |time| time
+                                .format("%Y-%m-%d %H:%M UTC")
+                                .to_string()),
+                        pkg_upd.num_votes,
+                        pkg_upd.popularity
                     );
                 }
-                if !Confirm::new()
-                    .with_prompt("Continue?")
-                    .default(true)
-                    .show_default(true)
-                    .interact()
-                    .unwrap()
-                {
+                if !confirm("Continue?", true).with_context(|| err_msg.clone())? {
                     return Ok(vec![]);
                 }
                 println!();
@@ -1114,31 +2869,53 @@ impl Repo {
     }
 
     /// Prepares the chroot container for usage. I.e., if the container exists, it is
-    /// updated. If it does not exist, it is being created
-    fn prepare_chroot(&self) -> anyhow::Result<()> {
+    /// updated (unless `opts.skip_chroot_update` is true). If the update fails and
+    /// `opts.tolerate_chroot_update_failure` is true, a warning is displayed and
+    /// the container is used as it is. If the container does not exist, it is
+    /// being created
+    fn prepare_chroot(&self, opts: &BuildOpts) -> anyhow::Result<()> {
         let err_msg = format!("Cannot prepare chroot for repository {}", &self.name);
 
+        chroot_lock!(self, true);
+
         if self.chroot_exists() {
+            if opts.skip_chroot_update {
+                msg!(
+                    "Using chroot for repository {} without updating it",
+                    &self.name
+                );
+                return Ok(());
+            }
+
             msg!("Updating chroot for repository {} ...", &self.name);
 
             // Update chroot
-            let reader = cmd!(
-                "arch-nspawn",
-                &self.chroot_dir.join(CHROOT_ROOT_SUB_PATH),
-                format!("--bind-ro={}", &self.local_dir.display()),
-                "pacman",
-                "-Syu",
-                "--noconfirm",
-            )
-            .stderr_to_stdout()
-            .stderr_capture()
-            .reader()
-            .with_context(|| err_msg.clone())?;
-            for line in BufReader::new(reader).lines() {
-                match line {
-                    Ok(text) => println!("{}", text),
-                    Err(err) => return Err(anyhow!(err).context(err_msg)),
+            let update = || -> anyhow::Result<()> {
+                let reader = cmd!(
+                    "arch-nspawn",
+                    &self.chroot_dir.join(CHROOT_ROOT_SUB_PATH),
+                    format!("--bind-ro={}", &self.local_dir.display()),
+                    "pacman",
+                    "-Syu",
+                    "--noconfirm",
+                )
+                .stderr_to_stdout()
+                .stderr_capture()
+                .reader()?;
+                for line in BufReader::new(reader).lines() {
+                    println!("{}", line?);
                 }
+                Ok(())
+            };
+            if let Err(err) = update() {
+                if !opts.tolerate_chroot_update_failure {
+                    return Err(err.context(err_msg));
+                }
+                warning!(
+                    "Update of chroot for repository {} failed: {:?}. Using chroot as it is",
+                    &self.name,
+                    err
+                );
             }
         } else {
             self.create_chroot().with_context(|| err_msg.clone())?;
@@ -1146,126 +2923,513 @@ impl Repo {
         Ok(())
     }
 
-    /// Removes packages with names contained in `pkg_names` from the repository DB
-    /// and removes the corresponding package files from the local repository
-    /// (cache) directory.
-    pub fn remove<S>(&self, pkg_names: &[S], no_confirm: bool) -> anyhow::Result<()>
-    where
-        S: AsRef<str> + Display,
-    {
+    /// Removes signature files (*.sig and *.asc) that do not have a
+    /// corresponding file from the repository. Contrary to clean_up(), neither
+    /// the repository DB nor the package files are checked
+    pub fn prune_sigs(&self) -> anyhow::Result<()> {
         lock!(self);
         exec_on_repo!(self, {
-            if self.db_exists() {
-                // Determine the names of the to-be-removed packages
-                let deps = self.deps()?;
-                let valid_pkg_names = self.valid_pkg_names(Some(pkg_names)).with_context(|| {
-                    format!("Cannot remove packages from repository {}", &self.name)
-                })?;
-                let to_be_removed_pkg_names: Vec<&str> = valid_pkg_names
-                    .into_iter()
-                    .filter(|pkg_name| {
-                        no_confirm
-                            || !deps.contains_key(pkg_name)
-                            || Confirm::new()
-                                .with_prompt(format!(
-                "The following package(s) depend on {1}: {0}. Do you really want to remove {1}?",
-                            deps.get(pkg_name).unwrap(),
-                            pkg_name
-                        ))
-                                .default(false)
-                                .show_default(true)
-                                .interact()
-                                .unwrap()
-                    })
-                    .collect();
-
-                // Remove packages from repository DB and remove package files
-                self.remove_pkgs::<&str>(&to_be_removed_pkg_names)
-                    .with_context(|| {
-                        format!("Cannot remove packages from repository {}", &self.name)
-                    })?;
-            }
+            self.remove_orphaned_sig_files();
         });
+
         Ok(())
     }
 
-    /// Removes the local cache directory of a remote repository (i.e., the directory
-    /// where repository data from the remote directory is copied for manipulation).
-    /// If the current repository is local, an error is returned
-    pub fn remove_cache_dir(&self) -> anyhow::Result<()> {
-        if !self.server.is_remote() {
-            warning!(
-                "Since '{}' is a local repository, there is no cache directory to be removed",
-                &self.name
-            );
-            return Ok(());
+    /// Removes all data that repman created locally for the current repository.
+    /// I.e., the chroot directory, the cache directory (if the repository is
+    /// remote), temporary directories of repman processes that are no longer
+    /// running and the lock file
+    pub fn purge(&self) -> anyhow::Result<()> {
+        let err_msg = format!("Cannot purge local data of repository {}", &self.name);
+
+        // A lock of a process that is no longer running is stale and can be
+        // removed. Otherwise, purging is not possible
+        if let Some(pid) = self.lock_holder().with_context(|| err_msg.clone())? {
+            if pid != process::id() && is_process_running(pid) {
+                return Err(
+                    anyhow!("Repository {} is locked by process {}", &self.name, pid)
+                        .context(err_msg),
+                );
+            }
+            fs::remove_file(self.lock_file().with_context(|| err_msg.clone())?)
+                .with_context(|| err_msg.clone())?;
         }
-
-        let err_msg = format!(
-            "Cannot remove cache directory for repository {}",
-            &self.name
-        );
-
-        if !self.local_dir.exists() {
-            msg!(
-                "Cache directory for repository {} does not exist. Nothing to remove",
-                &self.name
+        if let Some(pid) = self
+            .active_readers()
+            .with_context(|| err_msg.clone())?
+            .first()
+        {
+            return Err(
+                anyhow!("Repository {} is read by process {}", &self.name, pid).context(err_msg),
             );
-            return Ok(());
         }
 
-        lock!(self);
-        fs::remove_dir_all(&self.local_dir).with_context(|| err_msg)?;
+        self.remove_chroot_dir().with_context(|| err_msg.clone())?;
+        if self.is_remote() {
+            self.remove_cache_dir().with_context(|| err_msg.clone())?;
+        }
+        remove_stale_tmp_dirs().with_context(|| err_msg)?;
 
         Ok(())
     }
 
-    /// Removes chroot directory of the current repository
-    pub fn remove_chroot_dir(&self) -> anyhow::Result<()> {
-        if !self.chroot_exists() {
-            msg!(
-                "Chroot directory for repository {} does not exist. Nothing to remove",
-                &self.name
-            );
-            return Ok(());
+    /// Creates a read lock (i.e., a file with the current process ID) for the
+    /// current repository. Several processes can hold read locks at the same
+    /// time. A read lock can only be created if no other process holds a (write)
+    /// lock
+    fn read_lock(&self) -> anyhow::Result<()> {
+        let err_msg = format!("Cannot create read lock for repository {}", &self.name);
+        let lock_file = self.lock_file()?;
+        let read_lock_file = self.read_lock_file().with_context(|| err_msg.clone())?;
+
+        // The read lock is created before it is checked whether another process
+        // holds the lock. lock() creates the lock before it checks for read
+        // locks. Thus, if another process locks the repository at the same time,
+        // at least one of both processes notices the other. If the read lock
+        // file exists already, the current process holds the read lock already
+        create_pid_file(&read_lock_file).with_context(|| err_msg.clone())?;
+
+        if let Some(pid) = self.lock_holder().with_context(|| err_msg.clone())? {
+            if pid != process::id() {
+                fs::remove_file(&read_lock_file).with_context(|| err_msg)?;
+                return Err(anyhow!(
+                    "Lock file '{}' exists: repository {} is locked by process {}",
+                    lock_file.display(),
+                    &self.name,
+                    pid
+                ));
+            }
         }
 
-        let err_msg = format!(
-            "Cannot remove chroot directory for repository {}",
-            &self.name
-        );
+        Ok(())
+    }
 
-        lock!(self);
+    /// Returns the path to the read lock file of the current process for the
+    /// repository
+    fn read_lock_file(&self) -> anyhow::Result<PathBuf> {
+        let mut read_lock_file = self.lock_file()?.into_os_string();
+        read_lock_file.push(format!("{}{}", READ_LOCK_INFIX, process::id()));
+        Ok(PathBuf::from(read_lock_file))
+    }
 
-        // fs::remove_dir_all() can only be used if repman is running as root.
-        // Otherwise "rm", run via sudo or su, is be used
-        if sudo::check() == sudo::RunningAs::Root {
-            fs::remove_dir_all(&self.chroot_dir).with_context(|| err_msg.clone())
+    /// Removes the read lock of the current process for the repository
+    fn read_unlock(&self) -> anyhow::Result<()> {
+        let read_lock_file = self.read_lock_file()?;
+        if read_lock_file.exists() {
+            fs::remove_file(read_lock_file).with_context(|| {
+                format!("Cannot remove read lock for repository {}", &self.name)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Uploads the current state of the local copy of a remote repository without
+    /// downloading it before. This allows to recover from a failed upload: Since
+    /// the local copy was changed already, nothing has to be redone except the
+    /// upload
+    pub fn push(&self) -> anyhow::Result<()> {
+        let err_msg = format!("Cannot push repository {}", &self.name);
+
+        if !self.is_remote() {
+            return Err(
+                anyhow!("Pushing is only supported for remote repositories").context(err_msg)
+            );
+        }
+
+        lock!(self);
+        if !self.db_exists() {
+            return Err(anyhow!("Local copy of repository does not contain a DB").context(err_msg));
+        }
+
+        // The upload deletes remote files that do not exist locally. If the local
+        // copy is incomplete (e.g., since only the DB was downloaded by a read-only
+        // operation such as `repman ls`), the package files would be deleted
+        // remotely. Thus, the push is refused in this case
+        let missing_files = self.missing_pkg_files().with_context(|| err_msg.clone())?;
+        if !missing_files.is_empty() {
+            return Err(anyhow!(
+                "Local copy of repository is incomplete. These package files are referenced by the DB but do not exist locally: {}",
+                missing_files.join(", ")
+            )
+            .context(err_msg));
+        }
+
+        self.check_upload().with_context(|| err_msg.clone())?;
+
+        msg!("Uploading local copy of repository {} ...", &self.name);
+        self.upload_to(self.server.as_ref(), &[])
+            .with_context(|| err_msg)
+    }
+
+    /// Adds the packages `pkgs` that were changed by the operation `operation` to
+    /// the change summary, if such a summary is requested. The summary contains
+    /// one tab-separated line per package: The name of the repository, the kind
+    /// of change ("added" or "removed"), the package name, its version and its
+    /// signature status ("signed", "unsigned", or "-" for removed packages). The
+    /// lines are sorted to allow diffing the summaries of different runs. Since
+    /// the operation was successful already, errors are only printed as warnings
+    fn record_change_summary(&self, operation: &str, pkgs: &[HistoryPkg]) {
+        let Some(summary_file) = change_summary_file() else {
+            return;
+        };
+
+        let change = if operation == "remove" {
+            "removed"
         } else {
-            let output = if is_pkg_installed("sudo").with_context(|| err_msg.clone())? {
-                cmd!("sudo", "rm", "-rdf", &self.chroot_dir,)
-                    .stdout_null()
-                    .stderr_capture()
-                    .unchecked()
-                    .run()
-                    .with_context(|| err_msg.clone())?
-            } else {
-                cmd!("su", "root", "-c", "rm", "-rdf", &self.chroot_dir,)
-                    .stdout_null()
-                    .stderr_capture()
-                    .unchecked()
-                    .run()
-                    .with_context(|| err_msg.clone())?
+            "added"
+        };
+        let mut lines: BTreeSet<String> = match fs::read_to_string(summary_file) {
+            Ok(content) => content.lines().map(str::to_string).collect(),
+            Err(err) => {
+                warning!(
+                    "Cannot read change summary file '{}': {:?}",
+                    summary_file.display(),
+                    err
+                );
+                return;
+            }
+        };
+        for pkg in pkgs {
+            lines.insert(format!(
+                "{}\t{}\t{}\t{}\t{}",
+                &self.name,
+                change,
+                pkg.name,
+                pkg.version,
+                match pkg.signed {
+                    Some(true) => "signed",
+                    Some(false) => "unsigned",
+                    None => "-",
+                }
+            ));
+        }
+
+        let content: String = lines.into_iter().map(|line| line + "\n").collect();
+        if let Err(err) = fs::write(summary_file, content) {
+            warning!(
+                "Cannot write change summary file '{}': {:?}",
+                summary_file.display(),
+                err
+            );
+        }
+    }
+
+    /// Appends a record of the operation `operation` that changed the packages
+    /// `pkgs` to the history of the current repository. This must only be done
+    /// after the changes were uploaded successfully. Since the operation was
+    /// successful already, errors are only printed as warnings
+    fn record_history(&self, operation: &str, pkgs: Vec<HistoryPkg>) {
+        if pkgs.is_empty() {
+            return;
+        }
+
+        self.record_change_summary(operation, &pkgs);
+
+        let record = HistoryRecord {
+            timestamp: Utc::now().timestamp(),
+            operation: operation.to_string(),
+            packages: pkgs,
+        };
+        if let Err(err) = self.history_file().and_then(|history_file| {
+            let mut file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(history_file)?;
+            writeln!(file, "{}", serde_json::to_string(&record)?)?;
+            Ok(())
+        }) {
+            warning!(
+                "Cannot record history of repository {}: {:?}",
+                &self.name,
+                err
+            );
+        }
+    }
+
+    /// Stores the commits of the git sources of the PKGBUILD files `pkgbuilds`
+    /// whose packages are contained in `built_pkgs`. `vcs_commits` maps package
+    /// base names to these commits. Since the packages were built successfully
+    /// already, errors are only printed as warnings
+    fn record_vcs_commits(
+        &self,
+        pkgbuilds: &[PkgBuild],
+        built_pkgs: &[Pkg],
+        vcs_commits: &HashMap<String, Vec<String>>,
+    ) {
+        let built_pkg_names: HashSet<String> = built_pkgs
+            .iter()
+            .filter_map(|pkg| pkg.name().ok())
+            .collect();
+
+        for pkgbuild in pkgbuilds {
+            let Ok(pkg_base) = pkgbuild.pkg_base() else {
+                continue;
             };
-            if output.status.success() {
+            let Some(commits) = vcs_commits.get(&pkg_base) else {
+                continue;
+            };
+            if !pkgbuild.pkg_names().is_ok_and(|pkg_names| {
+                pkg_names
+                    .iter()
+                    .any(|pkg_name| built_pkg_names.contains(pkg_name))
+            }) {
+                continue;
+            }
+            if let Err(err) = self.vcs_commits_file(&pkg_base).and_then(|commits_file| {
+                if let Some(parent) = commits_file.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(commits_file, format!("{}\n", commits.join("\n")))?;
                 Ok(())
-            } else {
-                Err(anyhow!("gpg: {}", from_utf8(&output.stderr).unwrap()).context(err_msg))
+            }) {
+                warning!(
+                    "Cannot store commits of git sources of '{}': {:?}",
+                    pkgbuild.as_ref().display(),
+                    err
+                );
+            }
+        }
+    }
+
+    /// Updates the PGP keys that are used to verify the sources of packages during
+    /// builds. I.e., the keys configured as trusted keys for the current
+    /// repository are imported into the keyring of the current user (if they are
+    /// not contained yet) and all keys of that keyring are refreshed from a key
+    /// server. If `chroot` is true, the pacman keyring of the chroot container is
+    /// refreshed as well
+    pub fn refresh_keys(&self, chroot: bool) -> anyhow::Result<()> {
+        let err_msg = format!("Cannot refresh PGP keys for repository {}", &self.name);
+
+        lock!(self);
+
+        recv_pgp_keys(&self.trusted_keys).with_context(|| err_msg.clone())?;
+        refresh_pgp_keys().with_context(|| err_msg.clone())?;
+
+        if !chroot {
+            return Ok(());
+        }
+        if !self.chroot_exists() {
+            warning!(
+                "Chroot container of repository {} does not exist. Nothing to refresh there",
+                &self.name
+            );
+            return Ok(());
+        }
+
+        msg!(
+            "Refreshing pacman keyring of chroot container for repository {} ...",
+            &self.name
+        );
+        let reader = cmd!(
+            "arch-nspawn",
+            &self.chroot_dir.join(CHROOT_ROOT_SUB_PATH),
+            "pacman-key",
+            "--refresh-keys",
+        )
+        .stderr_to_stdout()
+        .reader()
+        .with_context(|| err_msg.clone())?;
+        for line in BufReader::new(reader).lines() {
+            match line {
+                Ok(text) => println!("{}", text),
+                Err(err) => return Err(anyhow!(err).context(err_msg)),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes packages with names contained in `pkg_names` from the repository DB
+    /// and removes the corresponding package files from the local repository
+    /// (cache) directory. If `older_than` is `Some(...)`, only packages that were
+    /// built before that point in time are removed. In that case, all packages
+    /// of the repository are considered if `pkg_names` is empty, and the
+    /// selected packages must be confirmed unless `no_confirm` is true
+    pub fn remove<S>(
+        &self,
+        pkg_names: &[S],
+        no_confirm: bool,
+        verify_other_repos: bool,
+        older_than: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<()>
+    where
+        S: AsRef<str> + Display,
+    {
+        let mut removed_pkgs: Vec<HistoryPkg> = vec![];
+        lock!(self);
+        exec_on_repo!(self, {
+            if self.db_exists() {
+                // Determine the names of the to-be-removed packages
+                let deps = self.deps()?;
+                let mut valid_pkg_names = self
+                    .valid_pkg_names(
+                        if pkg_names.is_empty() && older_than.is_some() {
+                            None
+                        } else {
+                            Some(pkg_names)
+                        },
+                        no_confirm,
+                    )
+                    .with_context(|| {
+                        format!("Cannot remove packages from repository {}", &self.name)
+                    })?;
+
+                // If requested, only packages that were built before the cutoff
+                // are removed. Since this can select many packages, they are
+                // listed and must be confirmed
+                if let Some(older_than) = older_than {
+                    let db_pkgs = self.db_pkgs().with_context(|| {
+                        format!("Cannot remove packages from repository {}", &self.name)
+                    })?;
+                    valid_pkg_names.retain(|pkg_name| {
+                        db_pkgs
+                            .get(pkg_name)
+                            .is_some_and(|db_pkg| db_pkg.build_date < older_than)
+                    });
+                    let cutoff = older_than.format("%Y-%m-%d");
+                    if valid_pkg_names.is_empty() {
+                        msg!(
+                            "No packages were built before {}. Nothing to remove",
+                            cutoff
+                        );
+                    } else {
+                        msg!(
+                            "The following package(s) were built before {}: {}",
+                            cutoff,
+                            valid_pkg_names.join(", ")
+                        );
+                        if !no_confirm && !confirm("Remove them?", false)? {
+                            valid_pkg_names.clear();
+                        }
+                    }
+                }
+
+                // If requested, packages of other repositories that depend on the
+                // to-be-removed packages are determined as well. They are
+                // reported as "<REPOSITORY>/<PACKAGE>"
+                let external_deps = if verify_other_repos {
+                    self.external_dependents(&valid_pkg_names)
+                } else {
+                    BTreeMap::new()
+                };
+                for (pkg_name, dependents) in &external_deps {
+                    warning!(
+                        "The following package(s) of other repositories depend on {}: {}",
+                        pkg_name,
+                        dependents.join(", ")
+                    );
+                }
+
+                let to_be_removed_pkg_names: Vec<&str> = valid_pkg_names
+                    .into_iter()
+                    .filter(|pkg_name| {
+                        let dependents: Vec<String> = deps
+                            .get(pkg_name)
+                            .into_iter()
+                            .flat_map(|dep_pkgs| dep_pkgs.iter().map(|dep| dep.to_string()))
+                            .chain(external_deps.get(*pkg_name).cloned().unwrap_or_default())
+                            .collect();
+                        no_confirm
+                            || dependents.is_empty()
+                            || confirm(
+                                format!(
+                "The following package(s) depend on {1}: {0}. Do you really want to remove {1}?",
+                                    dependents.join(", "),
+                                    pkg_name
+                                ),
+                                false,
+                            )
+                            .unwrap()
+                    })
+                    .collect();
+
+                // Versions must be determined before the packages are removed to
+                // be able to record them in the history
+                let db_pkgs = self.db_pkgs().with_context(|| {
+                    format!("Cannot remove packages from repository {}", &self.name)
+                })?;
+                removed_pkgs = to_be_removed_pkg_names
+                    .iter()
+                    .filter_map(|pkg_name| db_pkgs.get(pkg_name))
+                    .map(|db_pkg| HistoryPkg {
+                        name: db_pkg.name.clone(),
+                        version: db_pkg.version.clone(),
+                        signed: None,
+                    })
+                    .collect();
+
+                // Remove packages from repository DB and remove package files
+                self.remove_pkgs::<&str>(&to_be_removed_pkg_names)
+                    .with_context(|| {
+                        format!("Cannot remove packages from repository {}", &self.name)
+                    })?;
+                self.set_explicit(&to_be_removed_pkg_names, false)
+                    .with_context(|| {
+                        format!("Cannot remove packages from repository {}", &self.name)
+                    })?;
             }
+        });
+
+        // The history is only recorded after the upload was successful
+        self.record_history("remove", removed_pkgs);
+
+        Ok(())
+    }
+
+    /// Removes the local cache directory of a remote repository (i.e., the directory
+    /// where repository data from the remote directory is copied for manipulation).
+    /// If the current repository is local, an error is returned
+    pub fn remove_cache_dir(&self) -> anyhow::Result<()> {
+        if !self.server.is_remote() {
+            warning!(
+                "Since '{}' is a local repository, there is no cache directory to be removed",
+                &self.name
+            );
+            return Ok(());
+        }
+
+        let err_msg = format!(
+            "Cannot remove cache directory for repository {}",
+            &self.name
+        );
+
+        if !self.local_dir.exists() {
+            msg!(
+                "Cache directory for repository {} does not exist. Nothing to remove",
+                &self.name
+            );
+            return Ok(());
+        }
+
+        lock!(self);
+        fs::remove_dir_all(&self.local_dir).with_context(|| err_msg)?;
+
+        Ok(())
+    }
+
+    /// Removes chroot directory of the current repository
+    pub fn remove_chroot_dir(&self) -> anyhow::Result<()> {
+        if !self.chroot_exists() {
+            msg!(
+                "Chroot directory for repository {} does not exist. Nothing to remove",
+                &self.name
+            );
+            return Ok(());
         }
+
+        let err_msg = format!(
+            "Cannot remove chroot directory for repository {}",
+            &self.name
+        );
+
+        lock!(self);
+        chroot_lock!(self, true);
+
+        remove_as_root(&self.chroot_dir).with_context(|| err_msg)
     }
 
-    /// Removes signature files for the current repository
+    /// Removes signature files of the DB of the current repository. These files
+    /// are named after the DB name, which can differ from the repository name
     fn remove_db_sig_files(&self) -> anyhow::Result<()> {
         let err_msg = format!("Cannot remove DB sig files of repository {}", &self.name);
         let patterns: Vec<&str> = vec!["db", "files"];
@@ -1273,10 +3437,11 @@ impl Repo {
         for pattern in patterns {
             for path in (glob(
                 format!(
-                    "{}/{}.{}*.sig",
+                    "{}/{}.{}*{}",
                     &self.local_dir.display(),
-                    &self.name,
-                    pattern
+                    &self.db_name,
+                    pattern,
+                    SIG_SUFFIX
                 )
                 .as_str(),
             )
@@ -1337,9 +3502,39 @@ impl Repo {
         Ok(())
     }
 
+    /// Removes signature files (*.sig and *.asc) in the repository directory that
+    /// do not have a corresponding file in that directory. Errors are reported
+    /// but do not abort the removal of the other files
+    fn remove_orphaned_sig_files(&self) {
+        for suffix in [SIG_SUFFIX, ARMORED_SIG_SUFFIX] {
+            let pattern = format!("{}/*{}", &self.local_dir.display(), suffix);
+            for sig_file in glob(&pattern)
+                .unwrap_or_else(|_| panic!("Pattern '{}' is not supported", pattern))
+                .flatten()
+            {
+                if (sig_file.is_file() || sig_file.is_symlink())
+                    && !sig_file.with_extension("").exists()
+                {
+                    if let Err(err) = fs::remove_file(&sig_file) {
+                        error!(
+                            "{:?}",
+                            anyhow!(err).context(format!(
+                                "Cannot remove obsolete signature file '{}'",
+                                sig_file.display()
+                            ))
+                        );
+                    } else {
+                        msg!("Removed obsolete signature file '{}'", &sig_file.display());
+                    }
+                }
+            }
+        }
+    }
+
     /// Removes packages with names contained in `pkg_names` from the repository DB.
     /// It is not checked if the to-be-removed packages are really contained in the
-    /// DB. Thus, this must be  checked before calling this function
+    /// DB. Thus, this must be  checked before calling this function. As for
+    /// `add_pkgs_to_db()`, all packages are removed with one call of repo-remove
     fn remove_pkgs_from_db<S>(&self, pkg_names: &[S]) -> anyhow::Result<()>
     where
         S: AsRef<str>,
@@ -1367,57 +3562,243 @@ impl Repo {
             );
         }
 
-        // Assemble args for repo-remove
-        let repo_file = self
-            .local_dir
-            .join(self.db_name.clone() + DB_ARCHIVE_SUFFIX);
-        let mut args: Vec<&OsStr> = vec![OsStr::new("--verify")];
-        if self.sign_db {
-            args.extend([
-                OsStr::new("--sign"),
-                OsStr::new("--key"),
-                OsStr::new(self.gpg_key().unwrap()),
-            ]);
-        }
-        args.push(repo_file.as_os_str());
-        for pkg_name in pkg_names {
-            args.push(OsStr::new(pkg_name.as_ref()))
-        }
+        // repo-remove is executed on a staging copy of the DB. Thus, clients
+        // never see a partially written DB
+        self.update_db_staged(|repo_file| {
+            // Assemble args for repo-remove
+            let mut args: Vec<&OsStr> = vec![OsStr::new("--verify")];
+            if self.sign_db {
+                args.extend([
+                    OsStr::new("--sign"),
+                    OsStr::new("--key"),
+                    OsStr::new(self.gpg_key().unwrap()),
+                ]);
+            }
+            args.push(repo_file.as_os_str());
+            for pkg_name in pkg_names {
+                args.push(OsStr::new(pkg_name.as_ref()))
+            }
 
-        // Execute repo-remove
-        let output = cmd("repo-remove", &args)
-            .stdout_null()
-            .stderr_capture()
-            .unchecked()
-            .run()
-            .with_context(|| err_msg.clone())?;
-        if output.status.success() {
-            Ok(())
-        } else {
-            Err(anyhow!("repo-remove: {}", from_utf8(&output.stderr).unwrap()).context(err_msg))
-        }
+            // Execute repo-remove
+            let output = cmd("repo-remove", &args)
+                .stdout_null()
+                .stderr_capture()
+                .unchecked()
+                .run()?;
+            if output.status.success() {
+                Ok(())
+            } else {
+                Err(anyhow!(
+                    "repo-remove: {}",
+                    from_utf8(&output.stderr).unwrap()
+                ))
+            }
+        })
+        .with_context(|| err_msg)
     }
 
-    /// Signs package file for packages whose names are contained in `pkg_names`.
-    pub fn sign<S>(&self, pkg_names: Option<&[S]>) -> anyhow::Result<()>
+    /// Re-signs all packages and the DB of the current repository (the DB only if
+    /// that is required by the configuration), e.g. after the signing key was
+    /// rotated. If `gpg_key` is `Some(...)`, this key is used instead of the
+    /// key from the environment variable GPGKEY or makepkg.conf. The old
+    /// signature files are removed before. The packages are added to the DB
+    /// again, since it contains the signatures of the packages as well. Without
+    /// `gpg_key`, packages that have a designated key (see `pkg_gpg_key`) are
+    /// signed with that key
+    pub fn resign<S>(&self, gpg_key: Option<S>) -> anyhow::Result<()>
     where
-        S: AsRef<str> + Display,
+        S: AsRef<str>,
     {
+        let err_msg = format!("Cannot re-sign repository {}", &self.name);
+
+        let use_pkg_keys = gpg_key.is_none();
+        if let Some(gpg_key) = gpg_key {
+            self.gpg_key
+                .set(Some(gpg_key.as_ref().to_string()))
+                .map_err(|_| anyhow!("GPG key was determined already"))
+                .with_context(|| err_msg.clone())?;
+        }
+        let gpg_key = self
+            .gpg_key()
+            .ok_or_else(|| anyhow!("GPG key is not set"))
+            .with_context(|| err_msg.clone())?;
+
         lock!(self);
         exec_on_repo!(self, {
-            let err_msg = format!("Cannot sign packages of repository {}", &self.name);
-            // Signing packages makes only sense if there is a repository DB
-            if self.db_exists() {
-                // Sign the relevant packages
-                let gpg_key = self.gpg_key().with_context(|| err_msg.clone())?;
-                for pkg_name in self
-                    .valid_pkg_names(pkg_names)
-                    .with_context(|| err_msg.clone())?
-                {
-                    if let Err(err) = self
+            if !self.db_exists() {
+                return Err(anyhow!("Repository DB does not exist").context(err_msg));
+            }
+
+            // The old signature files must be removed first, since signing is
+            // skipped for packages that are signed already
+            let mut pkgs: Vec<Pkg> = vec![];
+            for pkg_name in self
+                .valid_pkg_names::<&str>(None, true)
+                .with_context(|| err_msg.clone())?
+            {
+                let pkg = self.pkg(pkg_name).with_context(|| err_msg.clone())?;
+                pkg.remove_sig_files().with_context(|| err_msg.clone())?;
+                pkg.sign(
+                    if use_pkg_keys {
+                        self.pkg_gpg_key(pkg_name).unwrap_or(gpg_key)
+                    } else {
+                        gpg_key
+                    },
+                    self.sig_mode,
+                )
+                .with_context(|| format!("Cannot sign package {}", pkg_name))
+                .with_context(|| err_msg.clone())?;
+                pkgs.push(pkg);
+            }
+            msg!("Re-signed {} package(s)", pkgs.len());
+
+            // Adding the packages again updates their signatures in the DB and
+            // signs the DB with the new key
+            self.remove_db_sig_files()
+                .with_context(|| err_msg.clone())?;
+            self.add_pkgs_to_db(&pkgs)
+                .with_context(|| err_msg.clone())?;
+        });
+
+        Ok(())
+    }
+
+    /// Replaces the local copy of the current (remote) repository by a fresh
+    /// download, e.g. if it was corrupted by an interrupted download. Since the
+    /// local copy is removed before, all files are downloaded completely instead
+    /// of only transferring the differences
+    pub fn resync(&self) -> anyhow::Result<()> {
+        let err_msg = format!("Cannot re-synchronize repository {}", &self.name);
+
+        if !self.is_remote() {
+            return Err(anyhow!(
+                "Repository {} is local. Thus, there is no local copy to be re-synchronized",
+                &self.name
+            )
+            .context(err_msg));
+        }
+
+        lock!(self);
+        fs::remove_dir_all(&self.local_dir).with_context(|| err_msg.clone())?;
+        ensure_dir(&self.local_dir).with_context(|| err_msg.clone())?;
+        self.download().with_context(|| err_msg)?;
+
+        Ok(())
+    }
+
+    /// Marks the packages with names contained in `pkg_names` as explicitly added
+    /// (if `explicit` is true) or as added as dependencies (if `explicit` is false)
+    fn set_explicit<S>(&self, pkg_names: &[S], explicit: bool) -> anyhow::Result<()>
+    where
+        S: AsRef<str>,
+    {
+        let err_msg = format!(
+            "Cannot store explicitly added packages of repository {}",
+            &self.name
+        );
+
+        let mut explicit_pkg_names = self.explicit_pkg_names().with_context(|| err_msg.clone())?;
+        for pkg_name in pkg_names {
+            if explicit {
+                explicit_pkg_names.insert(pkg_name.as_ref().to_string());
+            } else {
+                explicit_pkg_names.remove(pkg_name.as_ref());
+            }
+        }
+
+        let mut content = String::new();
+        for pkg_name in explicit_pkg_names {
+            content = content + &pkg_name + "\n";
+        }
+        fs::write(
+            self.explicit_pkgs_file().with_context(|| err_msg.clone())?,
+            content,
+        )
+        .with_context(|| err_msg)
+    }
+
+    /// Prints the changes of the PKGBUILD file `pkgbuild` compared to the stored
+    /// PKGBUILD file of the package version that is contained in the repository as
+    /// unified diff
+    fn show_pkgbuild_diff(&self, pkgbuild: &PkgBuild) -> anyhow::Result<()> {
+        let err_msg = format!(
+            "Cannot show changes of PKGBUILD file '{}'",
+            pkgbuild.as_ref().display()
+        );
+
+        let pkg_base = pkgbuild.pkg_base().with_context(|| err_msg.clone())?;
+        let Some(db_pkg) = self
+            .db_pkgs()
+            .with_context(|| err_msg.clone())?
+            .packages()
+            .find(|db_pkg| db_pkg.base == pkg_base)
+        else {
+            msg!("{} is not contained in the repository yet", pkg_base);
+            return Ok(());
+        };
+
+        let old_pkgbuild = self
+            .pkgbuild_archive_dir()
+            .with_context(|| err_msg.clone())?
+            .join(format!("{}-{}", &pkg_base, &db_pkg.version))
+            .join(PKGBUILD_FILE_NAME);
+        if !old_pkgbuild.exists() {
+            warning!(
+                "No stored PKGBUILD file for {} {}. Thus, changes cannot be shown",
+                &pkg_base,
+                &db_pkg.version
+            );
+            return Ok(());
+        }
+
+        msg!(
+            "Changes of PKGBUILD file for {} since {}:",
+            &pkg_base,
+            &db_pkg.version
+        );
+
+        // diff exits with 1 if the files differ. Thus, only exit codes greater than 1
+        // indicate errors
+        let output = cmd!("diff", "-u", &old_pkgbuild, pkgbuild.as_ref())
+            .unchecked()
+            .run()
+            .with_context(|| err_msg.clone())?;
+        if output.status.code().unwrap_or(2) > 1 {
+            return Err(anyhow!("diff exited with {}", output.status).context(err_msg));
+        }
+
+        Ok(())
+    }
+
+    /// Signs package file for packages whose names are contained in `pkg_names`.
+    /// If `no_confirm` is true, patterns in `pkg_names` are expanded without
+    /// asking the user for confirmation
+    pub fn sign<S>(&self, pkg_names: Option<&[S]>, no_confirm: bool) -> anyhow::Result<()>
+    where
+        S: AsRef<str> + Display,
+    {
+        lock!(self);
+        exec_on_repo!(self, {
+            let err_msg = format!("Cannot sign packages of repository {}", &self.name);
+            // Signing packages makes only sense if there is a repository DB
+            if self.db_exists() {
+                // Sign the relevant packages, each with its designated key
+                for pkg_name in self
+                    .valid_pkg_names(pkg_names, no_confirm)
+                    .with_context(|| err_msg.clone())?
+                {
+                    let Some(gpg_key) = self.pkg_gpg_key(pkg_name) else {
+                        error!(
+                            "{:?}",
+                            anyhow!("GPG key is not set")
+                                .context(format!("Cannot sign package {}", pkg_name))
+                        );
+                        continue;
+                    };
+                    if let Err(err) = self
                         .pkg(pkg_name)
                         .with_context(|| err_msg.clone())?
-                        .sign(gpg_key)
+                        .sign(gpg_key, self.sig_mode)
                     {
                         error!(
                             "{:?}",
@@ -1431,105 +3812,520 @@ impl Repo {
         Ok(())
     }
 
+    /// Signs the DB of the current repository (i.e., the DB and the files
+    /// archives) without changing the package files. That is required if SignDB
+    /// was set after the DB had been created. Since repo-add cannot be run
+    /// without packages, the archives are signed directly, and the symbolic
+    /// links to their signatures (`<DB>.db.sig` and `<DB>.files.sig`) are
+    /// created like repo-add does
+    pub fn sign_db_only(&self) -> anyhow::Result<()> {
+        let err_msg = format!("Cannot sign DB of repository {}", &self.name);
+
+        if !self.sign_db {
+            return Err(anyhow!(
+                "SignDB is not set in the configuration of repository {}",
+                &self.name
+            )
+            .context(err_msg));
+        }
+        let Some(gpg_key) = self.gpg_key() else {
+            return Err(anyhow!("GPG key is not set").context(err_msg));
+        };
+
+        lock!(self);
+        exec_on_repo!(self, {
+            if !self.db_exists() {
+                return Err(anyhow!("Repository DB does not exist").context(err_msg));
+            }
+
+            self.update_db_staged(|db_archive| {
+                let staging_dir = db_archive
+                    .parent()
+                    .unwrap_or_else(|| panic!("Cannot extract staging directory of DB archive"));
+                let db_archive_name = db_archive
+                    .file_name()
+                    .unwrap_or_else(|| panic!("Cannot extract file name of DB archive"))
+                    .to_string_lossy()
+                    .to_string();
+
+                // The files archive has the same suffix as the DB archive (e.g.,
+                // ".tar.xz")
+                let archive_suffix = db_archive_name
+                    .strip_prefix(&(self.db_name.clone() + DB_SUFFIX))
+                    .unwrap_or_default();
+                let files_archive_name =
+                    format!("{}{}{}", &self.db_name, FILES_SUFFIX, archive_suffix);
+
+                for (link_name, archive_name) in [
+                    (self.db_name.clone() + DB_SUFFIX, db_archive_name),
+                    (self.db_name.clone() + FILES_SUFFIX, files_archive_name),
+                ] {
+                    let archive = staging_dir.join(&archive_name);
+                    if !archive.exists() {
+                        continue;
+                    }
+                    sign_file(&archive, gpg_key, self.sig_mode)?;
+
+                    let sig_link = staging_dir.join(link_name + SIG_SUFFIX);
+                    if sig_link.is_symlink() || sig_link.exists() {
+                        fs::remove_file(&sig_link)?;
+                    }
+                    symlink(archive_name + SIG_SUFFIX, &sig_link)?;
+                }
+
+                Ok(())
+            })
+            .with_context(|| err_msg.clone())?;
+            msg!("Signed DB of repository {}", &self.name);
+        });
+
+        Ok(())
+    }
+
+    /// Determines the packages of the current repository that might need to be
+    /// rebuilt since they depend on a package of `built_pkgs` whose shared
+    /// libraries changed their soname. I.e., the sonames that the package
+    /// provides according to the repository DB are compared with the sonames
+    /// that the built package file provides. Thus, this function must be called
+    /// before the built packages are added to the DB. The DB is read from the
+    /// file directly to not cache DB data that is outdated after the packages
+    /// were added
+    fn soname_dependents(&self, built_pkgs: &[Pkg]) -> anyhow::Result<BTreeSet<String>> {
+        let err_msg = format!(
+            "Cannot determine packages of repository {} that depend on changed sonames",
+            &self.name
+        );
+
+        let mut dependents: BTreeSet<String> = BTreeSet::new();
+        if !self.db_exists() {
+            return Ok(dependents);
+        }
+
+        let db_pkgs = repodb_parser::parse(self.db_archive_file().as_path())
+            .with_context(|| err_msg.clone())?;
+        let deps = Deps::new(&db_pkgs).with_context(|| err_msg.clone())?;
+        for pkg in built_pkgs {
+            let pkg_name = pkg.name().with_context(|| err_msg.clone())?;
+            let Some(db_pkg) = db_pkgs.get(&pkg_name) else {
+                continue;
+            };
+            let sonames = pkg.sonames().with_context(|| err_msg.clone())?;
+            let changed_sonames: Vec<&str> = db_pkg
+                .provides
+                .iter()
+                .filter(|provide| is_soname(provide) && !sonames.contains(provide))
+                .map(String::as_str)
+                .collect();
+            if changed_sonames.is_empty() {
+                continue;
+            }
+
+            // Packages can either depend on the soname (e.g., "libfoo.so") or on
+            // the name of the package that provides the library
+            for dep in changed_sonames
+                .iter()
+                .map(|soname| soname.split('=').next().unwrap_or(soname))
+                .chain([pkg_name.as_str()])
+            {
+                if let Some(dep_pkgs) = deps.get(dep) {
+                    dependents.extend(dep_pkgs.iter().map(|dep_pkg| dep_pkg.to_string()));
+                }
+            }
+        }
+
+        // Packages that were just built do not need to be rebuilt
+        for pkg_name in built_pkgs.iter().filter_map(|pkg| pkg.name().ok()) {
+            dependents.remove(&pkg_name);
+        }
+
+        Ok(dependents)
+    }
+
+    /// Retrieves the package repository of the AUR package `pkg_name` (via the
+    /// cache of AUR clones) and prints the package meta data (.SRCINFO) of its
+    /// PKGBUILD file. Nothing is built. This is meant to inspect a package (e.g.,
+    /// its dependencies and architectures) before it is added to the current
+    /// repository
+    pub fn srcinfo<S>(&self, pkg_name: S) -> anyhow::Result<()>
+    where
+        S: AsRef<str> + Display,
+    {
+        let err_msg = format!("Cannot print .SRCINFO of AUR package {}", pkg_name);
+
+        exec_with_tmp_data!({
+            let (pkgbuild_dir, _) = self
+                .ensure_pkg_tmp_dirs()
+                .with_context(|| err_msg.clone())?;
+
+            // Split packages are stored in AUR under the name of their package
+            // base
+            let aur_data = AurData::new(
+                self.aur_url().with_context(|| err_msg.clone())?,
+                &[pkg_name.as_ref()],
+                false,
+            )
+            .with_context(|| err_msg.clone())?;
+            let pkg_base = aur_data
+                .pkg_name2base()
+                .into_iter()
+                .find(|(name, _)| *name == pkg_name.as_ref())
+                .map(|(_, pkg_base)| pkg_base.to_string())
+                .ok_or_else(|| anyhow!("Package {} not found in AUR", pkg_name))
+                .with_context(|| err_msg.clone())?;
+
+            let pkgbuild = PkgBuild::from_aur(&aur_data, Some(&[&pkg_base]), &pkgbuild_dir, false)
+                .with_context(|| err_msg.clone())?
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow!("Package repository could not be retrieved from AUR"))
+                .with_context(|| err_msg.clone())?;
+            print!("{}", pkgbuild.srcinfo().with_context(|| err_msg.clone())?);
+        });
+
+        Ok(())
+    }
+
+    /// Stores a copy of the PKGBUILD file `pkgbuild` that was used to build
+    /// packages of version `version` in the PKGBUILD archive directory of the
+    /// current repository
+    fn store_pkgbuild<S>(&self, pkgbuild: &PkgBuild, version: S) -> anyhow::Result<()>
+    where
+        S: AsRef<str> + Display,
+    {
+        let err_msg = format!(
+            "Cannot store PKGBUILD file '{}'",
+            pkgbuild.as_ref().display()
+        );
+
+        let dir = self
+            .pkgbuild_archive_dir()
+            .with_context(|| err_msg.clone())?
+            .join(format!(
+                "{}-{}",
+                pkgbuild.pkg_base().with_context(|| err_msg.clone())?,
+                version
+            ));
+        ensure_dir(&dir).with_context(|| err_msg.clone())?;
+        fs::copy(pkgbuild, dir.join(PKGBUILD_FILE_NAME)).with_context(|| err_msg)?;
+
+        Ok(())
+    }
+
     /// Unlocks the current repository. I.e., removed the corresponding lock file
     fn unlock(&self) -> anyhow::Result<()> {
         let err_msg = format!("Cannot create lock for repository {}", &self.name);
         let lock_file = self.lock_file()?;
 
-        if lock_file.exists() {
-            let pid = pid_from_file(&lock_file).with_context(|| err_msg.clone())?;
-            if pid != process::id() {
-                return Err(anyhow!(
-                    "Lock file '{}' exists: repository {} is locked by process {}",
-                    lock_file.display(),
-                    &self.name,
-                    pid
-                )
-                .context(err_msg));
-            }
+        match self.lock_holder().with_context(|| err_msg.clone())? {
+            Some(pid) if pid != process::id() => Err(anyhow!(
+                "Lock file '{}' exists: repository {} is locked by process {}",
+                lock_file.display(),
+                &self.name,
+                pid
+            )
+            .context(err_msg)),
+            Some(_) => fs::remove_file(lock_file).with_context(|| err_msg),
+            // A corrupt lock file was removed already
+            None => Ok(()),
+        }
+    }
+
+    /// Removes the lock for the chroot container of the current repository, if it
+    /// is held by the current process. The lock file does not exist anymore if
+    /// the chroot directory was removed in the meantime
+    fn unlock_chroot(&self) -> anyhow::Result<()> {
+        let err_msg = format!(
+            "Cannot remove lock for chroot container of repository {}",
+            &self.name
+        );
+        let lock_file = self.chroot_dir.join(CHROOT_REPMAN_LOCK_FILE_NAME);
+
+        if !lock_file.exists() {
+            return Ok(());
+        }
+        if pid_from_file(&lock_file).with_context(|| err_msg.clone())? == Some(process::id()) {
+            fs::remove_file(&lock_file).with_context(|| err_msg)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the lock for downloads of the current repository, if it is held
+    /// by the current process
+    fn unlock_download(&self) -> anyhow::Result<()> {
+        let err_msg = format!("Cannot remove download lock for repository {}", &self.name);
+        let download_lock_file = self.download_lock_file().with_context(|| err_msg.clone())?;
+
+        if download_lock_file.exists()
+            && pid_from_file(&download_lock_file).with_context(|| err_msg.clone())?
+                == Some(process::id())
+        {
+            fs::remove_file(&download_lock_file).with_context(|| err_msg)?;
         }
 
-        fs::remove_file(lock_file).with_context(|| err_msg)?;
         Ok(())
     }
 
-    /// Updates all packages whose names are contained in `pkg_names`. If `no_chroot`
-    /// is true, building the new packages is not done via `makepkg`, otherwise via
-    /// `makechrootpkg`. If `clean_chroot` is true, the chroot will be removed after
+    /// Unmounts the tmpfs for builds at `build_dir` and removes the mount point
+    fn unmount_build_tmpfs(&self, build_dir: &Path) -> anyhow::Result<()> {
+        let err_msg = format!(
+            "Cannot unmount tmpfs for builds of repository {}",
+            &self.name
+        );
+
+        if is_mount_point(build_dir).with_context(|| err_msg.clone())? {
+            unmount(build_dir).with_context(|| err_msg.clone())?;
+        }
+        fs::remove_dir(build_dir).with_context(|| err_msg)
+    }
+
+    /// Returns the paths of the files in the local directory of the current
+    /// repository that repman does not recognize. I.e., files that are neither
+    /// DB files, package files nor signature files. Directories (such as the
+    /// staging directory for DB changes) are not considered
+    fn untracked_files(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let (_, other_files) = self.db_and_other_files().with_context(|| {
+            format!(
+                "Cannot determine untracked files of repository {}",
+                &self.name
+            )
+        })?;
+
+        let mut files: Vec<PathBuf> = other_files
+            .iter()
+            .map(|name| self.local_dir.join(name))
+            .filter(|file| {
+                (file.is_file() || file.is_symlink())
+                    && ![SIG_SUFFIX, ARMORED_SIG_SUFFIX]
+                        .iter()
+                        .any(|suffix| file.to_string_lossy().ends_with(suffix))
+                    && Pkg::try_from(file.clone()).is_err()
+            })
+            .collect();
+        files.sort();
+
+        Ok(files)
+    }
+
+    /// Updates all packages whose names are contained in `pkg_names` (all
+    /// packages, if `pkg_names` is None) from AUR, and the packages of the
+    /// PKGBUILD files in the local directories `pkgbuild_dirs`. `opts`
+    /// controls how packages are built (e.g., if `opts.no_chroot` is true,
+    /// building the new packages is not done via `makechrootpkg`, but via
+    /// `makepkg`). If `clean_chroot` is true, the chroot will be removed after
     /// all packages have been built. If `no_confirm` is true, the user will not be
-    /// asked for confirmations.
-    pub fn update<S>(
+    /// asked for confirmations. Build durations are printed in the format
+    /// `format`. If `diff` is true, the changes of the PKGBUILD files compared to
+    /// the versions that were built before are shown, and the user is asked for
+    /// confirmation before the packages are built.
+    pub fn update<P, S>(
         &self,
         pkg_names: Option<&[S]>,
-        no_chroot: bool,
-        ignore_arch: bool,
-        force_no_version: bool,
+        pkgbuild_dirs: &[P],
+        opts: &BuildOpts,
+        force: ForceUpdate,
         clean_chroot: bool,
         no_confirm: bool,
+        format: Format,
+        diff: bool,
     ) -> anyhow::Result<()>
     where
+        P: AsRef<Path>,
         S: AsRef<str> + Display + Eq + Hash,
     {
         let err_msg = format!("Cannot update packages of repository {}", &self.name);
 
+        // With --since-commit, packages that are not tied to a specific version
+        // are candidates for an update as with --force-no-version. They are only
+        // rebuilt if their git sources have new commits, though (see below)
+        let candidates_force = if opts.since_commit && force == ForceUpdate::None {
+            ForceUpdate::NoVersion
+        } else {
+            force
+        };
+
+        let mut updated_pkgs: Vec<HistoryPkg> = vec![];
         lock!(self);
         exec_on_repo!(self, {
             if self.db_exists() {
-                // Extract names of packages that are contained in the current
-                // repository
-                let valid_pkg_names = self.valid_pkg_names(pkg_names).context(err_msg.clone())?;
-
-                // Initialize AUR information from AUR web interface. If names of to
-                // be updated packages were submitted (i.e., `pkg_names` is
-                // `Some(...)`), error messages are printed if these package could
-                // not be found in AUR. If no packages names were submitted, no
-                // messages will be printed
-                let aur_data =
-                    AurData::new(&valid_pkg_names, pkg_names.is_some()).context(err_msg.clone())?;
-
-                // Retrieve base names of packages that must be updated
-                let pkg_bases = self
-                    .pkgs_to_be_updated(&aur_data, force_no_version, no_confirm)
-                    .with_context(|| err_msg.clone())?;
-
-                if pkg_bases.is_empty() {
-                    return Ok(());
+                // If the DB is signed, all packages must be signed. Packages are
+                // only signed after an update if their previous versions were
+                // signed. Thus, unsigned packages are refused before anything is
+                // built
+                if self.sign_db {
+                    self.verify_db_pkgs_signed()
+                        .with_context(|| err_msg.clone())?;
                 }
 
-                // Execute package updates
                 exec_with_tmp_data!({
-                    if !no_chroot {
-                        // Create or update chroot container
-                        self.prepare_chroot().with_context(|| err_msg.clone())?;
-                    }
-
                     let (pkgbuild_dir, pkg_dir) = self
                         .ensure_pkg_tmp_dirs()
                         .with_context(|| err_msg.clone())?;
-                    let mut built_pkgs: Vec<Pkg> = vec![];
-
-                    for pkgbuild in PkgBuild::from_aur(&aur_data, Some(&pkg_bases), pkgbuild_dir)? {
-                        match Pkg::build(
-                            &pkgbuild,
-                            no_chroot,
-                            ignore_arch,
-                            None,
-                            self.gpg_key(),
-                            &self.local_dir,
-                            &self.chroot_dir,
-                            &pkg_dir,
-                        ) {
-                            Err(err) => {
-                                error!("{:?}", err);
+                    let mut pkgbuilds: Vec<PkgBuild> = vec![];
+
+                    // Updates from AUR. They are not determined if only PKGBUILD
+                    // files from local directories shall be updated (i.e., if
+                    // `pkg_names` is `Some(...)` but empty)
+                    if !matches!(pkg_names, Some(pkg_names) if pkg_names.is_empty()) {
+                        // Extract names of packages that are contained in the
+                        // current repository
+                        let valid_pkg_names = self
+                            .valid_pkg_names(pkg_names, no_confirm)
+                            .context(err_msg.clone())?;
+
+                        // Initialize AUR information from AUR web interface. If
+                        // names of to be updated packages were submitted (i.e.,
+                        // `pkg_names` is `Some(...)`), error messages are printed
+                        // if these package could not be found in AUR. If no
+                        // packages names were submitted, no messages will be
+                        // printed
+                        let aur_data = AurData::new(
+                            self.aur_url().with_context(|| err_msg.clone())?,
+                            &valid_pkg_names,
+                            pkg_names.is_some(),
+                        )
+                        .context(err_msg.clone())?;
+
+                        // Retrieve base names of packages that must be updated
+                        let pkg_bases = self
+                            .pkgs_to_be_updated(&aur_data, candidates_force, no_confirm)
+                            .with_context(|| err_msg.clone())?;
+
+                        if !pkg_bases.is_empty() {
+                            pkgbuilds = PkgBuild::from_aur(
+                                &aur_data,
+                                Some(&pkg_bases),
+                                &pkgbuild_dir,
+                                opts.refresh_clones,
+                            )?;
+                        }
+                    }
+
+                    // Updates from PKGBUILD files in local directories
+                    if !pkgbuild_dirs.is_empty() {
+                        pkgbuilds.extend(
+                            self.local_pkgbuilds_to_be_updated(
+                                PkgBuild::from_dirs(pkgbuild_dirs, &pkgbuild_dir)
+                                    .with_context(|| err_msg.clone())?,
+                                candidates_force,
+                                no_confirm,
+                            )
+                            .with_context(|| err_msg.clone())?,
+                        );
+                    }
+
+                    // If requested, skip PKGBUILD files whose git sources have
+                    // no new commits since their packages were built last. If
+                    // updates are forced, nothing is skipped, but the commits
+                    // are recorded nevertheless. If the commits of a PKGBUILD
+                    // file cannot be determined, its packages are built
+                    let mut vcs_commits: HashMap<String, Vec<String>> = HashMap::new();
+                    if opts.since_commit {
+                        let mut changed_pkgbuilds: Vec<PkgBuild> = vec![];
+                        for pkgbuild in pkgbuilds {
+                            let commits = match pkgbuild.vcs_source_commits() {
+                                Ok(commits) => commits,
+                                Err(err) => {
+                                    warning!("{:?}", err);
+                                    changed_pkgbuilds.push(pkgbuild);
+                                    continue;
+                                }
+                            };
+                            if commits.is_empty() {
+                                changed_pkgbuilds.push(pkgbuild);
                                 continue;
                             }
-                            Ok(pkgs) => built_pkgs.extend(pkgs),
+                            let pkg_base = pkgbuild.pkg_base().with_context(|| err_msg.clone())?;
+                            if force == ForceUpdate::None
+                                && fs::read_to_string(
+                                    self.vcs_commits_file(&pkg_base)
+                                        .with_context(|| err_msg.clone())?,
+                                )
+                                .is_ok_and(|content| content.lines().eq(commits.iter()))
+                            {
+                                msg!(
+                                    "Skipping '{}': No new commits since last build",
+                                    pkgbuild.as_ref().display()
+                                );
+                                continue;
+                            }
+                            vcs_commits.insert(pkg_base, commits);
+                            changed_pkgbuilds.push(pkgbuild);
+                        }
+                        pkgbuilds = changed_pkgbuilds;
+                    }
+
+                    if pkgbuilds.is_empty() {
+                        return Ok(());
+                    }
+
+                    // The chroot container is locked from its update until all
+                    // packages were built
+                    chroot_lock!(self, !opts.no_chroot);
+                    if !opts.no_chroot {
+                        // Create or update chroot container
+                        self.prepare_chroot(opts).with_context(|| err_msg.clone())?;
+                    }
+
+                    // If requested, show the changes of the PKGBUILD files compared to
+                    // the versions in the repository and let the user decide
+                    // whether to build them
+                    if diff {
+                        let mut confirmed_pkgbuilds: Vec<PkgBuild> = vec![];
+                        for pkgbuild in pkgbuilds {
+                            self.show_pkgbuild_diff(&pkgbuild)
+                                .with_context(|| err_msg.clone())?;
+                            if confirm(
+                                format!("Build packages from '{}'?", pkgbuild.as_ref().display()),
+                                true,
+                            )
+                            .with_context(|| err_msg.clone())?
+                            {
+                                confirmed_pkgbuilds.push(pkgbuild);
+                            }
                         }
+                        pkgbuilds = confirmed_pkgbuilds;
+                    }
+
+                    let built_pkgs = self
+                        .build_pkgs(&pkgbuilds, opts, None, &pkg_dir, format)
+                        .with_context(|| err_msg.clone())?;
+
+                    // With --nobuild, nothing was built. Thus, nothing is added
+                    // to the repository and nothing must be uploaded
+                    if opts.no_build {
+                        return Ok(());
+                    }
+
+                    // If the DB is signed, the built packages must be signed
+                    // before they are added
+                    if self.sign_db {
+                        self.verify_pkgs_signed(&built_pkgs)
+                            .with_context(|| err_msg.clone())?;
                     }
 
-                    // Add the successfully built packages to respository DB
+                    // Determine the packages that depend on changed sonames.
+                    // Failing to do so must not discard the built packages
+                    let soname_dependents = match self.soname_dependents(&built_pkgs) {
+                        Ok(dependents) => dependents,
+                        Err(err) => {
+                            warning!("{:?}", err);
+                            BTreeSet::new()
+                        }
+                    };
+
+                    // Add the successfully built packages to respository DB.
+                    // If the DB is signed, all its packages must be signed
+                    // afterwards
                     self.add_pkgs_to_db(&built_pkgs)
                         .with_context(|| err_msg.clone())?;
+                    if self.sign_db {
+                        self.verify_db_pkgs_signed()
+                            .with_context(|| err_msg.clone())?;
+                    }
+                    report_soname_dependents(&soname_dependents);
+                    self.record_vcs_commits(&pkgbuilds, &built_pkgs, &vcs_commits);
+                    updated_pkgs = built_pkgs.iter().flat_map(HistoryPkg::try_from).collect();
 
                     if clean_chroot {
                         self.remove_chroot_dir().with_context(|| err_msg.clone())?;
@@ -1538,22 +4334,227 @@ impl Repo {
             }
         });
 
-        Ok(())
-    }
+        // The history is only recorded after the upload was successful
+        self.record_history("update", updated_pkgs);
 
-    /// Uploads the files of the current repository from a local directory, if the
-    /// repository is remote. If the function is called for a local repository, it
-    /// does not do anything
-    fn upload(&self) -> anyhow::Result<()> {
-        self.server.upload_repo(&self.local_dir)
+        Ok(())
+    }
+
+    /// Path of the file where the commits of the git sources of the PKGBUILD file
+    /// of package base `pkg_base` are stored after it was built. Normally, that
+    /// is `~/.cache/repman/vcs/<REPOSITORY>/<PKG_BASE>`
+    fn vcs_commits_file<S>(&self, pkg_base: S) -> anyhow::Result<PathBuf>
+    where
+        S: AsRef<str>,
+    {
+        Ok(cache_dir()
+            .with_context(|| {
+                format!(
+                    "Cannot assemble path of VCS commits file for repository {}",
+                    &self.name
+                )
+            })?
+            .join(VCS_SUB_PATH)
+            .join(&self.name)
+            .join(pkg_base.as_ref()))
+    }
+
+    /// Executes `update` on a staging copy of the DB files of the current
+    /// repository. `update` gets the path of the staged DB archive and is
+    /// supposed to change the DB with repo-add or repo-remove. Afterwards, the
+    /// changed DB files are moved into place by renaming them. Since renaming is
+    /// atomic, clients that read the DB at the same time (e.g., pacman in case of
+    /// a repository in the local file system) never see a partially written DB.
+    /// The archives are moved before the symbolic links to them
+    fn update_db_staged<F>(&self, update: F) -> anyhow::Result<()>
+    where
+        F: FnOnce(&Path) -> anyhow::Result<()>,
+    {
+        let err_msg = format!("Cannot update DB of repository {}", &self.name);
+
+        // The staging directory must be located in the same file system as the
+        // repository directory. Otherwise, the files could not be renamed. A
+        // staging directory from an interrupted run is removed first
+        let staging_dir = self.local_dir.join(DB_STAGING_DIR_NAME);
+        if staging_dir.exists() {
+            fs::remove_dir_all(&staging_dir).with_context(|| err_msg.clone())?;
+        }
+        fs::create_dir(&staging_dir).with_context(|| err_msg.clone())?;
+        defer! {
+            if staging_dir.exists() {
+                if let Err(err) = fs::remove_dir_all(&staging_dir) {
+                    warning!(
+                        "Cannot remove staging directory '{}': {:?}",
+                        staging_dir.display(),
+                        err
+                    );
+                }
+            }
+        }
+
+        // Copy DB files to staging directory. Symbolic links are copied as links
+        let (db_files, _) = self.db_and_other_files().with_context(|| err_msg.clone())?;
+        for db_file in &db_files {
+            let file = self.local_dir.join(db_file);
+            if file.is_symlink() {
+                symlink(
+                    fs::read_link(&file).with_context(|| err_msg.clone())?,
+                    staging_dir.join(db_file),
+                )
+                .with_context(|| err_msg.clone())?;
+            } else {
+                fs::copy(&file, staging_dir.join(db_file)).with_context(|| err_msg.clone())?;
+            }
+        }
+
+        update(
+            &staging_dir.join(
+                self.db_archive_file()
+                    .file_name()
+                    .unwrap_or_else(|| panic!("Cannot extract file name of DB archive")),
+            ),
+        )
+        .with_context(|| err_msg.clone())?;
+
+        // Move changed DB files into place: Archives and signatures first, links
+        // afterwards
+        let mut staged_files: Vec<PathBuf> = fs::read_dir(&staging_dir)
+            .with_context(|| err_msg.clone())?
+            .flatten()
+            .map(|entry| entry.path())
+            .collect();
+        staged_files.sort_by_key(|file| file.is_symlink());
+        let mut staged_names: HashSet<String> = HashSet::new();
+        for file in &staged_files {
+            let name = file
+                .file_name()
+                .unwrap_or_else(|| panic!("Cannot extract file name of staged DB file"));
+            fs::rename(file, self.local_dir.join(name)).with_context(|| err_msg.clone())?;
+            staged_names.insert(name.to_string_lossy().to_string());
+        }
+
+        // Remove DB files that were removed by repo-add or repo-remove
+        for db_file in db_files {
+            if !staged_names.contains(&db_file) {
+                fs::remove_file(self.local_dir.join(db_file)).with_context(|| err_msg.clone())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Uploads the files of the current repository from a local directory, if the
+    /// repository is remote. If the function is called for a local repository, it
+    /// does not do anything. Files that did not change compared to `snapshot` are
+    /// not transferred
+    fn upload(&self, snapshot: &Snapshot) -> anyhow::Result<()> {
+        if !self.is_remote() {
+            return Ok(());
+        }
+
+        let unchanged_files = snapshot.unchanged_files(&self.local_dir).with_context(|| {
+            format!(
+                "Cannot determine unchanged files of repository {}",
+                &self.name
+            )
+        })?;
+        self.upload_to(self.server.as_ref(), &unchanged_files)
+    }
+
+    /// Uploads the files of the current repository from a local directory to
+    /// `server`. Files with names contained in `unchanged_files` are not
+    /// transferred.
+    /// The upload happens in two stages to make sure that the remote repository DB
+    /// never references package files that are not uploaded yet: First, all files
+    /// except the DB files are uploaded without deleting anything remotely.
+    /// Second, the DB files are uploaded and obsolete files are deleted remotely
+    fn upload_to(&self, server: &dyn Server, unchanged_files: &[String]) -> anyhow::Result<()> {
+        let err_msg = format!("Cannot upload repository {}", &self.name);
+
+        let (db_files, other_files) = self.db_and_other_files().with_context(|| err_msg.clone())?;
+
+        // Files that would be deleted remotely are displayed before anything is
+        // uploaded, and the user must confirm the deletion. This protects the
+        // remote repository from a broken local copy
+        if self.confirm_deletions {
+            let files = server
+                .files_to_be_deleted(&self.local_dir)
+                .with_context(|| err_msg.clone())?;
+            if !files.is_empty() {
+                msg!("The upload deletes these files remotely:");
+                for file in &files {
+                    println!("  {}", file);
+                }
+                if !confirm("Continue with the upload?", false).with_context(|| err_msg.clone())? {
+                    return Err(anyhow!(
+                        "Upload cancelled. The local copy of the repository can be uploaded later with 'repman push'"
+                    )
+                    .context(err_msg));
+                }
+            }
+        }
+
+        // Stage 1: Upload changed files except DB files
+        server
+            .upload_repo(
+                &self.local_dir,
+                &[unchanged_files, db_files.as_slice()].concat(),
+                false,
+            )
+            .with_context(|| err_msg.clone())?;
+
+        // Stage 2: Upload DB files and delete obsolete files. All other files are
+        // up-to-date after stage 1
+        server
+            .upload_repo(&self.local_dir, &other_files, true)
+            .with_context(|| err_msg)
+    }
+
+    /// Returns the names of the files in the local directory of the current
+    /// repository, split into the files that belong to the repository DB (i.e.,
+    /// DB and files archives, corresponding links and signatures) and all other
+    /// files
+    fn db_and_other_files(&self) -> anyhow::Result<(Vec<String>, Vec<String>)> {
+        let err_msg = format!(
+            "Cannot determine DB files of repository {} in '{}'",
+            &self.name,
+            self.local_dir.display()
+        );
+
+        let mut db_files: Vec<String> = vec![];
+        let mut other_files: Vec<String> = vec![];
+        for entry in fs::read_dir(&self.local_dir).with_context(|| err_msg.clone())? {
+            let entry = entry.with_context(|| err_msg.clone())?;
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if [DB_SUFFIX, FILES_SUFFIX].iter().any(|suffix| {
+                let db_file_name = format!("{}{}", &self.db_name, suffix);
+                name == db_file_name || name.starts_with(&(db_file_name + "."))
+            }) {
+                db_files.push(name);
+            } else {
+                other_files.push(name);
+            }
+        }
+
+        Ok((db_files, other_files))
     }
 
     /// Determines package names that are relevant for a processing step (such as
     /// removing, updating or signing these packages). If `pkg_names` is None, the
     /// names of all packages contained in the current repository are returned.
     /// Otherwise, only the names are returned that are contained in `pkg_names`
-    /// and where the corresponding package is contained in the current repository
-    fn valid_pkg_names<'a, S>(&'a self, pkg_names: Option<&'a [S]>) -> anyhow::Result<Vec<&str>>
+    /// and where the corresponding package is contained in the current repository.
+    /// Names that contain glob metacharacters (such as "python-*") are patterns.
+    /// They are expanded against the names of the packages of the repository. To
+    /// guard against accidental over-matching, the matching packages are listed
+    /// and (unless `no_confirm` is true) the user is asked for confirmation
+    fn valid_pkg_names<'a, S>(
+        &'a self,
+        pkg_names: Option<&'a [S]>,
+        no_confirm: bool,
+    ) -> anyhow::Result<Vec<&str>>
     where
         S: AsRef<str> + Display,
     {
@@ -1562,6 +4563,44 @@ impl Repo {
         match pkg_names {
             Some(pkg_names) => {
                 for pkg_name in pkg_names {
+                    if is_glob_pattern(pkg_name.as_ref()) {
+                        let pattern = glob::Pattern::new(pkg_name.as_ref())
+                            .with_context(|| format!("Pattern '{}' is invalid", pkg_name))
+                            .with_context(|| err_msg)?;
+                        let matching_pkg_names: Vec<&str> = self
+                            .db_pkgs()
+                            .with_context(|| err_msg)?
+                            .names()
+                            .map(String::as_str)
+                            .filter(|name| pattern.matches(name))
+                            .collect();
+                        if matching_pkg_names.is_empty() {
+                            error!(
+                                "No package of repository {} matches '{}'",
+                                &self.name, pkg_name
+                            );
+                            continue;
+                        }
+                        msg!(
+                            "'{}' matches the following package(s): {}",
+                            pkg_name,
+                            matching_pkg_names.join(", ")
+                        );
+                        if no_confirm
+                            || confirm(
+                                format!("Process these {} package(s)?", matching_pkg_names.len()),
+                                false,
+                            )
+                            .with_context(|| err_msg)?
+                        {
+                            for matching_pkg_name in matching_pkg_names {
+                                if !valid_pkg_names.contains(&matching_pkg_name) {
+                                    valid_pkg_names.push(matching_pkg_name);
+                                }
+                            }
+                        }
+                        continue;
+                    }
                     if self.contains_pkg(pkg_name).with_context(|| err_msg)? {
                         valid_pkg_names.push(pkg_name.as_ref());
                         continue;
@@ -1581,4 +4620,682 @@ impl Repo {
 
         Ok(valid_pkg_names)
     }
+
+    /// Rebuilds the package `pkg_name` of the current repository to verify that
+    /// its build is reproducible. The package is built from the PKGBUILD file in
+    /// `pkgbuild_dir`, if that is `Some(...)`, and from AUR otherwise. `opts`
+    /// controls how the package is built. To be able to reproduce the package
+    /// file, the build date of the package in the repository is used as
+    /// SOURCE_DATE_EPOCH. The repository is not changed: The built package file
+    /// is compared with the package file in the repository, and the differences
+    /// of the meta data and of the contained files are reported. An error is
+    /// returned if the files differ
+    pub fn verify_build<P, S>(
+        &self,
+        pkg_name: S,
+        pkgbuild_dir: Option<P>,
+        opts: &BuildOpts,
+    ) -> anyhow::Result<()>
+    where
+        P: AsRef<Path>,
+        S: AsRef<str> + Display,
+    {
+        let err_msg = format!(
+            "Cannot verify build of package {} of repository {}",
+            pkg_name, &self.name
+        );
+
+        // The repository is not changed and thus not uploaded. Nevertheless, a
+        // (write) lock is required since the chroot container is updated
+        lock!(self);
+        self.download().with_context(|| err_msg.clone())?;
+
+        let pkg = self.pkg(&pkg_name).with_context(|| err_msg.clone())?;
+        let opts = &BuildOpts {
+            source_date_epoch: self
+                .db_pkgs()
+                .with_context(|| err_msg.clone())?
+                .get(pkg_name.as_ref())
+                .map(|db_pkg| db_pkg.build_date.timestamp()),
+            ..opts.clone()
+        };
+
+        exec_with_tmp_data!({
+            let (tmp_pkgbuild_dir, pkg_dir) = self
+                .ensure_pkg_tmp_dirs()
+                .with_context(|| err_msg.clone())?;
+
+            let pkgbuild = match pkgbuild_dir {
+                Some(pkgbuild_dir) => PkgBuild::from_dirs(&[pkgbuild_dir], &tmp_pkgbuild_dir),
+                None => {
+                    let pkg_base = self
+                        .db_pkgs()
+                        .with_context(|| err_msg.clone())?
+                        .get(pkg_name.as_ref())
+                        .map(|db_pkg| db_pkg.base.clone())
+                        .unwrap_or_else(|| pkg_name.to_string());
+                    PkgBuild::from_aur(
+                        &AurData::new(
+                            self.aur_url().with_context(|| err_msg.clone())?,
+                            &[&pkg_base],
+                            true,
+                        )
+                        .with_context(|| err_msg.clone())?,
+                        Some(&[&pkg_base]),
+                        &tmp_pkgbuild_dir,
+                        opts.refresh_clones,
+                    )
+                }
+            }
+            .with_context(|| err_msg.clone())?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("No PKGBUILD file found"))
+            .with_context(|| err_msg.clone())?;
+
+            let version = pkgbuild.version().with_context(|| err_msg.clone())?;
+            let pkg_version = pkg.version().with_context(|| err_msg.clone())?;
+            if version != pkg_version {
+                warning!(
+                    "PKGBUILD file has version {}, but repository contains version {} of package {}",
+                    version,
+                    pkg_version,
+                    pkg_name
+                );
+            }
+
+            // Build package without adding it to the repository
+            if opts.no_chroot {
+                pkgbuild
+                    .build_with_makepkg(opts, &pkg_dir, None)
+                    .with_context(|| err_msg.clone())?;
+            } else {
+                chroot_lock!(self, true);
+                self.prepare_chroot(opts).with_context(|| err_msg.clone())?;
+                pkgbuild
+                    .build_with_makechrootpkg(
+                        opts,
+                        &self.local_dir,
+                        &self.chroot_dir,
+                        &pkg_dir,
+                        None,
+                    )
+                    .with_context(|| err_msg.clone())?;
+            }
+            let built_pkg = fs::read_dir(&pkg_dir)
+                .with_context(|| err_msg.clone())?
+                .flatten()
+                .filter_map(|entry| Pkg::try_from(entry.path()).ok())
+                .find(|built_pkg| {
+                    built_pkg
+                        .name()
+                        .is_ok_and(|built_pkg_name| built_pkg_name == pkg_name.as_ref())
+                })
+                .ok_or_else(|| anyhow!("Package {} was not built", pkg_name))
+                .with_context(|| err_msg.clone())?;
+
+            if fs::read(&built_pkg).with_context(|| err_msg.clone())?
+                != fs::read(&pkg).with_context(|| err_msg.clone())?
+            {
+                // Report differences of meta data and of the contained files.
+                // Lines that only exist in the repository package are marked
+                // with "-", lines that only exist in the built package with "+"
+                let diff = |old: Vec<String>, new: Vec<String>| -> Vec<String> {
+                    old.iter()
+                        .filter(|line| !new.contains(line))
+                        .map(|line| format!("    - {}", line))
+                        .chain(
+                            new.iter()
+                                .filter(|line| !old.contains(line))
+                                .map(|line| format!("    + {}", line)),
+                        )
+                        .collect()
+                };
+                msg!("Differences of package meta data:");
+                for line in diff(
+                    pkg.meta_data().with_context(|| err_msg.clone())?,
+                    built_pkg.meta_data().with_context(|| err_msg.clone())?,
+                ) {
+                    println!("{}", line);
+                }
+                msg!("Differences of contained files:");
+                for line in diff(
+                    pkg.file_list().with_context(|| err_msg.clone())?,
+                    built_pkg.file_list().with_context(|| err_msg.clone())?,
+                ) {
+                    println!("{}", line);
+                }
+
+                return Err(
+                    anyhow!("Built package file differs from package file in repository")
+                        .context(err_msg),
+                );
+            }
+        });
+
+        msg!("Build of package {} is reproducible", pkg_name);
+
+        Ok(())
+    }
+
+    /// Verifies the signature of the DB of the current repository if a trusted
+    /// key is configured for the repository. If the DB does not exist (yet),
+    /// there is nothing to verify
+    fn verify_db(&self) -> anyhow::Result<()> {
+        let Some(db_key) = &self.db_key else {
+            return Ok(());
+        };
+        if !self.db_exists() {
+            return Ok(());
+        }
+
+        verify_file(self.db_archive_file(), db_key).with_context(|| {
+            format!(
+                "DB of repository {} could not be verified. It might have been tampered with",
+                &self.name
+            )
+        })
+    }
+
+    /// Verifies that all packages of `pkgs` are signed before they are added to
+    /// the current repository, whose packages shall be signed. This makes sure
+    /// that clients which require signed packages can install all of them
+    fn verify_pkgs_signed(&self, pkgs: &[Pkg]) -> anyhow::Result<()> {
+        let unsigned_pkg_names = pkgs
+            .iter()
+            .filter(|pkg| !pkg.is_signed())
+            .map(Pkg::name)
+            .collect::<anyhow::Result<Vec<String>>>()
+            .with_context(|| {
+                format!(
+                    "Cannot verify that the packages for repository {} are signed",
+                    &self.name
+                )
+            })?;
+        if !unsigned_pkg_names.is_empty() {
+            return Err(anyhow!(
+                "{} package(s) are not signed: {}. They are not added to repository {}",
+                unsigned_pkg_names.len(),
+                unsigned_pkg_names.join(", "),
+                &self.name
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Verifies that all packages of the DB of the current repository are signed.
+    /// The DB is read from the file directly, since the cached DB data might be
+    /// outdated
+    fn verify_db_pkgs_signed(&self) -> anyhow::Result<()> {
+        if !self.db_exists() {
+            return Ok(());
+        }
+
+        let unsigned_db_pkg_names: Vec<String> =
+            repodb_parser::parse(self.db_archive_file().as_path())
+                .with_context(|| {
+                    format!(
+                        "Cannot verify that the packages of repository {} are signed",
+                        &self.name
+                    )
+                })?
+                .packages()
+                .filter(|db_pkg| !self.is_db_pkg_signed(db_pkg))
+                .map(|db_pkg| db_pkg.name.clone())
+                .collect();
+        if !unsigned_db_pkg_names.is_empty() {
+            return Err(anyhow!(
+                "The DB of repository {} contains {} unsigned package(s): {}. Sign them with 'repman sign'",
+                &self.name,
+                unsigned_db_pkg_names.len(),
+                unsigned_db_pkg_names.join(", ")
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks that the sources of all PKGBUILD files `pkgbuilds` are protected by
+/// checksums (see `PkgBuild::missing_checksums()`). Otherwise, the sources
+/// without checksums are listed and an error is returned
+fn check_checksums(pkgbuilds: &[PkgBuild]) -> anyhow::Result<()> {
+    let mut failed = false;
+    for pkgbuild in pkgbuilds {
+        let missing = pkgbuild.missing_checksums()?;
+        if missing.is_empty() {
+            continue;
+        }
+        failed = true;
+        error!("{}:", pkgbuild.as_ref().display());
+        for msg in missing {
+            error!("  {}", msg);
+        }
+    }
+    if failed {
+        return Err(anyhow!(
+            "Sources without checksums found (see above). No packages were built"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Returns true if `name` contains glob metacharacters. Such names cannot be
+/// package names, since these must only consist of alphanumeric characters and
+/// the characters "@._+-"
+fn is_glob_pattern(name: &str) -> bool {
+    name.contains(['*', '?', '['])
+}
+
+/// Reads the lines of a pacman.conf file from `reader` and replaces Include
+/// directives by the lines of the included files (glob patterns are supported,
+/// as in pacman.conf). Included files can contain Include directives as well.
+/// `depth` is the current nesting level of includes. It is used to detect
+/// cyclic includes
+fn expand_pacman_conf_includes<R>(reader: R, depth: usize) -> anyhow::Result<Vec<String>>
+where
+    R: BufRead,
+{
+    lazy_static! {
+        static ref RE_INCLUDE: Regex = Regex::new(r"^\s*Include\s*=\s*(.+?)\s*$").unwrap();
+    }
+
+    if depth > PACMAN_CONF_MAX_INCLUDE_DEPTH {
+        return Err(anyhow!(
+            "Include directives are nested too deeply (cyclic includes?)"
+        ));
+    }
+
+    let mut lines: Vec<String> = vec![];
+    for line in reader.lines() {
+        let line = line?;
+
+        let Some(pattern) = RE_INCLUDE
+            .captures(&line)
+            .and_then(|captures| captures.get(1))
+        else {
+            lines.push(line);
+            continue;
+        };
+
+        let err_msg = format!("Cannot include '{}'", pattern.as_str());
+        let mut files: Vec<PathBuf> = glob(pattern.as_str())
+            .with_context(|| err_msg.clone())?
+            .flatten()
+            .filter(|file| file.is_file())
+            .collect();
+        if files.is_empty() {
+            return Err(anyhow!("No file matches '{}'", pattern.as_str()).context(err_msg));
+        }
+        files.sort();
+        for file in files {
+            lines.extend(
+                expand_pacman_conf_includes(
+                    BufReader::new(File::open(&file).with_context(|| err_msg.clone())?),
+                    depth + 1,
+                )
+                .with_context(|| err_msg.clone())?,
+            );
+        }
+    }
+
+    Ok(lines)
+}
+
+/// Displays a warning with the packages `dependents` that might need to be
+/// rebuilt since the soname of a shared library they depend on changed
+fn report_soname_dependents(dependents: &BTreeSet<String>) {
+    if dependents.is_empty() {
+        return;
+    }
+    warning!(
+        "The soname of libraries changed. The following packages depend on them and might need to be rebuilt: {}",
+        dependents
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<&str>>()
+            .join(", ")
+    );
+}
+
+/// Returns the names of the configured repositories other than `name` whose
+/// server URL refers to the local directory `local_dir`. Since this is only
+/// used to warn about misconfigurations, repositories whose configuration
+/// cannot be evaluated are ignored
+fn repos_with_local_dir<S>(name: S, local_dir: &Path) -> Vec<String>
+where
+    S: AsRef<str>,
+{
+    let Ok(local_dir) = fs::canonicalize(local_dir) else {
+        return vec![];
+    };
+    let Ok(cfg_repos) = cfg::repos() else {
+        return vec![];
+    };
+
+    cfg_repos
+        .iter()
+        .filter(|(repo_name, _)| repo_name.as_str() != name.as_ref())
+        .filter_map(|(repo_name, cfg_repo)| {
+            let url = Url::parse(cfg_repo.server.first()?).ok()?;
+            (url.scheme() == "file"
+                && fs::canonicalize(url.path()).is_ok_and(|dir| dir == local_dir))
+            .then(|| repo_name.clone())
+        })
+        .collect()
+}
+
+/// Parses the server URLs of the configuration `cfg_repo` of repository `name`.
+/// The first URL is the one of the primary server
+fn server_urls<S>(name: S, cfg_repo: &cfg::CfgRepo) -> anyhow::Result<Vec<Url>>
+where
+    S: Display,
+{
+    cfg_repo
+        .server
+        .iter()
+        .map(|url| server::parse_url(url))
+        .collect::<Result<Vec<Url>, _>>()
+        .with_context(|| format!("Server URL of repository {} could not be parsed", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, os::unix::fs::PermissionsExt, rc::Rc, sync::Once};
+
+    /// Server that does not transfer anything but records the calls of the
+    /// Server trait methods
+    struct FakeServer {
+        calls: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl Server for FakeServer {
+        fn is_remote(&self) -> bool {
+            true
+        }
+
+        fn download_repo(&self, _local_dir: &Path) -> anyhow::Result<()> {
+            self.calls.borrow_mut().push("download_repo".to_string());
+            Ok(())
+        }
+
+        fn download_db(&self, _local_dir: &Path, db_name: &str) -> anyhow::Result<()> {
+            self.calls
+                .borrow_mut()
+                .push(format!("download_db {}", db_name));
+            Ok(())
+        }
+
+        fn upload_repo(
+            &self,
+            _local_dir: &Path,
+            excluded_files: &[String],
+            delete: bool,
+        ) -> anyhow::Result<()> {
+            let mut excluded_files = excluded_files.to_vec();
+            excluded_files.sort();
+            self.calls.borrow_mut().push(format!(
+                "upload_repo [{}] {}",
+                excluded_files.join(", "),
+                delete
+            ));
+            Ok(())
+        }
+    }
+
+    /// Script that replaces repo-add and repo-remove in tests. It does not change
+    /// the DB, but records its name and the number of packages it was called
+    /// with in the file `db-tool-calls` of the repository directory (i.e., the
+    /// parent of the staging directory that contains the DB)
+    const FAKE_DB_TOOL: &str = r#"#!/bin/sh
+while [ "${1#-}" != "$1" ]; do shift; done
+db="$1"
+shift
+echo "$(basename "$0") $#" >> "$(dirname "$db")/../db-tool-calls"
+"#;
+
+    /// Redirects the cache directory (which contains the local copies of remote
+    /// repositories and the lock files) to a temporary directory, and replaces
+    /// repo-add and repo-remove by `FAKE_DB_TOOL`
+    fn init_test_env() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            let test_dir = env::temp_dir().join(format!("repman-test-{}", process::id()));
+            env::set_var("XDG_CACHE_HOME", &test_dir);
+
+            let bin_dir = test_dir.join("bin");
+            fs::create_dir_all(&bin_dir).unwrap();
+            for tool in ["repo-add", "repo-remove"] {
+                fs::write(bin_dir.join(tool), FAKE_DB_TOOL).unwrap();
+                fs::set_permissions(bin_dir.join(tool), fs::Permissions::from_mode(0o755)).unwrap();
+            }
+            let mut path = OsString::from(bin_dir);
+            path.push(":");
+            path.push(env::var_os("PATH").unwrap_or_default());
+            env::set_var("PATH", path);
+        });
+    }
+
+    /// Creates a repository with name `name` and the configuration `cfg` that
+    /// uses a fake server. The calls of the fake server are recorded in the
+    /// returned vector
+    fn fake_repo(name: &str, cfg: &str) -> (Repo, Rc<RefCell<Vec<String>>>) {
+        init_test_env();
+        let calls = Rc::new(RefCell::new(vec![]));
+        let repo = Repo::with_server(
+            name,
+            toml::from_str(cfg).unwrap(),
+            Box::new(FakeServer {
+                calls: Rc::clone(&calls),
+            }),
+        )
+        .unwrap();
+        (repo, calls)
+    }
+
+    /// Creates the DB of `repo` (incl. the link to the DB archive) with entries
+    /// for the packages `pkgs`, which are given as pairs of the name and the
+    /// version of a package. The package files are not created
+    fn create_db(repo: &Repo, pkgs: &[(&str, &str)]) {
+        let db_dir = repo.local_dir.join("db-content");
+        for (name, version) in pkgs {
+            let pkg_dir = db_dir.join(format!("{}-{}", name, version));
+            fs::create_dir_all(&pkg_dir).unwrap();
+            fs::write(
+                pkg_dir.join("desc"),
+                format!(
+                    "%FILENAME%\n{}-{}-x86_64.pkg.tar.zst\n\n%NAME%\n{}\n\n%BASE%\n{}\n\n%VERSION%\n{}\n\n%ARCH%\nx86_64\n",
+                    name, version, name, name, version
+                ),
+            )
+            .unwrap();
+        }
+        // The entries of the archive must not contain "." as directory, since
+        // the DB parser requires a file name for each entry
+        let db_archive_name = repo.db_name.clone() + DB_SUFFIX + ".tar.gz";
+        let mut args: Vec<OsString> = vec![
+            "-czf".into(),
+            repo.local_dir.join(&db_archive_name).into(),
+            "-C".into(),
+            db_dir.clone().into(),
+        ];
+        args.extend(
+            pkgs.iter()
+                .map(|(name, version)| format!("{}-{}", name, version).into()),
+        );
+        duct::cmd("tar", args).run().unwrap();
+        fs::remove_dir_all(&db_dir).unwrap();
+        symlink(
+            &db_archive_name,
+            repo.local_dir.join(repo.db_name.clone() + DB_SUFFIX),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn download_from_server() {
+        let (repo, calls) = fake_repo(
+            "test-download",
+            r#"
+            Server = "https://example.org/test-download"
+            SignDB = false
+            "#,
+        );
+
+        repo.download().unwrap();
+
+        assert_eq!(*calls.borrow(), vec!["download_repo"]);
+        assert!(!repo.download_lock_file().unwrap().exists());
+    }
+
+    #[test]
+    fn upload_db_files_last() {
+        let (repo, calls) = fake_repo(
+            "test-upload",
+            r#"
+            Server = "https://example.org/test-upload"
+            SignDB = false
+            "#,
+        );
+        for file in [
+            "test-upload.db",
+            "test-upload.db.tar.gz",
+            "test-upload.files",
+            "test-upload.files.tar.gz",
+            "pkg-1.0-1-x86_64.pkg.tar.zst",
+        ] {
+            File::create(repo.local_dir.join(file)).unwrap();
+        }
+
+        repo.upload(&Snapshot::default()).unwrap();
+
+        assert_eq!(
+            *calls.borrow(),
+            vec![
+                "upload_repo [test-upload.db, test-upload.db.tar.gz, test-upload.files, test-upload.files.tar.gz] false",
+                "upload_repo [pkg-1.0-1-x86_64.pkg.tar.zst] true",
+            ]
+        );
+    }
+
+    #[test]
+    fn remove_db_sig_files_of_db_name() {
+        let (repo, _) = fake_repo(
+            "test-db-name",
+            r#"
+            DBName = "custom"
+            Server = "https://example.org/test-db-name"
+            SignDB = true
+            "#,
+        );
+        for file in [
+            "custom.db.tar.gz",
+            "custom.db.tar.gz.sig",
+            "custom.files.tar.gz.sig",
+            "test-db-name.db.tar.gz.sig",
+        ] {
+            File::create(repo.local_dir.join(file)).unwrap();
+        }
+
+        repo.remove_db_sig_files().unwrap();
+
+        assert_eq!(repo.db_name, "custom");
+        assert!(repo.local_dir.join("custom.db.tar.gz").exists());
+        assert!(!repo.local_dir.join("custom.db.tar.gz.sig").exists());
+        assert!(!repo.local_dir.join("custom.files.tar.gz.sig").exists());
+        assert!(repo.local_dir.join("test-db-name.db.tar.gz.sig").exists());
+    }
+
+    #[test]
+    fn list_after_db_download() {
+        let (repo, calls) = fake_repo(
+            "test-list",
+            r#"
+            Server = "https://example.org/test-list"
+            SignDB = false
+            "#,
+        );
+        create_db(&repo, &[("foo", "1.0-1"), ("bar", "2.0-1")]);
+        File::create(repo.local_dir.join("foo-1.0-1-x86_64.pkg.tar.zst.sig")).unwrap();
+
+        repo.list(Format::Json, None).unwrap();
+
+        assert_eq!(*calls.borrow(), vec!["download_db test-list"]);
+        let db_pkgs = repo.db_pkgs().unwrap();
+        assert!(repo.is_db_pkg_signed(db_pkgs.get("foo").unwrap()));
+        assert!(!repo.is_db_pkg_signed(db_pkgs.get("bar").unwrap()));
+    }
+
+    #[test]
+    fn push_refuses_incomplete_copy() {
+        let (repo, calls) = fake_repo(
+            "test-push",
+            r#"
+            Server = "https://example.org/test-push"
+            SignDB = false
+            "#,
+        );
+        create_db(&repo, &[("foo", "1.0-1"), ("bar", "2.0-1")]);
+        File::create(repo.local_dir.join("foo-1.0-1-x86_64.pkg.tar.zst")).unwrap();
+
+        assert!(repo.push().is_err());
+        assert!(calls.borrow().is_empty());
+
+        File::create(repo.local_dir.join("bar-2.0-1-x86_64.pkg.tar.zst")).unwrap();
+
+        repo.push().unwrap();
+        assert_eq!(calls.borrow().len(), 2);
+    }
+
+    #[test]
+    fn verify_signed_db_pkgs() {
+        let (repo, _) = fake_repo(
+            "test-signed",
+            r#"
+            Server = "https://example.org/test-signed"
+            SignDB = true
+            "#,
+        );
+        create_db(&repo, &[("foo", "1.0-1"), ("bar", "2.0-1")]);
+        File::create(repo.local_dir.join("foo-1.0-1-x86_64.pkg.tar.zst.sig")).unwrap();
+
+        assert!(repo.verify_db_pkgs_signed().is_err());
+
+        File::create(repo.local_dir.join("bar-2.0-1-x86_64.pkg.tar.zst.sig")).unwrap();
+
+        repo.verify_db_pkgs_signed().unwrap();
+    }
+
+    #[test]
+    fn add_and_remove_pkgs_with_one_call() {
+        let (repo, _) = fake_repo(
+            "test-batch",
+            r#"
+            Server = "https://example.org/test-batch"
+            SignDB = false
+            "#,
+        );
+        create_db(&repo, &[("qux", "0.1-1")]);
+        let pkgs: Vec<Pkg> = ["foo", "bar", "baz"]
+            .iter()
+            .map(|name| {
+                let file = repo
+                    .local_dir
+                    .join(format!("{}-1.0-1-x86_64.pkg.tar.zst", name));
+                File::create(&file).unwrap();
+                Pkg::try_from(file).unwrap()
+            })
+            .collect();
+
+        repo.add_pkgs_to_db(&pkgs).unwrap();
+        repo.remove_pkgs_from_db(&["foo", "bar"]).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(repo.local_dir.join("db-tool-calls")).unwrap(),
+            "repo-add 3\nrepo-remove 2\n"
+        );
+    }
 }
@@ -5,13 +5,21 @@
 use crate::internal::common::*;
 use anyhow::{anyhow, Context};
 use arch_msgs::*;
+use const_format::concatcp;
 use duct::cmd;
+use regex::escape;
 use std::{
     borrow::Cow,
+    cell::Cell,
+    collections::HashMap,
+    env,
     ffi::{OsStr, OsString},
+    fs,
     os::unix::ffi::OsStrExt,
-    path::Path,
+    path::{Path, PathBuf},
     str::from_utf8,
+    thread,
+    time::{Duration, SystemTime},
 };
 use url::Url;
 
@@ -23,9 +31,106 @@ pub trait Server {
     fn download_repo(&self, _local_dir: &Path) -> anyhow::Result<()> {
         Ok(())
     }
-    fn upload_repo(&self, _local_dir: &Path) -> anyhow::Result<()> {
+    /// Checks whether uploads are possible, i.e. whether the required tools
+    /// are installed and whether credentials are configured. This allows to
+    /// fail before a repository is changed locally instead of only when the
+    /// changes are uploaded
+    fn check_upload(&self) -> anyhow::Result<()> {
         Ok(())
     }
+    /// Downloads only the files of the DB with name `db_name` and the signature
+    /// files to `local_dir`, but not the package files. This is sufficient for
+    /// operations that only read the DB
+    fn download_db(&self, _local_dir: &Path, _db_name: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+    /// Uploads the content of `local_dir`. The files with names contained in
+    /// `excluded_files` are not transferred. Since they exist locally, they are
+    /// not deleted remotely either. Files that do not exist locally are only
+    /// deleted remotely if `delete` is true
+    fn upload_repo(
+        &self,
+        _local_dir: &Path,
+        _excluded_files: &[String],
+        _delete: bool,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+    /// Returns the names of the files that would be deleted remotely if the
+    /// content of `local_dir` was uploaded with `delete` being true. Nothing is
+    /// transferred or deleted (i.e., a dry run is done)
+    fn files_to_be_deleted(&self, _local_dir: &Path) -> anyhow::Result<Vec<String>> {
+        Ok(vec![])
+    }
+    /// Returns the URL of the fallback server that served the last download, or
+    /// `None` if it was served by the primary server
+    fn fallback_url(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Sizes and modification times of the files of a local repository directory.
+/// Comparing a snapshot taken after downloading a repository with the state of
+/// the directory before uploading it allows to determine the files that did not
+/// change in between
+#[derive(Default)]
+pub struct Snapshot(HashMap<String, (u64, SystemTime)>);
+
+impl Snapshot {
+    /// Takes a snapshot of the files in `dir`
+    pub fn new(dir: &Path) -> anyhow::Result<Snapshot> {
+        let mut snapshot = Snapshot::default();
+        for (name, size, modified) in file_attrs(dir)? {
+            snapshot.0.insert(name, (size, modified));
+        }
+        Ok(snapshot)
+    }
+
+    /// Returns the names of the files in `dir` whose size and modification time
+    /// are the same as in the snapshot
+    pub fn unchanged_files(&self, dir: &Path) -> anyhow::Result<Vec<String>> {
+        if self.0.is_empty() {
+            return Ok(vec![]);
+        }
+
+        Ok(file_attrs(dir)?
+            .into_iter()
+            .filter_map(|(name, size, modified)| {
+                if self.0.get(&name) == Some(&(size, modified)) {
+                    Some(name)
+                } else {
+                    None
+                }
+            })
+            .collect())
+    }
+}
+
+/// Retrieves name, size and modification time of all files in `dir`. Symbolic
+/// links are followed, since the target of a link (e.g., of the link to the
+/// repository DB) is uploaded under the name of the link
+fn file_attrs(dir: &Path) -> anyhow::Result<Vec<(String, u64, SystemTime)>> {
+    let err_msg = format!("Cannot retrieve file attributes in '{}'", dir.display());
+
+    let mut attrs: Vec<(String, u64, SystemTime)> = vec![];
+    for entry in fs::read_dir(dir).with_context(|| err_msg.clone())? {
+        let entry = entry.with_context(|| err_msg.clone())?;
+        let Ok(metadata) = fs::metadata(entry.path()) else {
+            continue;
+        };
+        if !metadata.is_file() {
+            continue;
+        }
+        if let Some(name) = entry.file_name().to_str() {
+            attrs.push((
+                name.to_string(),
+                metadata.len(),
+                metadata.modified().with_context(|| err_msg.clone())?,
+            ));
+        }
+    }
+
+    Ok(attrs)
 }
 
 /// Constants for currently supported URL schemes
@@ -33,21 +138,40 @@ const SCHEME_FILE: &str = "file";
 const SCHEME_RSYNC: &str = "rsync";
 const SCHEME_S3: &str = "s3";
 const SCHEME_GCS: &str = "gs";
+const SCHEME_RCLONE: &str = "rclone";
 
 /// Constants for optional dependencies
 const PKG_NAME_RSYNC: &str = "rsync";
 const PKG_NAME_SSH: &str = "openssh";
 const PKG_NAME_S3: &str = "s3cmd";
 const PKG_NAME_GCS: &str = "google-cloud-cli";
+const PKG_NAME_RCLONE: &str = "rclone";
+
+/// Delay before the first retry of a failed download or upload. The delay is
+/// doubled with each further retry, but does not exceed RETRY_MAX_DELAY_SECS
+const RETRY_DELAY_SECS: u64 = 2;
+const RETRY_MAX_DELAY_SECS: u64 = 60;
 
 /// Takes an URL and creates - based on its scheme - an instance of a
-/// corresponding type that implements the Server trait
-pub fn new(url: &Url) -> anyhow::Result<Box<dyn Server>> {
+/// corresponding type that implements the Server trait. Failed downloads and
+/// uploads are retried up to `retries` times. If `concurrency` is `Some(...)`,
+/// it limits the number of parallel file transfers of transfer tools that
+/// support that. For the other servers, a warning is printed
+pub fn new(url: &Url, retries: u32, concurrency: Option<u32>) -> anyhow::Result<Box<dyn Server>> {
+    // Local copies, rsync and s3cmd transfer files sequentially anyway
+    if concurrency.is_some() && [SCHEME_FILE, SCHEME_RSYNC, SCHEME_S3].contains(&url.scheme()) {
+        warning!(
+            "TransferConcurrency has no effect for server '{}', since its files are transferred sequentially",
+            url
+        );
+    }
+
     let server: Box<dyn Server> = match url.scheme() {
         SCHEME_FILE => Box::new(File::new()),
-        SCHEME_RSYNC => Box::new(Rsync::new(url.clone())),
-        SCHEME_S3 => Box::new(S3::new(url.clone())),
-        SCHEME_GCS => Box::new(Gcs::new(url.clone())),
+        SCHEME_RSYNC => Box::new(Rsync::new(url.clone(), retries)),
+        SCHEME_S3 => Box::new(S3::new(url.clone(), retries)),
+        SCHEME_GCS => Box::new(Gcs::new(url.clone(), retries, concurrency)),
+        SCHEME_RCLONE => Box::new(Rclone::new(url.clone(), retries, concurrency)),
         _ => {
             return Err(anyhow!("Server URL '{}' has unsupported scheme", &url));
         }
@@ -56,12 +180,122 @@ pub fn new(url: &Url) -> anyhow::Result<Box<dyn Server>> {
     Ok(server)
 }
 
+/// Takes a list of URLs and creates an instance of a type that implements the
+/// Server trait. The first URL is the primary server. If further URLs are
+/// given, these are used as fallbacks for downloads. Failed downloads and uploads
+/// are retried up to `retries` times per server. `concurrency` limits the number
+/// of parallel file transfers (see `new()`)
+pub fn new_with_fallbacks(
+    urls: &[Url],
+    retries: u32,
+    concurrency: Option<u32>,
+) -> anyhow::Result<Box<dyn Server>> {
+    let Some((primary_url, fallback_urls)) = urls.split_first() else {
+        return Err(anyhow!("No server URL given"));
+    };
+
+    let primary = new(primary_url, retries, concurrency)?;
+    if fallback_urls.is_empty() {
+        return Ok(primary);
+    }
+
+    let mut servers: Vec<(String, Box<dyn Server>)> = vec![(primary_url.to_string(), primary)];
+    for url in fallback_urls {
+        servers.push((url.to_string(), new(url, retries, concurrency)?));
+    }
+    if let Some((url, _)) = servers.iter().find(|(_, server)| !server.is_remote()) {
+        return Err(anyhow!(
+            "Fallback servers are only supported for remote repositories, but '{}' is local",
+            url
+        ));
+    }
+
+    Ok(Box::new(Fallback {
+        servers,
+        served_by: Cell::new(0),
+    }))
+}
+
+/// Parses `url`. For rclone, URLs of the form rclone://<REMOTE>:<PATH> are
+/// accepted as well, though they are no valid URLs (<PATH> would be taken as
+/// port). They are converted into rclone://<REMOTE>:/<PATH> before parsing,
+/// which corresponds to the same rclone path
+pub fn parse_url(url: &str) -> Result<Url, url::ParseError> {
+    if let Some((remote, path)) = url
+        .strip_prefix(concatcp!(SCHEME_RCLONE, "://"))
+        .and_then(|rest| rest.split_once(':'))
+    {
+        if !remote.contains('/') && !path.starts_with('/') {
+            return Url::parse(&format!("{}://{}:/{}", SCHEME_RCLONE, remote, path));
+        }
+    }
+    Url::parse(url)
+}
+
+/// Runs the transfer command `cmd`. If it fails, it is retried up to `retries`
+/// times. The delay between two attempts increases exponentially
+fn run_transfer(cmd: &duct::Expression, retries: u32) -> anyhow::Result<()> {
+    let mut attempt: u32 = 0;
+    loop {
+        let output = cmd.stdout_null().stderr_capture().unchecked().run()?;
+        if output.status.success() {
+            return Ok(());
+        }
+
+        let err = from_utf8(&output.stderr).unwrap().to_string();
+        if attempt >= retries {
+            return Err(anyhow!(err));
+        }
+
+        attempt += 1;
+        let delay = (RETRY_DELAY_SECS << (attempt - 1).min(8)).min(RETRY_MAX_DELAY_SECS);
+        warning!(
+            "Transfer failed: {}Retrying in {} seconds (retry {} of {}) ...",
+            err,
+            delay,
+            attempt,
+            retries
+        );
+        thread::sleep(Duration::from_secs(delay));
+    }
+}
+
+/// Runs the dry-run command `cmd` of a transfer tool that is contained in one of
+/// the packages `pkg_names`, and returns its output (stdout and stderr)
+fn run_dry_run(cmd: &duct::Expression, pkg_names: &[&str]) -> anyhow::Result<String> {
+    let err_msg = "Cannot determine files that would be deleted remotely";
+
+    for pkg_name in pkg_names {
+        if !is_pkg_installed(pkg_name).with_context(|| err_msg)? {
+            return Err(anyhow!(
+                "Uploading a repository requires package {} being installed",
+                pkg_name
+            ))
+            .context(err_msg);
+        }
+    }
+
+    let output = cmd
+        .stderr_to_stdout()
+        .stdout_capture()
+        .unchecked()
+        .run()
+        .with_context(|| err_msg)?;
+    let text = String::from_utf8_lossy(&output.stdout).to_string();
+    if !output.status.success() {
+        return Err(anyhow!(text).context(err_msg));
+    }
+
+    Ok(text)
+}
+
 /// Generic code for downloading a repository from a remote location. $cmd must
 /// be of type duct::Expression. It can be created with the macro duct::cmd!() or
 /// the function duct::cmd(), for example. $pkg_names must be a string array of
-/// packages to are required by the tool that is used for the download.
+/// packages to are required by the tool that is used for the download. $retries
+/// is the maximum number of retries if the download fails.
 macro_rules! download_repo {
-    ($remote_dir:expr, $pkg_names:expr, $cmd:expr) => {
+    ($remote_dir:expr, $pkg_names:expr, $retries:expr, $cmd:expr) => {
         let err_msg = "Cannot download repository";
 
         // Check if required packages are installed
@@ -84,27 +318,19 @@ macro_rules! download_repo {
         );
 
         // Sync changes from remote directory to local cache directory
-        let output = $cmd
-            .stdout_null()
-            .stderr_capture()
-            .unchecked()
-            .run()
-            .with_context(|| err_msg)?;
-
-        return if output.status.success() {
-            Ok(())
-        } else {
-            Err(anyhow!(from_utf8(&output.stderr).unwrap().to_string()).context(err_msg))
-        };
+        return run_transfer(&$cmd, $retries).with_context(|| err_msg);
     };
 }
 
 /// Generic code for uploading a repository to a remote location. $cmd must be of
 /// type duct::Expression. It can be created with the macro duct::cmd!() or the
 /// function duct::cmd(), for example. $pkg_names must be a string array of
-/// packages to are required by the tool that is used for the upload.
+/// packages to are required by the tool that is used for the upload. $delete
+/// must be the value of the corresponding parameter of upload_repo(). It is
+/// only used for messages. $retries is the maximum number of retries if the
+/// upload fails.
 macro_rules! upload_repo {
-    ($remote_dir:expr, $pkg_names:expr, $cmd:expr) => {
+    ($remote_dir:expr, $pkg_names:expr, $delete:expr, $retries:expr, $cmd:expr) => {
         let err_msg = "Cannot upload repository";
 
         // Check if required packages are installed
@@ -121,27 +347,113 @@ macro_rules! upload_repo {
             }
         }
 
-        msg!(
-            "Uploading repository to {} ... (this may take a while)",
-            $remote_dir
-        );
+        if $delete {
+            msg!("Finalizing upload of repository to {} ...", $remote_dir);
+        } else {
+            msg!(
+                "Uploading repository to {} ... (this may take a while)",
+                $remote_dir
+            );
+        }
 
         // Sync changes from the local cache directory to the remote directory
-        let output = $cmd
-            .stdout_null()
-            .stderr_capture()
-            .unchecked()
-            .run()
-            .with_context(|| err_msg)?;
-
-        return if output.status.success() {
-            Ok(())
-        } else {
-            Err(anyhow!(from_utf8(&output.stderr).unwrap().to_string()).context(err_msg))
-        };
+        return run_transfer(&$cmd, $retries).with_context(|| err_msg);
     };
 }
 
+/// Implementation for a primary server with fallback servers. Downloads are
+/// tried from one server after the other until a download succeeds. Uploads
+/// always go to the primary server. A fallback server can be outdated. Thus,
+/// uploads are refused if the last download was served by a fallback server:
+/// Otherwise, newer files on the primary server could be deleted
+struct Fallback {
+    servers: Vec<(String, Box<dyn Server>)>,
+    /// Index of the server that served the last download
+    served_by: Cell<usize>,
+}
+impl Fallback {
+    /// Returns an error if the last download was served by a fallback server
+    fn check_served_by_primary(&self) -> anyhow::Result<()> {
+        match self.fallback_url() {
+            Some(url) => Err(anyhow!(
+                "Repository was downloaded from fallback server {}, which might be outdated. Thus, it is not uploaded to primary server {}",
+                url,
+                self.servers[0].0
+            )),
+            None => Ok(()),
+        }
+    }
+}
+impl Server for Fallback {
+    fn is_remote(&self) -> bool {
+        true
+    }
+
+    fn check_upload(&self) -> anyhow::Result<()> {
+        self.servers[0].1.check_upload()
+    }
+
+    fn download_repo(&self, local_dir: &Path) -> anyhow::Result<()> {
+        let mut result = Ok(());
+        for (i, (url, server)) in self.servers.iter().enumerate() {
+            if i > 0 {
+                msg!("Trying fallback server {} ...", url);
+            }
+            result = server.download_repo(local_dir);
+            match &result {
+                Ok(()) => {
+                    self.served_by.set(i);
+                    return result;
+                }
+                Err(err) => warning!("Download from {} failed: {:?}", url, err),
+            }
+        }
+        result
+    }
+
+    fn download_db(&self, local_dir: &Path, db_name: &str) -> anyhow::Result<()> {
+        let mut result = Ok(());
+        for (i, (url, server)) in self.servers.iter().enumerate() {
+            if i > 0 {
+                msg!("Trying fallback server {} ...", url);
+            }
+            result = server.download_db(local_dir, db_name);
+            match &result {
+                Ok(()) => {
+                    self.served_by.set(i);
+                    return result;
+                }
+                Err(err) => warning!("Download from {} failed: {:?}", url, err),
+            }
+        }
+        result
+    }
+
+    fn upload_repo(
+        &self,
+        local_dir: &Path,
+        excluded_files: &[String],
+        delete: bool,
+    ) -> anyhow::Result<()> {
+        self.check_served_by_primary()?;
+        self.servers[0]
+            .1
+            .upload_repo(local_dir, excluded_files, delete)
+    }
+
+    fn files_to_be_deleted(&self, local_dir: &Path) -> anyhow::Result<Vec<String>> {
+        self.check_served_by_primary()?;
+        self.servers[0].1.files_to_be_deleted(local_dir)
+    }
+
+    fn fallback_url(&self) -> Option<&str> {
+        match self.served_by.get() {
+            0 => None,
+            i => Some(&self.servers[i].0),
+        }
+    }
+}
+
 /// Implementation for local file system
 struct File {}
 impl File {
@@ -154,12 +466,41 @@ impl Server for File {}
 /// Implementation for rsync/SSH server
 struct Rsync {
     ssh_dir: String,
+    retries: u32,
 }
 impl Rsync {
-    pub fn new(url: Url) -> Self {
+    pub fn new(url: Url, retries: u32) -> Self {
         Rsync {
             ssh_dir: ssh_path_from_url(&url),
+            retries,
+        }
+    }
+
+    /// Assembles the arguments of rsync for uploading the content of `local_dir`
+    fn upload_args(
+        &self,
+        local_dir: &Path,
+        excluded_files: &[String],
+        delete: bool,
+    ) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec![OsString::from("-a"), OsString::from("-z")];
+        if delete {
+            // With --delay-updates, the updated files are put into place at the
+            // end of the transfer, which makes the upload as atomic as possible
+            args.extend([
+                OsString::from("--delete"),
+                OsString::from("--delay-updates"),
+            ]);
         }
+        // The excluded files are read from stdin (see `filter_file()`)
+        if !excluded_files.is_empty() {
+            args.push(OsString::from("--exclude-from=-"));
+        }
+        args.extend([
+            ensure_ends_with_slash(local_dir.as_os_str()).into_owned(),
+            OsString::from(&self.ssh_dir),
+        ]);
+        args
     }
 }
 impl Server for Rsync {
@@ -167,10 +508,17 @@ impl Server for Rsync {
         true
     }
 
+    fn check_upload(&self) -> anyhow::Result<()> {
+        // Credentials for ssh cannot be checked without connecting to the
+        // server. Thus, only the required packages are checked
+        check_pkgs_installed(&self.ssh_dir, &[PKG_NAME_RSYNC, PKG_NAME_SSH])
+    }
+
     fn download_repo(&self, local_dir: &Path) -> anyhow::Result<()> {
         download_repo!(
             self.ssh_dir,
             [PKG_NAME_RSYNC, PKG_NAME_SSH],
+            self.retries,
             cmd!(
                 "rsync",
                 "-a",
@@ -182,29 +530,100 @@ impl Server for Rsync {
         );
     }
 
-    fn upload_repo(&self, local_dir: &Path) -> anyhow::Result<()> {
-        upload_repo!(
+    fn download_db(&self, local_dir: &Path, db_name: &str) -> anyhow::Result<()> {
+        // Excluded files are not deleted locally (since --delete-excluded is not
+        // set)
+        download_repo!(
             self.ssh_dir,
             [PKG_NAME_RSYNC, PKG_NAME_SSH],
+            self.retries,
             cmd!(
                 "rsync",
                 "-a",
                 "-z",
                 "--delete",
-                ensure_ends_with_slash(local_dir.as_os_str()),
-                &self.ssh_dir,
+                format!("--include={}.db*", db_name),
+                format!("--include={}.files*", db_name),
+                "--include=*.sig",
+                "--exclude=*",
+                format!("{}/", &self.ssh_dir),
+                local_dir,
             )
         );
     }
+
+    fn upload_repo(
+        &self,
+        local_dir: &Path,
+        excluded_files: &[String],
+        delete: bool,
+    ) -> anyhow::Result<()> {
+        upload_repo!(
+            self.ssh_dir,
+            [PKG_NAME_RSYNC, PKG_NAME_SSH],
+            delete,
+            self.retries,
+            cmd("rsync", self.upload_args(local_dir, excluded_files, delete))
+                .stdin_bytes(filter_file(excluded_files, |file| format!("/{}", file)))
+        );
+    }
+
+    fn files_to_be_deleted(&self, local_dir: &Path) -> anyhow::Result<Vec<String>> {
+        let mut args = vec![
+            OsString::from("--dry-run"),
+            OsString::from("--itemize-changes"),
+        ];
+        args.extend(self.upload_args(local_dir, &[], true));
+
+        // rsync reports files to be deleted as "*deleting <FILE>"
+        Ok(
+            run_dry_run(&cmd("rsync", args), &[PKG_NAME_RSYNC, PKG_NAME_SSH])?
+                .lines()
+                .filter_map(|line| line.strip_prefix("*deleting"))
+                .map(|file| file.trim().to_string())
+                .collect(),
+        )
+    }
 }
 
 /// Implementation for AWS S3
 struct S3 {
     url: Url,
+    retries: u32,
 }
 impl S3 {
-    pub fn new(url: Url) -> Self {
-        S3 { url }
+    pub fn new(url: Url, retries: u32) -> Self {
+        S3 { url, retries }
+    }
+
+    /// Assembles the arguments of s3cmd for uploading the content of `local_dir`
+    fn upload_args(
+        &self,
+        local_dir: &Path,
+        excluded_files: &[String],
+        delete: bool,
+    ) -> Vec<OsString> {
+        let mut args: Vec<OsString> = ["sync", "--follow-symlinks", "--acl-public"]
+            .iter()
+            .map(OsString::from)
+            .collect();
+        if delete {
+            args.push(OsString::from("--delete-removed"));
+        }
+        // Since excluded files exist locally, they are not deleted remotely. The
+        // excluded files are read from stdin (see `filter_file()`). s3cmd does not
+        // support "-" as file name for stdin
+        if !excluded_files.is_empty() {
+            args.extend([
+                OsString::from("--exclude-from"),
+                OsString::from("/dev/stdin"),
+            ]);
+        }
+        args.extend([
+            ensure_ends_with_slash(local_dir.as_os_str()).into_owned(),
+            ensure_ends_with_slash(OsStr::new(&self.url.as_str())).into_owned(),
+        ]);
+        args
     }
 }
 impl Server for S3 {
@@ -212,10 +631,33 @@ impl Server for S3 {
         true
     }
 
+    fn check_upload(&self) -> anyhow::Result<()> {
+        check_pkgs_installed(self.url.as_str(), &[PKG_NAME_S3])?;
+
+        // s3cmd either takes the credentials from its configuration file or from
+        // the corresponding environment variables
+        let cfg_file = match env::var_os("S3CMD_CONFIG") {
+            Some(file) => Some(PathBuf::from(file)),
+            None => dirs::home_dir().map(|dir| dir.join(".s3cfg")),
+        };
+        if cfg_file.is_some_and(|file| file.is_file())
+            || (env::var_os("AWS_ACCESS_KEY_ID").is_some()
+                && env::var_os("AWS_SECRET_ACCESS_KEY").is_some())
+        {
+            return Ok(());
+        }
+
+        Err(credentials_missing(
+            self.url.as_str(),
+            "configure s3cmd via 's3cmd --configure' or set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY",
+        ))
+    }
+
     fn download_repo(&self, local_dir: &Path) -> anyhow::Result<()> {
         download_repo!(
             self.url,
             [PKG_NAME_S3],
+            self.retries,
             cmd!(
                 "s3cmd",
                 "sync",
@@ -226,30 +668,169 @@ impl Server for S3 {
         );
     }
 
-    fn upload_repo(&self, local_dir: &Path) -> anyhow::Result<()> {
-        upload_repo!(
+    fn download_db(&self, local_dir: &Path, db_name: &str) -> anyhow::Result<()> {
+        // With s3cmd, --include patterns take precedence over --exclude patterns
+        download_repo!(
             self.url,
             [PKG_NAME_S3],
+            self.retries,
             cmd!(
                 "s3cmd",
                 "sync",
-                "--follow-symlinks",
-                "--delete-removed",
-                "--acl-public",
-                ensure_ends_with_slash(local_dir.as_os_str()),
+                "--exclude=*",
+                format!("--include={}.db*", db_name),
+                format!("--include={}.files*", db_name),
+                "--include=*.sig",
                 ensure_ends_with_slash(OsStr::new(&self.url.as_str())),
+                ensure_ends_with_slash(local_dir.as_os_str()),
             )
         );
     }
+
+    fn upload_repo(
+        &self,
+        local_dir: &Path,
+        excluded_files: &[String],
+        delete: bool,
+    ) -> anyhow::Result<()> {
+        upload_repo!(
+            self.url,
+            [PKG_NAME_S3],
+            delete,
+            self.retries,
+            cmd("s3cmd", self.upload_args(local_dir, excluded_files, delete))
+                .stdin_bytes(filter_file(excluded_files, str::to_string))
+        );
+    }
+
+    fn files_to_be_deleted(&self, local_dir: &Path) -> anyhow::Result<Vec<String>> {
+        let mut args = vec![OsString::from("--dry-run")];
+        args.extend(self.upload_args(local_dir, &[], true));
+
+        // s3cmd reports files to be deleted as "delete: '<URL>'"
+        Ok(run_dry_run(&cmd("s3cmd", args), &[PKG_NAME_S3])?
+            .lines()
+            .filter_map(|line| line.strip_prefix("delete: "))
+            .map(|url| file_name(url.trim().trim_matches('\'')))
+            .collect())
+    }
 }
 
 /// Implementation for Google Cloud Storage
 struct Gcs {
     url: Url,
+    retries: u32,
+    concurrency: Option<u32>,
 }
 impl Gcs {
-    pub fn new(url: Url) -> Self {
-        Gcs { url }
+    pub fn new(url: Url, retries: u32, concurrency: Option<u32>) -> Self {
+        Gcs {
+            url,
+            retries,
+            concurrency,
+        }
+    }
+
+    /// Assembles the top-level arguments of gsutil that control the parallelism
+    /// of transfers. Without a concurrency limit, gsutil uses its defaults for
+    /// parallel transfers (-m). With a limit of 1, files are transferred
+    /// sequentially
+    fn parallelism_args(&self) -> Vec<OsString> {
+        match self.concurrency {
+            None => vec![OsString::from("-m")],
+            Some(1) => vec![],
+            Some(concurrency) => vec![
+                OsString::from("-m"),
+                OsString::from("-o"),
+                OsString::from(format!("GSUtil:parallel_process_count={}", concurrency)),
+                OsString::from("-o"),
+                OsString::from("GSUtil:parallel_thread_count=1"),
+            ],
+        }
+    }
+
+    /// Copies the files in `local_dir` except the files with names contained in
+    /// `excluded_files`. gsutil only supports excluding files via one regular
+    /// expression, which would become too long for large repositories. Thus,
+    /// the files to be copied are passed to "gsutil cp" via stdin instead.
+    /// `delete` is only used for messages
+    fn copy_files(
+        &self,
+        local_dir: &Path,
+        excluded_files: &[String],
+        delete: bool,
+    ) -> anyhow::Result<()> {
+        let mut files: Vec<PathBuf> = vec![];
+        for entry in fs::read_dir(local_dir)
+            .with_context(|| format!("Cannot read directory '{}'", local_dir.display()))?
+            .flatten()
+        {
+            if entry.path().is_file()
+                && !excluded_files
+                    .iter()
+                    .any(|file| OsStr::new(file) == entry.file_name())
+            {
+                files.push(entry.path());
+            }
+        }
+        if files.is_empty() {
+            return Ok(());
+        }
+        files.sort_unstable();
+
+        let mut args = self.parallelism_args();
+        args.extend([
+            OsString::from("cp"),
+            OsString::from("-I"),
+            ensure_ends_with_slash(OsStr::new(&self.url.as_str())).into_owned(),
+        ]);
+        let mut stdin: Vec<u8> = vec![];
+        for file in files {
+            stdin.extend(file.as_os_str().as_bytes());
+            stdin.push(b'\n');
+        }
+
+        upload_repo!(
+            self.url,
+            [PKG_NAME_GCS],
+            delete,
+            self.retries,
+            cmd("gsutil", args).stdin_bytes(stdin)
+        );
+    }
+
+    /// Deletes the files remotely that do not exist in `local_dir`. Like for
+    /// copying files, their URLs are passed to "gsutil rm" via stdin
+    fn delete_obsolete_files(&self, local_dir: &Path) -> anyhow::Result<()> {
+        let files = self.files_to_be_deleted(local_dir)?;
+        if files.is_empty() {
+            return Ok(());
+        }
+
+        let mut args = self.parallelism_args();
+        args.extend([OsString::from("rm"), OsString::from("-I")]);
+        run_transfer(
+            &cmd("gsutil", args).stdin_bytes(
+                files
+                    .iter()
+                    .map(|file| format!("{}/{}\n", self.url.as_str().trim_end_matches('/'), file))
+                    .collect::<String>(),
+            ),
+            self.retries,
+        )
+        .with_context(|| format!("Cannot delete obsolete files from {}", self.url))
+    }
+
+    /// Assembles the arguments of gsutil for a dry run of an upload of the
+    /// content of `local_dir`, with files being deleted remotely
+    fn dry_run_args(&self, local_dir: &Path) -> Vec<OsString> {
+        let mut args = self.parallelism_args();
+        args.extend(["rsync", "-r", "-u", "-d", "-n"].iter().map(OsString::from));
+        args.extend([
+            local_dir.as_os_str().to_os_string(),
+            OsString::from(self.url.as_str()),
+        ]);
+        args
     }
 }
 impl Server for Gcs {
@@ -257,39 +838,291 @@ impl Server for Gcs {
         true
     }
 
+    fn check_upload(&self) -> anyhow::Result<()> {
+        check_pkgs_installed(self.url.as_str(), &[PKG_NAME_GCS])?;
+
+        // gsutil either takes the credentials from a boto configuration file,
+        // from a service account key file or from the active gcloud account
+        if env::var_os("GOOGLE_APPLICATION_CREDENTIALS").is_some()
+            || env::var_os("BOTO_CONFIG").is_some()
+            || dirs::home_dir().is_some_and(|dir| dir.join(".boto").is_file())
+        {
+            return Ok(());
+        }
+        // gsutil can be installed without gcloud (e.g., from PyPI). In that case,
+        // the credentials cannot be checked, and the upload is tried anyway
+        let output = match cmd!(
+            "gcloud",
+            "auth",
+            "list",
+            "--filter=status:ACTIVE",
+            "--format=value(account)"
+        )
+        .stdout_capture()
+        .stderr_null()
+        .unchecked()
+        .run()
+        {
+            Ok(output) => output,
+            Err(err) => {
+                warning!(
+                    "Cannot check credentials for {} since gcloud cannot be run: {}",
+                    self.url,
+                    err
+                );
+                return Ok(());
+            }
+        };
+        if output.status.success() && !output.stdout.trim_ascii().is_empty() {
+            return Ok(());
+        }
+
+        Err(credentials_missing(
+            self.url.as_str(),
+            "log in via 'gcloud auth login' or set GOOGLE_APPLICATION_CREDENTIALS",
+        ))
+    }
+
+    fn download_repo(&self, local_dir: &Path) -> anyhow::Result<()> {
+        let mut args = self.parallelism_args();
+        args.extend(["rsync", "-r", "-d", "-u"].iter().map(OsString::from));
+        args.extend([
+            OsString::from(self.url.as_str()),
+            local_dir.as_os_str().to_os_string(),
+        ]);
+
+        download_repo!(self.url, [PKG_NAME_GCS], self.retries, cmd("gsutil", args));
+    }
+
+    fn download_db(&self, local_dir: &Path, db_name: &str) -> anyhow::Result<()> {
+        // gsutil only supports excluding files. Thus, all files except the DB
+        // and signature files are excluded via a negative lookahead
+        let mut args = self.parallelism_args();
+        args.extend(["rsync", "-r", "-u", "-x"].iter().map(OsString::from));
+        args.extend([
+            OsString::from(format!(
+                "^(?!{0}\\.db|{0}\\.files|.*\\.sig$)",
+                escape(db_name)
+            )),
+            OsString::from(self.url.as_str()),
+            local_dir.as_os_str().to_os_string(),
+        ]);
+
+        download_repo!(self.url, [PKG_NAME_GCS], self.retries, cmd("gsutil", args));
+    }
+
+    fn upload_repo(
+        &self,
+        local_dir: &Path,
+        excluded_files: &[String],
+        delete: bool,
+    ) -> anyhow::Result<()> {
+        self.copy_files(local_dir, excluded_files, delete)?;
+        if delete {
+            self.delete_obsolete_files(local_dir)?;
+        }
+        Ok(())
+    }
+
+    fn files_to_be_deleted(&self, local_dir: &Path) -> anyhow::Result<Vec<String>> {
+        // gsutil reports files to be deleted as "Would remove <URL>"
+        Ok(run_dry_run(
+            &cmd("gsutil", self.dry_run_args(local_dir)),
+            &[PKG_NAME_GCS],
+        )?
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("Would remove "))
+        .map(file_name)
+        .collect())
+    }
+}
+
+/// Implementation for storage locations that are accessed via rclone. This
+/// covers all storage providers that are supported by rclone, provided a
+/// corresponding remote is configured in rclone
+struct Rclone {
+    rclone_dir: String,
+    retries: u32,
+    concurrency: Option<u32>,
+}
+impl Rclone {
+    pub fn new(url: Url, retries: u32, concurrency: Option<u32>) -> Self {
+        Rclone {
+            rclone_dir: rclone_path_from_url(&url),
+            retries,
+            concurrency,
+        }
+    }
+
+    /// Assembles the arguments of rclone that limit the number of parallel file
+    /// transfers, if a limit is set
+    fn parallelism_args(&self) -> Vec<OsString> {
+        match self.concurrency {
+            None => vec![],
+            Some(concurrency) => vec![
+                OsString::from("--transfers"),
+                OsString::from(concurrency.to_string()),
+            ],
+        }
+    }
+
+    /// Assembles the arguments of rclone for uploading the content of
+    /// `local_dir`
+    fn upload_args(
+        &self,
+        local_dir: &Path,
+        excluded_files: &[String],
+        delete: bool,
+    ) -> Vec<OsString> {
+        // "rclone copy" does not delete files remotely, in contrast to "rclone
+        // sync"
+        let mut args: Vec<OsString> = vec![
+            OsString::from(if delete { "sync" } else { "copy" }),
+            OsString::from("--copy-links"),
+        ];
+        args.extend(self.parallelism_args());
+        // Since excluded files exist locally, they are not deleted remotely. The
+        // excluded files are read from stdin (see `filter_file()`)
+        if !excluded_files.is_empty() {
+            args.extend([OsString::from("--exclude-from"), OsString::from("-")]);
+        }
+        args.extend([
+            ensure_ends_with_slash(local_dir.as_os_str()).into_owned(),
+            ensure_ends_with_slash(OsStr::new(&self.rclone_dir)).into_owned(),
+        ]);
+        args
+    }
+}
+impl Server for Rclone {
+    fn is_remote(&self) -> bool {
+        true
+    }
+
+    fn check_upload(&self) -> anyhow::Result<()> {
+        check_pkgs_installed(&self.rclone_dir, &[PKG_NAME_RCLONE])?;
+
+        // The credentials are part of the configuration of the rclone remote.
+        // Thus, it is checked whether the remote exists
+        let remote = format!("{}:", self.rclone_dir.split_once(':').unwrap_or_default().0);
+        let output = cmd!("rclone", "listremotes")
+            .stdout_capture()
+            .stderr_null()
+            .unchecked()
+            .run()
+            .with_context(|| format!("Cannot check credentials for {}", self.rclone_dir))?;
+        if String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .any(|line| line.trim() == remote)
+        {
+            return Ok(());
+        }
+
+        Err(credentials_missing(
+            &self.rclone_dir,
+            &format!("configure remote {} via 'rclone config'", remote),
+        ))
+    }
+
     fn download_repo(&self, local_dir: &Path) -> anyhow::Result<()> {
+        let mut args = vec![OsString::from("sync")];
+        args.extend(self.parallelism_args());
+        args.extend([
+            ensure_ends_with_slash(OsStr::new(&self.rclone_dir)).into_owned(),
+            ensure_ends_with_slash(local_dir.as_os_str()).into_owned(),
+        ]);
+
         download_repo!(
-            self.url,
-            [PKG_NAME_GCS],
-            cmd!(
-                "gsutil",
-                "-m",
-                "rsync",
-                "-r",
-                "-d",
-                "-u",
-                &self.url.as_str(),
-                local_dir,
-            )
+            self.rclone_dir,
+            [PKG_NAME_RCLONE],
+            self.retries,
+            cmd("rclone", args)
         );
     }
 
-    fn upload_repo(&self, local_dir: &Path) -> anyhow::Result<()> {
+    fn download_db(&self, local_dir: &Path, db_name: &str) -> anyhow::Result<()> {
+        // With filters, "rclone sync" only deletes files locally that match
+        // the filters
+        let mut args = vec![OsString::from("sync")];
+        args.extend(self.parallelism_args());
+        for pattern in [
+            format!("/{}.db*", escape_rclone_glob(db_name)),
+            format!("/{}.files*", escape_rclone_glob(db_name)),
+            "*.sig".to_string(),
+        ] {
+            args.extend([OsString::from("--include"), OsString::from(pattern)]);
+        }
+        args.extend([
+            ensure_ends_with_slash(OsStr::new(&self.rclone_dir)).into_owned(),
+            ensure_ends_with_slash(local_dir.as_os_str()).into_owned(),
+        ]);
+
+        download_repo!(
+            self.rclone_dir,
+            [PKG_NAME_RCLONE],
+            self.retries,
+            cmd("rclone", args)
+        );
+    }
+
+    fn upload_repo(
+        &self,
+        local_dir: &Path,
+        excluded_files: &[String],
+        delete: bool,
+    ) -> anyhow::Result<()> {
         upload_repo!(
-            self.url,
-            [PKG_NAME_GCS],
-            cmd!(
-                "gsutil",
-                "-m",
-                "rsync",
-                "-r",
-                "-d",
-                "-u",
-                local_dir,
-                &self.url.as_str(),
+            self.rclone_dir,
+            [PKG_NAME_RCLONE],
+            delete,
+            self.retries,
+            cmd(
+                "rclone",
+                self.upload_args(local_dir, excluded_files, delete)
             )
+            .stdin_bytes(filter_file(excluded_files, |file| format!(
+                "/{}",
+                escape_rclone_glob(file)
+            )))
         );
     }
+
+    fn files_to_be_deleted(&self, local_dir: &Path) -> anyhow::Result<Vec<String>> {
+        let mut args = self.upload_args(local_dir, &[], true);
+        args.insert(1, OsString::from("--dry-run"));
+
+        // rclone reports files to be deleted as "NOTICE: <FILE>: Skipped delete
+        // as --dry-run is set (...)"
+        Ok(run_dry_run(&cmd("rclone", args), &[PKG_NAME_RCLONE])?
+            .lines()
+            .filter_map(|line| {
+                line.split_once(": Skipped delete as --dry-run is set")
+                    .map(|(prefix, _)| prefix)
+            })
+            .map(|prefix| {
+                file_name(
+                    prefix
+                        .rsplit_once("NOTICE: ")
+                        .map_or(prefix, |(_, file)| file),
+                )
+            })
+            .collect())
+    }
+}
+
+/// Assembles the content of a filter file for a transfer tool with one pattern
+/// per file of `files`, which is created by `pattern`. Filter files are passed
+/// to the transfer tools via stdin instead of passing the patterns as
+/// arguments, since the number and the length of arguments is limited. For
+/// large repositories, these limits could be exceeded otherwise
+fn filter_file<F>(files: &[String], pattern: F) -> Vec<u8>
+where
+    F: Fn(&str) -> String,
+{
+    files
+        .iter()
+        .map(|file| format!("{}\n", pattern(file)))
+        .collect::<String>()
+        .into_bytes()
 }
 
 /// Appends a slash at an OS string if it does not end already with one
@@ -324,3 +1157,59 @@ fn ssh_path_from_url(url: &Url) -> String {
         url.path()
     )
 }
+
+/// Checks whether the packages `pkg_names` that are required for uploading a
+/// repository to `remote_dir` are installed
+fn check_pkgs_installed(remote_dir: &str, pkg_names: &[&str]) -> anyhow::Result<()> {
+    let err_msg = format!("Cannot upload to {}", remote_dir);
+
+    for pkg_name in pkg_names {
+        if !is_pkg_installed(pkg_name).with_context(|| err_msg.clone())? {
+            return Err(anyhow!(
+                "Uploading a repository to {} requires package {} being installed",
+                remote_dir,
+                pkg_name
+            ))
+            .context(err_msg);
+        }
+    }
+
+    Ok(())
+}
+
+/// Assembles the error for missing credentials for uploads to `remote_dir`.
+/// `hint` explains how the credentials can be configured
+fn credentials_missing(remote_dir: &str, hint: &str) -> anyhow::Error {
+    anyhow!("Credentials missing ({})", hint).context(format!(
+        "Cannot upload to {}: credentials missing",
+        remote_dir
+    ))
+}
+
+/// Converts an URL of the form rclone://<REMOTE>/<PATH> (or
+/// rclone://<REMOTE>:/<PATH>) into the path <REMOTE>:<PATH> that can be used
+/// for rclone
+fn rclone_path_from_url(url: &Url) -> String {
+    format!(
+        "{}:{}",
+        url.host_str().unwrap_or_default(),
+        url.path().trim_start_matches('/')
+    )
+}
+
+/// Escapes characters that have a special meaning in rclone filter patterns
+fn escape_rclone_glob(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '\\' | '*' | '?' | '[' | ']' | '{' | '}') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Returns the file name of a path or URL, i.e. the part after the last slash
+fn file_name(path: &str) -> String {
+    path.rsplit('/').next().unwrap_or(path).to_string()
+}
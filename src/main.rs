@@ -2,11 +2,16 @@
 //
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use crate::internal::{cfg, repo::Repo};
+use crate::internal::{
+    cfg, common,
+    pkgbuild::{BuildOpts, PkgBuild, PKGBUILD_FILE_NAME},
+    repo::{ForceUpdate, Repo},
+};
 use anyhow::{anyhow, Context};
 use arch_msgs::*;
-use clap::Parser;
-use dialoguer::Confirm;
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::{CommandFactory, Parser};
+use std::{io, path::Path};
 
 mod cli;
 mod internal;
@@ -14,37 +19,88 @@ mod internal;
 /// Executes repman (sub) command by calling the corresponding function from their
 /// internal API
 fn execute(args: &cli::Args) -> anyhow::Result<()> {
+    // Override architecture of the system if one was set explicitly
+    if let Some(arch) = &args.arch {
+        common::set_arch(arch)?;
+    }
+
+    // Answer all confirmation prompts with yes if requested
+    if args.yes {
+        common::set_assume_yes();
+    }
+
+    // Write a summary of the changed packages for machine processing if requested.
+    // The file is only created for commands that change packages
+    if let Some(change_summary) = &args.change_summary {
+        if matches!(
+            &args.command,
+            cli::Commands::Add { .. } | cli::Commands::Rm { .. } | cli::Commands::Update { .. }
+        ) {
+            common::set_change_summary_file(change_summary.clone())?;
+        } else {
+            warning!("'--change-summary' is ignored since the command does not change packages");
+        }
+    }
+
+    // Limit the number of parallel AUR requests and clones if requested
+    if let Some(max_parallel) = args.max_parallel {
+        if max_parallel == 0 {
+            return Err(anyhow!("'--max-parallel' must be greater than 0"));
+        }
+        cfg::set_max_parallel(max_parallel);
+    }
+
     match &args.command {
         // Build and add packages
         cli::Commands::Add {
             repo_name,
             aur_pkg_names,
             pkgbuild_dirs,
+            git_urls,
+            pkg_files,
+            edit,
+            as_deps,
+            only_new,
+            format,
             clean_chroot,
-            no_chroot,
-            ignore_arch,
+            require_checksums,
+            install_after,
             sign,
+            build_args,
         } => {
-            if *no_chroot && *clean_chroot {
+            if build_args.no_chroot && *clean_chroot {
                 return Err(anyhow!(
                     "If '-n/--nochroot' is set, setting '-c/--clean' does not make sense"
                 ));
             }
+            let opts = build_args.build_opts()?;
 
             Repo::new(repo_name)?.add(
                 aur_pkg_names,
                 pkgbuild_dirs,
-                *no_chroot,
-                *ignore_arch,
+                git_urls,
+                pkg_files,
+                &BuildOpts {
+                    require_checksums: *require_checksums,
+                    install_after: *install_after,
+                    only_new: *only_new,
+                    ..opts
+                },
                 *clean_chroot,
                 *sign,
+                *format,
+                *edit,
+                *as_deps,
             )
         }
 
         // Cleanup a repository
-        cli::Commands::CleanUp { repo_name } => Repo::new(repo_name)
+        cli::Commands::CleanUp {
+            repo_name,
+            prune_untracked,
+        } => Repo::new(repo_name)
             .with_context(|| format!("Cannot clear data of repository {}", repo_name))?
-            .clean_up(),
+            .clean_up(*prune_untracked),
 
         // Delete local data of a repository - i.e., chroot directory and/or
         // local repository directory in case of a remote repository
@@ -77,19 +133,118 @@ fn execute(args: &cli::Args) -> anyhow::Result<()> {
             Ok(())
         }
 
+        // Generate shell completions
+        cli::Commands::Completions { shell } => {
+            clap_complete::generate(
+                *shell,
+                &mut cli::Args::command(),
+                env!("CARGO_PKG_NAME"),
+                &mut io::stdout(),
+            );
+            Ok(())
+        }
+
+        // Print dependency graph of a repository
+        cli::Commands::Deps {
+            repo_name,
+            dot,
+            format,
+        } => {
+            let err_msg = format!("Cannot show dependencies of repository {}", repo_name);
+            Repo::new(repo_name)
+                .with_context(|| err_msg.clone())?
+                .dep_graph(*dot, *format)
+                .with_context(|| err_msg)
+        }
+
+        // Print paths of configuration files and directories
+        cli::Commands::ConfigPath => {
+            println!("Global configuration:    {}", cfg::cfg_file().display());
+            println!(
+                "Configuration directory: {}",
+                common::config_dir()?.display()
+            );
+            println!("Repositories:            {}", cfg::repos_file()?.display());
+            println!(
+                "Cache directory:         {}",
+                common::cache_dir()?.display()
+            );
+            println!(
+                "Locks directory:         {}",
+                common::locks_dir()?.display()
+            );
+            Ok(())
+        }
+
+        // Print pacman.conf for chroot builds
+        cli::Commands::DumpPacmanConf { repo_name } => {
+            let err_msg = format!(
+                "Cannot print pacman.conf for chroot of repository {}",
+                repo_name
+            );
+            Repo::new(repo_name)
+                .with_context(|| err_msg.clone())?
+                .dump_pacman_conf()
+                .with_context(|| err_msg)
+        }
+
+        // Remove leftovers of builds
+        cli::Commands::Gc { repo_name } => {
+            let err_msg = format!("Cannot remove build leftovers of repository {}", repo_name);
+            Repo::new(repo_name)
+                .with_context(|| err_msg.clone())?
+                .gc()
+                .with_context(|| err_msg)
+        }
+
+        // Show history of a repository
+        cli::Commands::History { repo_name } => {
+            let err_msg = format!("Cannot show history of repository {}", repo_name);
+            Repo::new(repo_name)
+                .with_context(|| err_msg.clone())?
+                .history()
+                .with_context(|| err_msg)
+        }
+
         // List packages of one repository
-        cli::Commands::Ls { repo_name } => {
+        cli::Commands::Ls {
+            repo_name,
+            format,
+            depends_on,
+        } => {
             let err_msg = format!("Cannot list content of repository {}", repo_name);
             Repo::new(repo_name)
                 .with_context(|| err_msg.clone())?
-                .list()
+                .list(*format, depends_on.as_deref())
                 .with_context(|| err_msg)
         }
 
         // List all configured repositories
-        cli::Commands::LsRepos => {
+        cli::Commands::LsRepos { detailed } => {
             for repo_name in cfg::repos()?.keys() {
-                println!("{}", repo_name)
+                println!("{}", repo_name);
+                if !*detailed {
+                    continue;
+                }
+
+                // Errors of one repository must not prevent the other repositories
+                // from being listed
+                match Repo::new(repo_name).and_then(|repo| repo.details()) {
+                    Err(err) => error!("{:?}", err),
+                    Ok(details) => {
+                        let yes_no = |b: bool| if b { "yes" } else { "no" };
+                        println!("  Server:      {}", details.url);
+                        println!("  Remote:      {}", yes_no(details.is_remote));
+                        println!("  DB (local):  {}", yes_no(details.db_exists_locally));
+                        if let Some(db_exists_remotely) = details.db_exists_remotely {
+                            println!("  DB (remote): {}", yes_no(db_exists_remotely));
+                        }
+                        match details.pkg_count {
+                            Some(pkg_count) => println!("  Packages:    {}", pkg_count),
+                            None => println!("  Packages:    -"),
+                        }
+                    }
+                }
             }
             Ok(())
         }
@@ -99,14 +254,14 @@ fn execute(args: &cli::Args) -> anyhow::Result<()> {
             let err_msg = format!("Cannot make chroot container for repository {}", repo_name);
             let repo = Repo::new(repo_name).with_context(|| err_msg.clone())?;
             if repo.chroot_exists() {
-                if Confirm::new()
-                    .with_prompt(format!(
+                if common::confirm(
+                    format!(
                         "A chroot for repository {} exists already. It is now being deleted. OK?",
                         repo_name
-                    ))
-                    .default(true)
-                    .interact()
-                    .with_context(|| err_msg.clone())?
+                    ),
+                    true,
+                )
+                .with_context(|| err_msg.clone())?
                 {
                     repo.remove_chroot_dir().with_context(|| err_msg.clone())?
                 } else {
@@ -117,28 +272,156 @@ fn execute(args: &cli::Args) -> anyhow::Result<()> {
             Ok(())
         }
 
+        // Copy a repository to another location
+        cli::Commands::Mirror { repo_name, url } => {
+            let err_msg = format!("Cannot mirror repository {}", repo_name);
+            Repo::new(repo_name)
+                .with_context(|| err_msg.clone())?
+                .mirror(url)
+                .with_context(|| err_msg)
+        }
+
+        // List the package files that a PKGBUILD file produces
+        cli::Commands::Packagelist { pkgbuild_dir } => {
+            let pkgbuild = pkgbuild_from_path(pkgbuild_dir)?;
+            for pkg_file in pkgbuild.pkg_files(pkgbuild_dir)? {
+                if let Some(file_name) = pkg_file.file_name() {
+                    println!("{}", file_name.to_string_lossy());
+                }
+            }
+            Ok(())
+        }
+
+        // Remove signature files without counterpart
+        cli::Commands::PruneSigs { repo_name } => {
+            let err_msg = format!("Cannot prune signature files of repository {}", repo_name);
+            Repo::new(repo_name)
+                .with_context(|| err_msg.clone())?
+                .prune_sigs()
+                .with_context(|| err_msg)
+        }
+
+        // Remove all local data of a repository
+        cli::Commands::Purge {
+            repo_name,
+            no_confirm,
+        } => {
+            let err_msg = format!("Cannot purge local data of repository {}", repo_name);
+            let repo = Repo::new(repo_name).with_context(|| err_msg.clone())?;
+            if !*no_confirm
+                && !common::confirm(
+                    format!(
+                        "All local data of repository {} is now being deleted. OK?",
+                        repo_name
+                    ),
+                    false,
+                )
+                .with_context(|| err_msg.clone())?
+            {
+                return Ok(());
+            }
+            repo.purge().with_context(|| err_msg)?;
+            msg!("Local data of repository {} removed", repo_name);
+            Ok(())
+        }
+
+        // Upload local copy of a remote repository
+        cli::Commands::Push { repo_name } => {
+            let err_msg = format!("Cannot push repository {}", repo_name);
+            Repo::new(repo_name)
+                .with_context(|| err_msg.clone())?
+                .push()
+                .with_context(|| err_msg)
+        }
+
+        // Update PGP keys used for builds
+        cli::Commands::RefreshKeys { repo_name, chroot } => {
+            let err_msg = format!("Cannot refresh PGP keys for repository {}", repo_name);
+            Repo::new(repo_name)
+                .with_context(|| err_msg.clone())?
+                .refresh_keys(*chroot)
+                .with_context(|| err_msg)
+        }
+
         // Remove packages of a repository
         cli::Commands::Rm {
             repo_name,
             no_confirm,
+            verify_before_remove,
+            older_than,
             pkg_names,
         } => {
-            if pkg_names.is_empty() {
+            if pkg_names.is_empty() && older_than.is_none() {
                 Ok(())
             } else {
                 let err_msg = format!("Cannot remove packages from repository {}", &repo_name);
+                let older_than = older_than
+                    .as_deref()
+                    .map(parse_date)
+                    .transpose()
+                    .with_context(|| err_msg.clone())?;
                 Repo::new(repo_name)
                     .with_context(|| err_msg.clone())?
-                    .remove(pkg_names, *no_confirm)
+                    .remove(pkg_names, *no_confirm, *verify_before_remove, older_than)
                     .with_context(|| err_msg)
             }
         }
 
+        // Re-sign all packages and the DB of a repository
+        cli::Commands::Resign { repo_name, gpg_key } => {
+            let err_msg = format!("Cannot re-sign repository {}", repo_name);
+            Repo::new(repo_name)
+                .with_context(|| err_msg.clone())?
+                .resign(gpg_key.as_deref())
+                .with_context(|| err_msg)
+        }
+
+        cli::Commands::Resync {
+            repo_name,
+            no_confirm,
+        } => {
+            let err_msg = format!("Cannot re-synchronize repository {}", repo_name);
+            let repo = Repo::new(repo_name).with_context(|| err_msg.clone())?;
+            if !*no_confirm
+                && !common::confirm(
+                    format!(
+                        "The local copy of repository {} is now being deleted and downloaded again. OK?",
+                        repo_name
+                    ),
+                    false,
+                )
+                .with_context(|| err_msg.clone())?
+            {
+                return Ok(());
+            }
+            repo.resync().with_context(|| err_msg)?;
+            msg!("Repository {} re-synchronized", repo_name);
+            Ok(())
+        }
+
         // Sign packages of a repository
         cli::Commands::Sign {
             repo_name,
             all,
+            db_only,
             pkg_names,
+            ..
+        } if *db_only => {
+            if *all || !pkg_names.is_empty() {
+                return Err(anyhow!(
+                    "'--db-only' cannot be combined with package names or '--all'"
+                ));
+            }
+            Repo::new(repo_name)
+                .with_context(|| format!("Cannot sign DB of repository {}", repo_name))?
+                .sign_db_only()
+        }
+        cli::Commands::Sign {
+            repo_name,
+            all,
+            no_confirm,
+            pkg_names,
+            ..
         } => match *all {
             true if !pkg_names.is_empty() => Err(anyhow!(
                 "Either submit package names or set option '--all', but not both."
@@ -148,53 +431,202 @@ fn execute(args: &cli::Args) -> anyhow::Result<()> {
                 let err_msg = format!("Cannot sign packages of repository {}", repo_name);
                 Repo::new(repo_name)
                     .with_context(|| err_msg.clone())?
-                    .sign(if *all { None } else { Some(pkg_names) })
+                    .sign(if *all { None } else { Some(pkg_names) }, *no_confirm)
                     .with_context(|| err_msg)
             }
         },
 
+        // Print package meta data of an AUR package
+        cli::Commands::Srcinfo {
+            repo_name,
+            pkg_name,
+        } => Repo::new(repo_name)?.srcinfo(pkg_name),
+
         // Update packages
         cli::Commands::Update {
             repo_name,
+            all_repos,
+            format,
+            diff,
             clean_chroot,
-            no_chroot,
-            ignore_arch,
+            since_commit,
             force_no_version,
+            force_all,
             no_confirm,
             all,
+            pkgbuild_dirs,
             pkg_names,
+            build_args,
         } => {
-            if *no_chroot && *clean_chroot {
+            if build_args.no_chroot && *clean_chroot {
                 return Err(anyhow!(
                     "If '-n/--nochroot' is set, setting '-c/--clean' does not make sense"
                 ));
             }
+            let opts = build_args.build_opts()?;
 
             match *all {
                 true if !pkg_names.is_empty() => Err(anyhow!(
                     "Either submit package names or set the options '--all', but not both"
                 )),
-                false if pkg_names.is_empty() => {
-                    warning!("Either submit package names or the option '--all'");
+                false if pkg_names.is_empty() && pkgbuild_dirs.is_empty() => {
+                    warning!("Either submit package names, directories or the option '--all'");
+                    Ok(())
+                }
+                _ => {
+                    let opts = BuildOpts {
+                        since_commit: *since_commit,
+                        ..opts
+                    };
+                    let update = |repo_name: &str| {
+                        Repo::new(repo_name)?.update(
+                            if *all { None } else { Some(pkg_names) },
+                            pkgbuild_dirs,
+                            &opts,
+                            if *force_all {
+                                ForceUpdate::All
+                            } else if *force_no_version {
+                                ForceUpdate::NoVersion
+                            } else {
+                                ForceUpdate::None
+                            },
+                            *clean_chroot,
+                            *no_confirm,
+                            *format,
+                            *diff,
+                        )
+                    };
+
+                    if !*all_repos {
+                        return update(repo_name.as_deref().ok_or_else(|| {
+                            anyhow!("Repository name is required if '--all-repos' is not set")
+                        })?);
+                    }
+
+                    // Errors of one repository must not prevent the other
+                    // repositories from being updated. Thus, they are collected
+                    // and reported at the end
+                    let mut failed_repo_names: Vec<String> = vec![];
+                    let repo_names: Vec<String> = cfg::repos()?.into_keys().collect();
+                    for repo_name in &repo_names {
+                        msg!("Updating repository {} ...", repo_name);
+                        if let Err(err) = update(repo_name) {
+                            error!("{:?}", err);
+                            failed_repo_names.push(repo_name.clone());
+                        }
+                    }
+
+                    msg!("Summary:");
+                    for repo_name in &repo_names {
+                        println!(
+                            "  {}: {}",
+                            repo_name,
+                            if failed_repo_names.contains(repo_name) {
+                                "failed"
+                            } else {
+                                "updated"
+                            }
+                        );
+                    }
+                    if !failed_repo_names.is_empty() {
+                        return Err(anyhow!(
+                            "Update failed for repositories: {}",
+                            failed_repo_names.join(", ")
+                        ));
+                    }
                     Ok(())
                 }
-                _ => Repo::new(repo_name)?.update(
-                    if *all { None } else { Some(pkg_names) },
-                    *no_chroot,
-                    *ignore_arch,
-                    *force_no_version,
-                    *clean_chroot,
-                    *no_confirm,
-                ),
             }
         }
+        cli::Commands::ValidatePkgbuild { pkgbuild_dir } => {
+            let pkgbuild = pkgbuild_from_path(pkgbuild_dir)?;
+
+            let (errors, warnings) = pkgbuild.validate()?;
+            for warn in &warnings {
+                warning!("{}", warn);
+            }
+            for err in &errors {
+                error!("{}", err);
+            }
+            if !errors.is_empty() {
+                return Err(anyhow!(
+                    "PKGBUILD file '{}' is not valid",
+                    pkgbuild.as_ref().display()
+                ));
+            }
+
+            msg!("PKGBUILD file '{}' is valid", pkgbuild.as_ref().display());
+            Ok(())
+        }
+
+        // Rebuild a package of a repository to verify that its build is
+        // reproducible
+        cli::Commands::VerifyBuild {
+            repo_name,
+            pkgbuild_dir,
+            pkg_name,
+            build_args,
+        } => {
+            let err_msg = format!(
+                "Cannot verify build of package {} of repository {}",
+                pkg_name, repo_name
+            );
+            if build_args.no_build {
+                return Err(anyhow!(
+                    "'--nobuild' is not possible since the package must be built to verify it"
+                ));
+            }
+            Repo::new(repo_name)
+                .with_context(|| err_msg.clone())?
+                .verify_build(
+                    pkg_name,
+                    pkgbuild_dir.as_ref(),
+                    &build_args.build_opts().with_context(|| err_msg.clone())?,
+                )
+                .with_context(|| err_msg)
+        }
     }
 }
 
+/// Converts `date` in the format YYYY-MM-DD into the point in time at the
+/// beginning of that day (UTC)
+fn parse_date(date: &str) -> anyhow::Result<DateTime<Utc>> {
+    Ok(NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .with_context(|| format!("Date '{}' does not have the format YYYY-MM-DD", date))?
+        .and_hms_opt(0, 0, 0)
+        .unwrap_or_else(|| panic!("Cannot determine beginning of day {}", date))
+        .and_utc())
+}
+
+/// Creates a PKGBUILD file instance from `path`, which is either a directory
+/// that contains a PKGBUILD file or the path of a PKGBUILD file
+fn pkgbuild_from_path(path: &Path) -> anyhow::Result<PkgBuild> {
+    PkgBuild::try_from(if path.is_dir() {
+        path.join(PKGBUILD_FILE_NAME)
+    } else {
+        path.to_path_buf()
+    })
+}
+
 fn main() {
+    let args = cli::Args::parse();
+
     // Execute repman (sub) command. In case of an error: Exit with error code
-    if let Err(err) = execute(&cli::Args::parse()) {
-        error!("{:?}", err);
+    if let Err(err) = execute(&args) {
+        if args.json_errors {
+            // The context chain starts with the outermost context and ends with
+            // the root cause
+            eprintln!(
+                "{}",
+                serde_json::json!({
+                    "code": 1,
+                    "message": err.to_string(),
+                    "chain": err.chain().map(ToString::to_string).collect::<Vec<String>>(),
+                })
+            );
+        } else {
+            error!("{:?}", err);
+        }
         std::process::exit(1);
     }
 }